@@ -0,0 +1,156 @@
+use std::time::{Duration, Instant};
+use sym_engine::{System, SystemLoader, Space, Access, Matcher, Id, Value};
+
+const MIN_SAMPLES: usize = 10;
+const MAX_SAMPLES: usize = 1000;
+const SAMPLE_TIME: Duration = Duration::from_secs(2);
+
+const MATCHERS: &[(&str, Matcher)] = &[
+    ("search", Matcher::Search),
+    ("compiled", Matcher::Compiled),
+];
+
+// rules that never fire, so every run searches everything
+const JOIN_RULES: &str = "
+    rule bench:pair {
+        $ROOT.item: $x,
+        $x.value: $v,
+        $ROOT.item: $y,
+        $y.value: $w,
+        $limit is $v + 1000,
+        $w > $limit,
+    } do {
+        + $ROOT.found: [$v, $w],
+    }
+";
+
+const SCOPE_RULES: &str = "
+    rule bench:scopes {
+        $ROOT.item: $x,
+        $x.group: 3,
+        optional { $x.label: $label } else { $label: none },
+        not { $ROOT.skip: $label },
+        $x.tags: $tags,
+        $tag in $tags,
+        $tag < 0,
+    } do {
+        + $ROOT.found: $tag,
+    }
+";
+
+const MOVE_RULES: &str = "
+    rule bench:finish {
+        $ROOT.item: $x,
+        $x.state: moved,
+        $x.value: $v,
+    } do {
+        - $x.state: moved,
+        + $x.state: done,
+        + $ROOT.total: $v,
+    }
+    rule bench:move {
+        $ROOT.item: $x,
+        $x.state: new,
+    } do {
+        - $x.state: new,
+        + $x.state: moved,
+    }
+";
+
+fn main() {
+    let filters = std::env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with('-'))
+        .collect::<Vec<_>>();
+    let selected = |name: &str| {
+        filters.is_empty() || filters.iter().any(|filter| name.contains(filter.as_str()))
+    };
+
+    for &(matcher_name, matcher) in MATCHERS {
+        let name = format!("join/{}", matcher_name);
+        if selected(&name) {
+            let system = load_system(JOIN_RULES, matcher);
+            let (mut space, root) = populate(200);
+            bench(&name, || (), |()| {
+                assert!(system.run_to_first(&mut space, &[root]).unwrap().is_none());
+            });
+        }
+    }
+
+    for &(matcher_name, matcher) in MATCHERS {
+        let name = format!("scopes/{}", matcher_name);
+        if selected(&name) {
+            let system = load_system(SCOPE_RULES, matcher);
+            let (mut space, root) = populate(20_000);
+            bench(&name, || (), |()| {
+                assert!(system.run_to_first(&mut space, &[root]).unwrap().is_none());
+            });
+        }
+    }
+
+    for &(matcher_name, matcher) in MATCHERS {
+        let name = format!("saturation/{}", matcher_name);
+        if selected(&name) {
+            let system = load_system(MOVE_RULES, matcher);
+            bench(&name, || populate(100), |(mut space, root)| {
+                assert_eq!(system.run_saturation(&mut space, &[root]).unwrap(), 200);
+            });
+        }
+    }
+}
+
+fn load_system(rules: &str, matcher: Matcher) -> System {
+    let mut system = System::new("bench", &["ROOT"]).unwrap();
+    let mut loader = SystemLoader::new(vec![&mut system]);
+    loader.load_str(rules).expect("benchmark rules load");
+    system.set_matcher(matcher);
+    system
+}
+
+fn populate(items: i64) -> (Space, Id) {
+    let mut space = Space::new();
+    let root = space.create_id();
+    for index in 0..items {
+        let item = space.create_object().apply(|attrs| {
+            attrs.add("value", index);
+            attrs.add("group", index % 10);
+            attrs.add("state", "new");
+            if index % 2 == 0 {
+                attrs.add("label", index % 7);
+            }
+            let tags = vec![Value::from(index), Value::from(index % 100)];
+            attrs.add("tags", Value::Tuple(tags.into()));
+            attrs.object()
+        });
+        space.attributes_mut(root).add("item", item);
+    }
+    (space, root)
+}
+
+// runs the routine with fresh input from `setup` until the sample time is
+// used up, only timing the routine itself
+fn bench<S, T, R>(name: &str, mut setup: S, mut routine: R)
+where
+    S: FnMut() -> T,
+    R: FnMut(T),
+{
+    routine(setup());
+    let mut samples = Vec::new();
+    let started = Instant::now();
+    while samples.len() < MIN_SAMPLES
+        || (samples.len() < MAX_SAMPLES && started.elapsed() < SAMPLE_TIME)
+    {
+        let input = setup();
+        let start = Instant::now();
+        routine(input);
+        samples.push(start.elapsed());
+    }
+    samples.sort();
+    println!(
+        "{:<24} median {:>12.3?}   min {:>12.3?}   samples {}",
+        name,
+        samples[samples.len() / 2],
+        samples[0],
+        samples.len(),
+    );
+}
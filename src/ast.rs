@@ -1,5 +1,5 @@
 
-use crate::{Value, RemovalMode};
+use crate::{Value, RemovalMode, DeletionMode, SystemRunMode, ClosureMode};
 use crate::data::{ArithBinOp, CompareOp};
 use crate::parser::{Span};
 
@@ -86,15 +86,32 @@ pub enum ValueSpecKind<'a> {
     Tuple(Bindable<'a, Vec<ValueSpec<'a>>>),
     Enum(Bindable<'a, Vec<Enumerable<'a>>>),
     Struct(Bindable<'a, Vec<AttributeSpec<'a>>>),
+    Rest(Variable<'a>),
+    Calculation(Calculation<'a>),
 }
 
 #[derive(Debug, Clone)]
 pub struct AttributeSpec<'a> {
     pub position: Span<'a>,
-    pub attribute: Ident<'a>,
+    pub path: Vec<Ident<'a>>,
+    pub attribute: AttributeName<'a>,
+    pub closure: Option<Closure<'a>>,
     pub value_spec: ValueSpec<'a>,
 }
 
+#[derive(Debug, Clone)]
+pub struct Closure<'a> {
+    pub mode: ClosureMode,
+    pub max_depth: Option<i64>,
+    pub depth: Option<Variable<'a>>,
+}
+
+#[derive(Debug, Clone)]
+pub enum AttributeName<'a> {
+    Fixed(Ident<'a>),
+    Variable(Variable<'a>),
+}
+
 #[derive(Debug, Clone)]
 pub struct BindingAttributeSpec<'a> {
     pub position: Span<'a>,
@@ -128,15 +145,80 @@ pub struct Comparison<'a> {
 pub enum Calculation<'a> {
     Int(i64),
     Float(f64),
+    Symbol(Ident<'a>),
     Variable(Variable<'a>),
+    Tuple(Vec<Calculation<'a>>),
+    Call(Ident<'a>, Vec<Calculation<'a>>),
+    Index(Box<Calculation<'a>>, Box<Calculation<'a>>),
+    Slice(Box<Calculation<'a>>, Option<Box<Calculation<'a>>>, Option<Box<Calculation<'a>>>),
     BimOp(ArithBinOp, Box<Calculation<'a>>, Box<Calculation<'a>>),
+    Concat(Box<Calculation<'a>>, Box<Calculation<'a>>),
+    Member(Box<Calculation<'a>>, Box<Calculation<'a>>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Membership<'a> {
+    pub position: Span<'a>,
+    pub item: Enumerable<'a>,
+    pub tuple: Variable<'a>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClearSpec<'a> {
+    pub position: Span<'a>,
+    pub variable: Variable<'a>,
+    pub attribute: Option<(Vec<Ident<'a>>, AttributeName<'a>)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RunSpec<'a> {
+    pub position: Span<'a>,
+    pub system: Path<'a>,
+    pub mode: SystemRunMode,
+    pub inputs: Vec<Variable<'a>>,
+}
+
+// the items of a `{ ... }` block, with the span of its closing brace
+#[derive(Debug, Clone)]
+pub struct Block<'a, T> {
+    pub items: Vec<T>,
+    pub end: Span<'a>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OptionalDefault<'a> {
+    pub position: Span<'a>,
+    pub variable: Variable<'a>,
+    pub value: Literal<'a>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OptionalSelect<'a> {
+    pub position: Span<'a>,
+    pub selects: Block<'a, RuleSelect<'a>>,
+    pub defaults: Block<'a, OptionalDefault<'a>>,
 }
 
 #[derive(Debug, Clone)]
 pub struct ConditionalApply<'a> {
-    pub condition: Vec<RuleSelect<'a>>,
-    pub then_apply: Vec<RuleApply<'a>>,
-    pub otherwise_apply: Vec<RuleApply<'a>>,
+    pub position: Span<'a>,
+    pub condition: Block<'a, RuleSelect<'a>>,
+    pub then_apply: Block<'a, RuleApply<'a>>,
+    pub otherwise_apply: Option<Block<'a, RuleApply<'a>>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ForEachApply<'a> {
+    pub position: Span<'a>,
+    pub selection: Block<'a, RuleSelect<'a>>,
+    pub apply: Block<'a, RuleApply<'a>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchBranch<'a> {
+    pub position: Span<'a>,
+    pub condition: Block<'a, RuleSelect<'a>>,
+    pub apply: Block<'a, RuleApply<'a>>,
 }
 
 #[derive(Debug, Clone)]
@@ -144,21 +226,74 @@ pub enum RuleSelect<'a> {
     Binding(BindingSpec<'a>),
     BindingAttribute(BindingAttributeSpec<'a>),
     Comparison(Comparison<'a>),
-    Not(Vec<RuleSelect<'a>>),
+    Not(Block<'a, RuleSelect<'a>>, Span<'a>),
+    ForAll(Block<'a, RuleSelect<'a>>, Block<'a, RuleSelect<'a>>, Span<'a>),
+    Optional(OptionalSelect<'a>),
     Calculation(Variable<'a>, Calculation<'a>, Span<'a>),
+    Membership(Membership<'a>),
 }
 
 #[derive(Debug, Clone)]
 pub enum RuleApply<'a> {
     Add(BindingAttributeSpec<'a>),
+    Set(BindingAttributeSpec<'a>),
     Remove(BindingAttributeSpec<'a>, RemovalMode),
+    Clear(ClearSpec<'a>),
+    Delete(Variable<'a>, DeletionMode, Span<'a>),
+    Emit(ValueSpec<'a>),
+    Halt(Option<ValueSpec<'a>>, Span<'a>),
+    Run(RunSpec<'a>),
     Conditional(ConditionalApply<'a>),
+    Match(Block<'a, MatchBranch<'a>>, Span<'a>),
+    ForEach(ForEachApply<'a>),
 }
 
 #[derive(Debug, Clone)]
 pub struct Rule<'a> {
+    pub position: Span<'a>,
     pub system_name: Path<'a>,
     pub name: Path<'a>,
-    pub select: Vec<RuleSelect<'a>>,
-    pub apply: Vec<RuleApply<'a>>,
-}
\ No newline at end of file
+    pub select: Block<'a, RuleSelect<'a>>,
+    pub apply: Block<'a, RuleApply<'a>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SystemDeclaration<'a> {
+    pub position: Span<'a>,
+    pub name: Path<'a>,
+    pub inputs: Vec<Ident<'a>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Comment<'a> {
+    pub span: Span<'a>,
+}
+
+#[derive(Debug, Clone)]
+pub enum DocumentItem<'a> {
+    System(SystemDeclaration<'a>),
+    Rule(Rule<'a>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Document<'a> {
+    pub items: Vec<DocumentItem<'a>>,
+    pub comments: Vec<Comment<'a>>,
+}
+
+impl<'a> Document<'a> {
+
+    pub fn systems(&self) -> impl Iterator<Item = &SystemDeclaration<'a>> {
+        self.items.iter().filter_map(|item| match item {
+            DocumentItem::System(system) => Some(system),
+            DocumentItem::Rule(_) => None,
+        })
+    }
+
+    pub fn rules(&self) -> impl Iterator<Item = &Rule<'a>> {
+        self.items.iter().filter_map(|item| match item {
+            DocumentItem::Rule(rule) => Some(rule),
+            DocumentItem::System(_) => None,
+        })
+    }
+}
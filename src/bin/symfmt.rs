@@ -0,0 +1,78 @@
+
+use std::io::{Read, Write};
+use std::process::{exit};
+
+const USAGE: &str = "usage: symfmt [--check] [<file>...]";
+
+fn main() {
+    let mut check = false;
+    let mut paths = Vec::new();
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--check" => check = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            },
+            _ if arg.starts_with('-') => {
+                eprintln!("unknown option `{}`\n{}", arg, USAGE);
+                exit(2);
+            },
+            _ => paths.push(arg),
+        }
+    }
+
+    if paths.is_empty() {
+        let mut source = String::new();
+        if let Err(error) = std::io::stdin().read_to_string(&mut source) {
+            eprintln!("unable to read input: {}", error);
+            exit(2);
+        }
+        let formatted = format_or_exit("<stdin>", &source);
+        if check {
+            if formatted != source {
+                eprintln!("<stdin> is not formatted");
+                exit(1);
+            }
+        } else if let Err(error) = std::io::stdout().write_all(formatted.as_bytes()) {
+            eprintln!("unable to write output: {}", error);
+            exit(2);
+        }
+        return;
+    }
+
+    let mut unformatted = false;
+    for path in &paths {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(error) => {
+                eprintln!("unable to read `{}`: {}", path, error);
+                exit(2);
+            },
+        };
+        let formatted = format_or_exit(path, &source);
+        if formatted == source {
+            continue;
+        }
+        if check {
+            eprintln!("`{}` is not formatted", path);
+            unformatted = true;
+        } else if let Err(error) = std::fs::write(path, formatted) {
+            eprintln!("unable to write `{}`: {}", path, error);
+            exit(2);
+        }
+    }
+    if unformatted {
+        exit(1);
+    }
+}
+
+fn format_or_exit(name: &str, source: &str) -> String {
+    match sym_engine::format_source(source) {
+        Ok(formatted) => formatted,
+        Err(error) => {
+            eprintln!("unable to format `{}`: {}", name, error);
+            exit(2);
+        },
+    }
+}
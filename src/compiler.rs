@@ -1,8 +1,9 @@
 
 use std::sync::{Arc};
 use std::cell::{RefCell};
+use std::collections::{HashMap};
 use num_traits::{ToPrimitive};
-use crate::{ast, Value};
+use crate::{ast, Value, Statistics};
 use crate::data::{ArithBinOp};
 
 mod cfg;
@@ -10,8 +11,12 @@ mod cfg_ops;
 mod optimizer;
 mod ops;
 mod builder;
+mod decompile;
+mod explain;
+mod prefixes;
 
-pub use ops::{Op, OpApply, TupleItem};
+pub use ops::{Op, OpApply, TupleItem, AttributeClosure, ClosureDepth, Comparison};
+pub use prefixes::{SharedPrefixes};
 pub use builder::{
     SelectBuilder,
     EnumBuilder,
@@ -82,6 +87,8 @@ pub struct CompiledRule {
     bindings_len: usize,
     ops: Vec<Op>,
     apply_ops: Vec<OpApply>,
+    plans: Vec<optimizer::SelectPlan>,
+    cfg: cfg::CfgRule,
 }
 
 impl CompiledRule {
@@ -101,6 +108,26 @@ impl CompiledRule {
     pub fn apply_ops(&self) -> &[OpApply] {
         &self.apply_ops
     }
+
+    pub fn to_source(
+        &self,
+        system_name: &str,
+        input_variables: &[Arc<str>],
+    ) -> Result<String, DecompileError> {
+        decompile::rule_source(system_name, input_variables, &self.cfg)
+    }
+
+    pub fn replan(&mut self, input_variables_len: usize, statistics: Option<&Statistics>) {
+        let (ops, apply_ops, plans)
+            = optimizer::optimize(&self.cfg, input_variables_len, statistics);
+        self.ops = ops;
+        self.apply_ops = apply_ops;
+        self.plans = plans;
+    }
+
+    pub fn explain(&self, input_variables: &[Arc<str>]) -> String {
+        explain::explain_rule(self, input_variables)
+    }
 }
 
 struct DisplayVarNames<'a>(&'a [Arc<str>]);
@@ -167,6 +194,71 @@ pub enum CompileError {
     IllegalObjectSpecification {
         line: u32,
     },
+    #[error("illegal place for tuple rest specification at line {line}")]
+    IllegalTupleRest {
+        line: u32,
+    },
+    #[error("multiple tuple rest specifications at line {line}")]
+    MultipleTupleRests {
+        line: u32,
+    },
+    #[error("illegal place for attribute path at line {line}")]
+    IllegalAttributePath {
+        line: u32,
+    },
+    #[error("illegal place for calculation at line {line}")]
+    IllegalCalculation {
+        line: u32,
+    },
+    #[error("illegal attribute closure at line {line}")]
+    IllegalClosure {
+        line: u32,
+    },
+    #[error("unknown function `{name}` at line {line}")]
+    UnknownFunction {
+        line: u32,
+        name: Arc<str>,
+    },
+    #[error("invalid number of arguments for function `{name}` at line {line}")]
+    InvalidArgumentCount {
+        line: u32,
+        name: Arc<str>,
+    },
+    #[error("default for `${name}` which is not bound by the optional pattern at line {line}")]
+    IllegalOptionalDefault {
+        line: u32,
+        name: Arc<str>,
+    },
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum DecompileError {
+    #[error("unknown rule `{0}`")]
+    UnknownRule(Arc<str>),
+    #[error("rule `{rule}` uses the value `{value:?}` which has no source representation")]
+    UnrepresentableValue {
+        rule: Arc<str>,
+        value: Value,
+    },
+    #[error("rule `{rule}` uses the name `{name}` which is not a valid identifier")]
+    InvalidName {
+        rule: Arc<str>,
+        name: Arc<str>,
+    },
+    #[error("rule `{rule}` creates an object that is never attached")]
+    DetachedObject {
+        rule: Arc<str>,
+    },
+    #[error("rule `{rule}` uses a created or calculated value where a variable is required")]
+    UnnamedValue {
+        rule: Arc<str>,
+    },
+    #[error("rule `{rule}` calculates a value that is never used")]
+    UnusedCalculation {
+        rule: Arc<str>,
+    },
+    #[error("decompiled source could not be parsed: {0}")]
+    InvalidSource(Arc<str>),
 }
 
 pub fn build_and_compile<F>(
@@ -182,7 +274,13 @@ where
 {
     let builder::BuiltRule { select, apply, bindings_len }
         = builder::build(input_variables.len(), builder_cb);
-    let cfg = cfg::CfgRule { name, select, apply, bindings_len };
+    let cfg = cfg::CfgRule {
+        name,
+        select,
+        apply,
+        bindings_len,
+        binding_names: HashMap::new(),
+    };
     compile_cfg(cfg, input_variables.len())
 }
 
@@ -200,8 +298,8 @@ fn compile_cfg(
 ) -> CompiledRule {
     let bindings_len = cfg.bindings_len;
     let name = cfg.name.as_ref().into();
-    let (ops, apply_ops) = optimizer::optimize(&cfg, input_variables_len);
-    CompiledRule { name, bindings_len, ops, apply_ops }
+    let (ops, apply_ops, plans) = optimizer::optimize(&cfg, input_variables_len, None);
+    CompiledRule { name, bindings_len, ops, apply_ops, plans, cfg }
 }
 
 #[derive(Debug, Clone)]
@@ -209,6 +307,13 @@ pub enum Calculation {
     Value(Value),
     Binding(Binding),
     BinOp(ArithBinOp, Box<Calculation>, Box<Calculation>),
+    Tuple(Vec<Calculation>),
+    Concat(Box<Calculation>, Box<Calculation>),
+    Member(Box<Calculation>, Box<Calculation>),
+    Len(Box<Calculation>),
+    Index(Box<Calculation>, Box<Calculation>),
+    Slice(Box<Calculation>, Option<Box<Calculation>>, Option<Box<Calculation>>),
+    Symbol(Vec<Calculation>),
 }
 
 impl Calculation {
@@ -226,10 +331,25 @@ impl Calculation {
         match *self {
             Calculation::Value(_) => (),
             Calculation::Binding(binding) => callback(binding),
-            Calculation::BinOp(_, ref left, ref right) => {
+            Calculation::BinOp(_, ref left, ref right)
+            | Calculation::Concat(ref left, ref right)
+            | Calculation::Member(ref left, ref right)
+            | Calculation::Index(ref left, ref right) => {
                 left.for_each_binding(callback);
                 right.for_each_binding(callback);
             },
+            Calculation::Len(ref inner) => inner.for_each_binding(callback),
+            Calculation::Slice(ref inner, ref start, ref end) => {
+                inner.for_each_binding(callback);
+                for bound in start.iter().chain(end.iter()) {
+                    bound.for_each_binding(callback);
+                }
+            },
+            Calculation::Tuple(ref items) | Calculation::Symbol(ref items) => {
+                for item in items {
+                    item.for_each_binding(callback);
+                }
+            },
         }
     }
 }
@@ -283,4 +403,22 @@ pub enum ApplyTupleItem {
 pub enum RemovalMode {
     Optional,
     Required,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DeletionMode {
+    Object,
+    WithReferences,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SystemRunMode {
+    ToFirst,
+    Saturation,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ClosureMode {
+    OneOrMore,
+    ZeroOrMore,
 }
\ No newline at end of file
@@ -11,6 +11,9 @@ use super::{
     Calculation,
     ApplyTupleItem,
     RemovalMode,
+    DeletionMode,
+    SystemRunMode,
+    ClosureMode,
 };
 
 #[derive(Debug)]
@@ -33,7 +36,6 @@ where
         _bindings_lifetime: std::marker::PhantomData,
     };
     let input_bindings = (0..input_bindings_len)
-        .into_iter()
         .map(|_| linked_binding_sequence.next())
         .collect::<Vec<_>>();
     let select_builder = SelectBuilder {
@@ -110,6 +112,71 @@ impl<'seq, 'bind> ApplyBuilder<'seq, 'bind> {
         binding
     }
 
+    pub fn add_calculation<F>(
+        &mut self,
+        calculation_cb: F,
+    ) -> BuilderBinding<'bind>
+    where
+        F: FnOnce(&CalcBuilder) -> CalcBuilderNode,
+    {
+        let calculation_root = calculation_cb(&CalcBuilder(())).0;
+        let binding = self.binding_sequence.next();
+        self.apply.push(CfgOpApply::Calculation {
+            binding: binding.inner,
+            operation: calculation_root,
+        });
+        binding
+    }
+
+    pub fn add_binding_emission(&mut self, binding: BuilderBinding<'bind>) {
+        self.apply.push(CfgOpApply::EmitBinding {
+            binding: binding.inner,
+        });
+    }
+
+    pub fn add_value_emission<V>(&mut self, value: V)
+    where
+        V: Into<Value>,
+    {
+        self.apply.push(CfgOpApply::EmitValue {
+            value: value.into(),
+        });
+    }
+
+    pub fn add_system_run(
+        &mut self,
+        system: &str,
+        mode: SystemRunMode,
+        inputs: &[BuilderBinding<'bind>],
+    ) {
+        self.apply.push(CfgOpApply::RunSystem {
+            system: system.into(),
+            mode,
+            inputs: inputs.iter().map(|input| input.inner).collect(),
+        });
+    }
+
+    pub fn add_halt(&mut self) {
+        self.apply.push(CfgOpApply::Halt {
+            reason: None,
+        });
+    }
+
+    pub fn add_halt_with_value<V>(&mut self, reason: V)
+    where
+        V: Into<Value>,
+    {
+        self.apply.push(CfgOpApply::Halt {
+            reason: Some(ApplyTupleItem::Value(reason.into())),
+        });
+    }
+
+    pub fn add_halt_with_binding(&mut self, reason: BuilderBinding<'bind>) {
+        self.apply.push(CfgOpApply::Halt {
+            reason: Some(ApplyTupleItem::Binding(reason.inner)),
+        });
+    }
+
     pub fn add_binding_attribute_addition<K>(
         &mut self,
         binding: BuilderBinding<'bind>,
@@ -161,6 +228,39 @@ impl<'seq, 'bind> ApplyBuilder<'seq, 'bind> {
         });
     }
 
+    pub fn add_binding_attribute_replacement<K>(
+        &mut self,
+        binding: BuilderBinding<'bind>,
+        attribute: K,
+        value_binding: BuilderBinding<'bind>,
+    )
+    where
+        K: Into<Symbol>,
+    {
+        self.apply.push(CfgOpApply::SetBindingAttribute {
+            binding: binding.inner,
+            attribute: attribute.into(),
+            value_binding: value_binding.inner,
+        });
+    }
+
+    pub fn add_value_attribute_replacement<K, V>(
+        &mut self,
+        binding: BuilderBinding<'bind>,
+        attribute: K,
+        value: V,
+    )
+    where
+        K: Into<Symbol>,
+        V: Into<Value>,
+    {
+        self.apply.push(CfgOpApply::SetValueAttribute {
+            binding: binding.inner,
+            attribute: attribute.into(),
+            value: value.into(),
+        });
+    }
+
     pub fn add_value_attribute_removal<K, V>(
         &mut self,
         binding: BuilderBinding<'bind>,
@@ -179,6 +279,102 @@ impl<'seq, 'bind> ApplyBuilder<'seq, 'bind> {
             mode,
         });
     }
+
+    pub fn add_attributes_clearing(&mut self, binding: BuilderBinding<'bind>) {
+        self.apply.push(CfgOpApply::ClearAttributes {
+            binding: binding.inner,
+        });
+    }
+
+    pub fn add_attribute_clearing<K>(&mut self, binding: BuilderBinding<'bind>, attribute: K)
+    where
+        K: Into<Symbol>,
+    {
+        self.apply.push(CfgOpApply::ClearAttribute {
+            binding: binding.inner,
+            attribute: attribute.into(),
+        });
+    }
+
+    pub fn add_variable_attribute_clearing(
+        &mut self,
+        binding: BuilderBinding<'bind>,
+        attribute_binding: BuilderBinding<'bind>,
+    ) {
+        self.apply.push(CfgOpApply::ClearVariableAttribute {
+            binding: binding.inner,
+            attribute_binding: attribute_binding.inner,
+        });
+    }
+
+    pub fn add_object_deletion(&mut self, binding: BuilderBinding<'bind>, mode: DeletionMode) {
+        self.apply.push(CfgOpApply::DeleteObject {
+            binding: binding.inner,
+            mode,
+        });
+    }
+
+    pub fn add_variable_binding_attribute_addition(
+        &mut self,
+        binding: BuilderBinding<'bind>,
+        attribute_binding: BuilderBinding<'bind>,
+        value_binding: BuilderBinding<'bind>,
+    ) {
+        self.apply.push(CfgOpApply::AddVariableBindingAttribute {
+            binding: binding.inner,
+            attribute_binding: attribute_binding.inner,
+            value_binding: value_binding.inner,
+        });
+    }
+
+    pub fn add_variable_binding_attribute_removal(
+        &mut self,
+        binding: BuilderBinding<'bind>,
+        attribute_binding: BuilderBinding<'bind>,
+        value_binding: BuilderBinding<'bind>,
+        mode: RemovalMode,
+    ) {
+        self.apply.push(CfgOpApply::RemoveVariableBindingAttribute {
+            binding: binding.inner,
+            attribute_binding: attribute_binding.inner,
+            value_binding: value_binding.inner,
+            mode,
+        });
+    }
+
+    pub fn add_variable_value_attribute_addition<V>(
+        &mut self,
+        binding: BuilderBinding<'bind>,
+        attribute_binding: BuilderBinding<'bind>,
+        value: V,
+    )
+    where
+        V: Into<Value>,
+    {
+        self.apply.push(CfgOpApply::AddVariableValueAttribute {
+            binding: binding.inner,
+            attribute_binding: attribute_binding.inner,
+            value: value.into(),
+        });
+    }
+
+    pub fn add_variable_value_attribute_removal<V>(
+        &mut self,
+        binding: BuilderBinding<'bind>,
+        attribute_binding: BuilderBinding<'bind>,
+        value: V,
+        mode: RemovalMode,
+    )
+    where
+        V: Into<Value>,
+    {
+        self.apply.push(CfgOpApply::RemoveVariableValueAttribute {
+            binding: binding.inner,
+            attribute_binding: attribute_binding.inner,
+            value: value.into(),
+            mode,
+        });
+    }
 }
 
 #[derive(Debug)]
@@ -208,7 +404,7 @@ pub struct SelectBuilder<'seq, 'bind> {
 
 impl<'seq, 'bind> SelectBuilder<'seq, 'bind> {
 
-    pub fn into_apply_builder<'select>(self) -> ApplyBuilder<'seq, 'bind> {
+    pub fn into_apply_builder(self) -> ApplyBuilder<'seq, 'bind> {
         let binding_sequence = self.binding_sequence;
         ApplyBuilder {
             binding_sequence,
@@ -267,15 +463,14 @@ impl<'seq, 'bind> SelectBuilder<'seq, 'bind> {
         enum_builder_cb: F,
     )
     where
-        F: FnOnce(&mut EnumBuilder<'seq, 'bind>),
+        F: FnOnce(&mut EnumBuilder<'bind>),
     {
-        let Self { binding_sequence, select } = self;
         let mut enum_builder = EnumBuilder {
-            binding_sequence: *binding_sequence,
             enum_options: Vec::new(),
+            _bindings_lifetime: std::marker::PhantomData,
         };
         enum_builder_cb(&mut enum_builder);
-        select.push(CfgOpSelect::EnumBinding {
+        self.select.push(CfgOpSelect::EnumBinding {
             binding: binding.inner,
             options: enum_builder.enum_options,
         });
@@ -345,6 +540,103 @@ impl<'seq, 'bind> SelectBuilder<'seq, 'bind> {
         });
     }
 
+    pub fn add_attribute_closure<K>(
+        &mut self,
+        binding: BuilderBinding<'bind>,
+        attribute: K,
+        mode: ClosureMode,
+        max_depth: Option<usize>,
+    ) -> (BuilderBinding<'bind>, BuilderBinding<'bind>)
+    where
+        K: Into<Symbol>,
+    {
+        let value_binding = self.binding_sequence.next();
+        let depth_binding = self.binding_sequence.next();
+        self.select.push(CfgOpSelect::AttributeClosure {
+            binding: binding.inner,
+            attribute: attribute.into(),
+            mode,
+            max_depth,
+            value_binding: value_binding.inner,
+            depth_binding: Some(depth_binding.inner),
+        });
+        (value_binding, depth_binding)
+    }
+
+    pub fn add_attribute_closure_requirement<K>(
+        &mut self,
+        binding: BuilderBinding<'bind>,
+        attribute: K,
+        mode: ClosureMode,
+        max_depth: Option<usize>,
+        value_binding: BuilderBinding<'bind>,
+    )
+    where
+        K: Into<Symbol>,
+    {
+        self.select.push(CfgOpSelect::AttributeClosure {
+            binding: binding.inner,
+            attribute: attribute.into(),
+            mode,
+            max_depth,
+            value_binding: value_binding.inner,
+            depth_binding: None,
+        });
+    }
+
+    pub fn add_attribute_name_binding(
+        &mut self,
+        binding: BuilderBinding<'bind>,
+    ) -> BuilderBinding<'bind> {
+        let attribute_binding = self.binding_sequence.next();
+        self.select.push(CfgOpSelect::RequireVariableAttribute {
+            binding: binding.inner,
+            attribute_binding: attribute_binding.inner,
+        });
+        attribute_binding
+    }
+
+    pub fn add_attribute_pair_binding(
+        &mut self,
+        binding: BuilderBinding<'bind>,
+    ) -> (BuilderBinding<'bind>, BuilderBinding<'bind>) {
+        let attribute_binding = self.binding_sequence.next();
+        let value_binding = self.binding_sequence.next();
+        self.select.push(CfgOpSelect::VariableAttributeBinding {
+            binding: binding.inner,
+            attribute_binding: attribute_binding.inner,
+            value_binding: value_binding.inner,
+        });
+        (attribute_binding, value_binding)
+    }
+
+    pub fn add_variable_attribute_binding(
+        &mut self,
+        binding: BuilderBinding<'bind>,
+        attribute_binding: BuilderBinding<'bind>,
+    ) -> BuilderBinding<'bind> {
+        let value_binding = self.binding_sequence.next();
+        self.select.push(CfgOpSelect::VariableAttributeBinding {
+            binding: binding.inner,
+            attribute_binding: attribute_binding.inner,
+            value_binding: value_binding.inner,
+        });
+        value_binding
+    }
+
+    pub fn add_variable_attribute_binding_requirement(
+        &mut self,
+        binding: BuilderBinding<'bind>,
+        attribute_binding: BuilderBinding<'bind>,
+        value_binding: BuilderBinding<'bind>,
+    ) {
+        self.select.push(CfgOpSelect::VariableAttributeBinding {
+            binding: binding.inner,
+            attribute_binding: attribute_binding.inner,
+            value_binding: value_binding.inner,
+        });
+    }
+
     pub fn add_not_clause<'bind_inner, F>(
         &mut self,
         not_clause_cb: F,
@@ -366,6 +658,40 @@ impl<'seq, 'bind> SelectBuilder<'seq, 'bind> {
         });
     }
 
+    pub fn add_forall_clause<'bind_inner, P, C, T>(
+        &mut self,
+        premise_cb: P,
+        conclusion_cb: C,
+    )
+    where
+        'bind: 'bind_inner,
+        P: FnOnce(&mut SelectBuilder<'_, 'bind_inner>) -> T,
+        C: FnOnce(&mut SelectBuilder<'_, 'bind_inner>, T),
+    {
+        let Self { binding_sequence, select } = self;
+        let binding_mark = binding_sequence.mark();
+        let mut premise_builder = SelectBuilder {
+            binding_sequence: *binding_sequence,
+            select: Vec::new(),
+        };
+        let premise = premise_cb(&mut premise_builder);
+        let conclusion_mark = binding_sequence.mark();
+        let mut conclusion_builder = SelectBuilder {
+            binding_sequence: *binding_sequence,
+            select: Vec::new(),
+        };
+        conclusion_cb(&mut conclusion_builder, premise);
+        let mut body = premise_builder.select;
+        body.push(CfgOpSelect::Not {
+            body: conclusion_builder.select,
+            binding_mark: conclusion_mark,
+        });
+        select.push(CfgOpSelect::Not {
+            body,
+            binding_mark,
+        });
+    }
+
     pub fn add_comparison<F>(
         &mut self,
         comparison_cb: F,
@@ -401,6 +727,43 @@ impl<'seq, 'bind> SelectBuilder<'seq, 'bind> {
         });
         binding
     }
+
+    pub fn add_tuple_member_value_requirement<V>(
+        &mut self,
+        binding: BuilderBinding<'bind>,
+        value: V,
+    )
+    where
+        V: Into<Value>,
+    {
+        self.select.push(CfgOpSelect::RequireTupleMember {
+            binding: binding.inner,
+            value: value.into(),
+        });
+    }
+
+    pub fn add_tuple_member_binding(
+        &mut self,
+        binding: BuilderBinding<'bind>,
+    ) -> BuilderBinding<'bind> {
+        let value_binding = self.binding_sequence.next();
+        self.select.push(CfgOpSelect::TupleMemberBinding {
+            binding: binding.inner,
+            value_binding: value_binding.inner,
+        });
+        value_binding
+    }
+
+    pub fn add_tuple_member_binding_requirement(
+        &mut self,
+        binding: BuilderBinding<'bind>,
+        value_binding: BuilderBinding<'bind>,
+    ) {
+        self.select.push(CfgOpSelect::TupleMemberBinding {
+            binding: binding.inner,
+            value_binding: value_binding.inner,
+        });
+    }
 }
 
 #[derive(Debug)]
@@ -431,6 +794,44 @@ impl CalcBuilder {
     fn_calc_builder_binop!(subtract, ArithBinOp::Sub);
     fn_calc_builder_binop!(multiply, ArithBinOp::Mul);
     fn_calc_builder_binop!(divide, ArithBinOp::Div);
+
+    pub fn tuple(&self, items: Vec<CalcBuilderNode>) -> CalcBuilderNode {
+        CalcBuilderNode(Calculation::Tuple(items.into_iter().map(|item| item.0).collect()))
+    }
+
+    pub fn concat(&self, left: CalcBuilderNode, right: CalcBuilderNode) -> CalcBuilderNode {
+        CalcBuilderNode(Calculation::Concat(Box::new(left.0), Box::new(right.0)))
+    }
+
+    pub fn member(&self, item: CalcBuilderNode, tuple: CalcBuilderNode) -> CalcBuilderNode {
+        CalcBuilderNode(Calculation::Member(Box::new(item.0), Box::new(tuple.0)))
+    }
+
+    pub fn len(&self, tuple: CalcBuilderNode) -> CalcBuilderNode {
+        CalcBuilderNode(Calculation::Len(Box::new(tuple.0)))
+    }
+
+    pub fn index(&self, tuple: CalcBuilderNode, index: CalcBuilderNode) -> CalcBuilderNode {
+        CalcBuilderNode(Calculation::Index(Box::new(tuple.0), Box::new(index.0)))
+    }
+
+    pub fn slice(
+        &self,
+        tuple: CalcBuilderNode,
+        start: Option<CalcBuilderNode>,
+        end: Option<CalcBuilderNode>,
+    ) -> CalcBuilderNode {
+        CalcBuilderNode(Calculation::Slice(
+            Box::new(tuple.0),
+            start.map(|start| Box::new(start.0)),
+            end.map(|end| Box::new(end.0)),
+        ))
+    }
+
+    pub fn symbol(&self, parts: Vec<CalcBuilderNode>) -> CalcBuilderNode {
+        assert!(!parts.is_empty(), "symbol calculation requires at least one part");
+        CalcBuilderNode(Calculation::Symbol(parts.into_iter().map(|part| part.0).collect()))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -510,12 +911,12 @@ impl<const LEFT: bool, const RIGHT: bool> CompareBuilder<false, LEFT, RIGHT> {
 }
 
 #[derive(Debug)]
-pub struct EnumBuilder<'seq, 'bind> {
-    binding_sequence: LinkedBindingSequence<'seq, 'bind>,
+pub struct EnumBuilder<'bind> {
     enum_options: Vec<EnumOption>,
+    _bindings_lifetime: std::marker::PhantomData<fn() -> &'bind ()>,
 }
 
-impl<'seq, 'bind> EnumBuilder<'seq, 'bind> {
+impl<'bind> EnumBuilder<'bind> {
 
     pub fn add_value_option<V>(&mut self, value: V)
     where
@@ -557,4 +958,26 @@ impl<'seq, 'bind> TupleBuilder<'seq, 'bind> {
     pub fn add_existing_binding_item(&mut self, binding: BuilderBinding<'bind>) {
         self.tuple_items.push(OpenTupleItem::Binding(binding.inner));
     }
+
+    pub fn add_ignored_rest(&mut self) {
+        self.push_rest(OpenTupleItem::IgnoreRest);
+    }
+
+    pub fn add_new_rest_binding(&mut self) -> BuilderBinding<'bind> {
+        let binding = self.binding_sequence.next();
+        self.push_rest(OpenTupleItem::RestBinding(binding.inner));
+        binding
+    }
+
+    pub fn add_existing_rest_binding(&mut self, binding: BuilderBinding<'bind>) {
+        self.push_rest(OpenTupleItem::RestBinding(binding.inner));
+    }
+
+    fn push_rest(&mut self, item: OpenTupleItem) {
+        assert!(
+            !self.tuple_items.iter().any(OpenTupleItem::is_rest),
+            "only a single rest item per tuple",
+        );
+        self.tuple_items.push(item);
+    }
 }
\ No newline at end of file
@@ -2,7 +2,8 @@
 use std::sync::{Arc};
 use std::cell::{RefCell};
 use std::collections::{HashMap};
-use crate::{ast, Value};
+use num_traits::{ToPrimitive};
+use crate::{ast, Value, Symbol};
 use crate::parser::{Span};
 use super::cfg_ops::{CfgOpSelect, CfgOpApply, OpenTupleItem};
 use super::{
@@ -32,19 +33,25 @@ pub fn ast_to_cfg(
     }
 
     let mut select = Vec::new();
-    compile_rule_selects(&mut env, &ast.select, &mut select)?;
+    compile_rule_selects(&mut env, &ast.select.items, &mut select)?;
 
     let mut apply = Vec::new();
-    compile_rule_applys(&mut env, &ast.apply, &mut apply)?;
+    compile_rule_applys(&mut env, &ast.apply.items, &mut apply)?;
 
     verify_distinct_bindings(&instance_counts.borrow())?;
     verify_multi_usage(&env, &access_counts.borrow(), input_variables.len())?;
 
+    let names = binding_names.borrow()
+        .iter()
+        .map(|(&binding, name)| (binding, name.as_str().into()))
+        .collect();
+
     let cfg_rule = CfgRule {
         name: ast.name.as_str().into(),
-        select: select,
-        apply: apply,
+        select,
+        apply,
         bindings_len: binding_sequence.len(),
+        binding_names: names,
     };
     Ok(cfg_rule)
 }
@@ -55,6 +62,7 @@ pub struct CfgRule {
     pub select: Vec<CfgOpSelect>,
     pub apply: Vec<CfgOpApply>,
     pub bindings_len: usize,
+    pub binding_names: HashMap<Binding, Arc<str>>,
 }
 
 #[derive(Debug, Clone)]
@@ -188,8 +196,44 @@ fn compile_rule_apply(
 ) -> Result<(), CompileError> {
     match rule_apply {
         ast::RuleApply::Remove(spec, mode) => compile_apply_remove(env, spec, *mode, ops),
-        ast::RuleApply::Add(spec) => compile_apply_add(env, spec, ops),
+        ast::RuleApply::Add(spec) => compile_apply_add(env, spec, AdditionMode::Add, ops),
+        ast::RuleApply::Set(spec) => compile_apply_add(env, spec, AdditionMode::Replace, ops),
+        ast::RuleApply::Clear(spec) => compile_apply_clear(env, spec, ops),
+        ast::RuleApply::Delete(variable, mode, position) => {
+            let binding = existing_named_binding(env, variable, position)?;
+            ops.push(CfgOpApply::DeleteObject { binding, mode: *mode });
+            Ok(())
+        },
+        ast::RuleApply::Emit(value_spec) => {
+            let op = match compile_apply_value(env, value_spec, true, ops)? {
+                ApplyTupleItem::Value(value) => CfgOpApply::EmitValue { value },
+                ApplyTupleItem::Binding(binding) => CfgOpApply::EmitBinding { binding },
+            };
+            ops.push(op);
+            Ok(())
+        },
+        ast::RuleApply::Halt(reason, _) => {
+            let reason = match reason {
+                Some(value_spec) => Some(compile_apply_value(env, value_spec, true, ops)?),
+                None => None,
+            };
+            ops.push(CfgOpApply::Halt { reason });
+            Ok(())
+        },
+        ast::RuleApply::Run(spec) => {
+            let inputs = spec.inputs.iter()
+                .map(|variable| existing_named_binding(env, variable, &spec.position))
+                .collect::<Result<Vec<_>, _>>()?;
+            ops.push(CfgOpApply::RunSystem {
+                system: spec.system.as_str().into(),
+                mode: spec.mode,
+                inputs,
+            });
+            Ok(())
+        },
         ast::RuleApply::Conditional(cond) => compile_apply_conditional(env, cond, ops),
+        ast::RuleApply::Match(branches, _) => compile_apply_match(env, &branches.items, ops),
+        ast::RuleApply::ForEach(for_each) => compile_apply_for_each(env, for_each, ops),
     }
 }
 
@@ -199,22 +243,22 @@ fn compile_apply_conditional(
     ops: &mut Vec<CfgOpApply>,
 ) -> Result<(), CompileError> {
 
+    let mut then_env = env.clone();
     let condition = {
         let mut ops = Vec::new();
-        let mut env = env.clone();
-        compile_rule_selects(&mut env, &cond.condition, &mut ops)?;
+        compile_rule_selects(&mut then_env, &cond.condition.items, &mut ops)?;
         ops
     };
     let then_apply = {
         let mut ops = Vec::new();
-        let mut env = env.clone();
-        compile_rule_applys(&mut env, &cond.then_apply, &mut ops)?;
+        compile_rule_applys(&mut then_env, &cond.then_apply.items, &mut ops)?;
         ops
     };
     let otherwise_apply = {
         let mut ops = Vec::new();
         let mut env = env.clone();
-        compile_rule_applys(&mut env, &cond.otherwise_apply, &mut ops)?;
+        let otherwise_apply = cond.otherwise_apply.as_ref().map_or(&[][..], |block| &block.items);
+        compile_rule_applys(&mut env, otherwise_apply, &mut ops)?;
         ops
     };
 
@@ -222,47 +266,101 @@ fn compile_apply_conditional(
     Ok(())
 }
 
+fn compile_apply_match(
+    env: &mut Env,
+    branches: &[ast::MatchBranch],
+    ops: &mut Vec<CfgOpApply>,
+) -> Result<(), CompileError> {
+
+    let mut compiled = Vec::new();
+    for branch in branches {
+        let mut branch_env = env.clone();
+        let mut condition = Vec::new();
+        compile_rule_selects(&mut branch_env, &branch.condition.items, &mut condition)?;
+        let mut apply = Vec::new();
+        compile_rule_applys(&mut branch_env, &branch.apply.items, &mut apply)?;
+        compiled.push((condition, apply));
+    }
+
+    ops.push(CfgOpApply::Match { branches: compiled });
+    Ok(())
+}
+
+fn compile_apply_for_each(
+    env: &mut Env,
+    for_each: &ast::ForEachApply,
+    ops: &mut Vec<CfgOpApply>,
+) -> Result<(), CompileError> {
+
+    let mut loop_env = env.clone();
+    let mut selection = Vec::new();
+    compile_rule_selects(&mut loop_env, &for_each.selection.items, &mut selection)?;
+    let mut apply = Vec::new();
+    compile_rule_applys(&mut loop_env, &for_each.apply.items, &mut apply)?;
+
+    ops.push(CfgOpApply::ForEach { selection, apply });
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy)]
+enum AdditionMode {
+    Add,
+    Replace,
+}
+
 fn compile_apply_add(
     env: &mut Env<'_>,
     spec: &ast::BindingAttributeSpec<'_>,
+    mode: AdditionMode,
     ops: &mut Vec<CfgOpApply>,
 ) -> Result<(), CompileError> {
     let binding = existing_named_binding(env, &spec.variable, &spec.position)?;
-    compile_apply_add_attribute(env, binding, &spec.attribute_spec, ops)
+    let binding = compile_apply_attribute_path(env, binding, &spec.attribute_spec.path, ops);
+    compile_apply_add_attribute(env, binding, &spec.attribute_spec, mode, ops)
+}
+
+fn compile_apply_attribute_path(
+    env: &mut Env<'_>,
+    binding: Binding,
+    path: &[ast::Ident<'_>],
+    ops: &mut Vec<CfgOpApply>,
+) -> Binding {
+    let mut binding = binding;
+    for step in path {
+        let value_binding = env.anon();
+        ops.push(CfgOpApply::ResolveAttribute {
+            binding,
+            attribute: step.as_str().into(),
+            value_binding,
+        });
+        binding = value_binding;
+    }
+    binding
 }
 
 fn compile_apply_add_attribute(
     env: &mut Env<'_>,
     binding: Binding,
     spec: &ast::AttributeSpec<'_>,
+    mode: AdditionMode,
     ops: &mut Vec<CfgOpApply>,
 ) -> Result<(), CompileError> {
+    no_closure(&spec.closure, &spec.position)?;
+    let attribute = apply_attribute(env, &spec.attribute, &spec.position)?;
     match &spec.value_spec.kind {
         ast::ValueSpecKind::Literal(literal) => {
-            ops.push(CfgOpApply::AddValueAttribute {
-                binding,
-                attribute: spec.attribute.as_str().into(),
-                value: literal.to_value(),
-            });
+            ops.push(attribute.add_value(binding, literal.to_value(), mode));
             Ok(())
         },
         ast::ValueSpecKind::Variable(variable) => {
             let value_binding = existing_named_binding(env, variable, &spec.position)?;
-            ops.push(CfgOpApply::AddBindingAttribute {
-                binding,
-                attribute: spec.attribute.as_str().into(),
-                value_binding,
-            });
+            ops.push(attribute.add_binding(binding, value_binding, mode));
             Ok(())
         },
         ast::ValueSpecKind::Tuple(ast::Bindable { variable: direct, inner: values }) => {
             let value_binding = nameable_new_binding(env, direct, &spec.position)?;
             compile_apply_tuple(env, value_binding, values, true, ops)?;
-            ops.push(CfgOpApply::AddBindingAttribute {
-                binding,
-                attribute: spec.attribute.as_str().into(),
-                value_binding,
-            });
+            ops.push(attribute.add_binding(binding, value_binding, mode));
             Ok(())
         },
         ast::ValueSpecKind::Enum(_) => Err(CompileError::IllegalEnumSpecification {
@@ -271,13 +369,17 @@ fn compile_apply_add_attribute(
         ast::ValueSpecKind::Struct(ast::Bindable { variable: direct, inner: attributes }) => {
             let value_binding = nameable_new_binding(env, direct, &spec.position)?;
             compile_apply_object(env, value_binding, attributes, ops)?;
-            ops.push(CfgOpApply::AddBindingAttribute {
-                binding,
-                attribute: spec.attribute.as_str().into(),
-                value_binding,
-            });
+            ops.push(attribute.add_binding(binding, value_binding, mode));
+            Ok(())
+        },
+        ast::ValueSpecKind::Calculation(calculation) => {
+            let value_binding = compile_apply_calculation(env, calculation, &spec.position, ops)?;
+            ops.push(attribute.add_binding(binding, value_binding, mode));
             Ok(())
         },
+        ast::ValueSpecKind::Rest(_) => Err(CompileError::IllegalTupleRest {
+            line: spec.position.location_line(),
+        }),
     }
 }
 
@@ -288,35 +390,23 @@ fn compile_apply_remove(
     ops: &mut Vec<CfgOpApply>,
 ) -> Result<(), CompileError> {
     let binding = existing_named_binding(env, &spec.variable, &spec.position)?;
+    let binding = compile_apply_attribute_path(env, binding, &spec.attribute_spec.path, ops);
+    no_closure(&spec.attribute_spec.closure, &spec.position)?;
+    let attribute = apply_attribute(env, &spec.attribute_spec.attribute, &spec.position)?;
     match &spec.attribute_spec.value_spec.kind {
         ast::ValueSpecKind::Literal(literal) => {
-            ops.push(CfgOpApply::RemoveValueAttribute {
-                binding,
-                attribute: spec.attribute_spec.attribute.as_str().into(),
-                value: literal.to_value(),
-                mode,
-            });
+            ops.push(attribute.remove_value(binding, literal.to_value(), mode));
             Ok(())
         },
         ast::ValueSpecKind::Variable(variable) => {
             let value_binding = existing_named_binding(env, variable, &spec.position)?;
-            ops.push(CfgOpApply::RemoveBindingAttribute {
-                binding,
-                attribute: spec.attribute_spec.attribute.as_str().into(),
-                value_binding,
-                mode,
-            });
+            ops.push(attribute.remove_binding(binding, value_binding, mode));
             Ok(())
         },
         ast::ValueSpecKind::Tuple(ast::Bindable { variable: direct, inner: values }) => {
             let value_binding = nameable_new_binding(env, direct, &spec.position)?;
             compile_apply_tuple(env, value_binding, values, false, ops)?;
-            ops.push(CfgOpApply::RemoveBindingAttribute {
-                binding,
-                attribute: spec.attribute_spec.attribute.as_str().into(),
-                value_binding,
-                mode,
-            });
+            ops.push(attribute.remove_binding(binding, value_binding, mode));
             Ok(())
         },
         ast::ValueSpecKind::Enum(_) => Err(CompileError::IllegalEnumSpecification {
@@ -325,6 +415,191 @@ fn compile_apply_remove(
         ast::ValueSpecKind::Struct(_) => Err(CompileError::IllegalObjectSpecification {
             line: spec.position.location_line(),
         }),
+        ast::ValueSpecKind::Rest(_) => Err(CompileError::IllegalTupleRest {
+            line: spec.position.location_line(),
+        }),
+        ast::ValueSpecKind::Calculation(calculation) => {
+            let value_binding = compile_apply_calculation(env, calculation, &spec.position, ops)?;
+            ops.push(attribute.remove_binding(binding, value_binding, mode));
+            Ok(())
+        },
+    }
+}
+
+fn compile_apply_clear(
+    env: &mut Env<'_>,
+    spec: &ast::ClearSpec<'_>,
+    ops: &mut Vec<CfgOpApply>,
+) -> Result<(), CompileError> {
+    let binding = existing_named_binding(env, &spec.variable, &spec.position)?;
+    if let Some((path, attribute)) = &spec.attribute {
+        let binding = compile_apply_attribute_path(env, binding, path, ops);
+        let attribute = apply_attribute(env, attribute, &spec.position)?;
+        ops.push(attribute.clear(binding));
+    } else {
+        ops.push(CfgOpApply::ClearAttributes { binding });
+    }
+    Ok(())
+}
+
+fn compile_apply_calculation(
+    env: &mut Env<'_>,
+    calculation: &ast::Calculation<'_>,
+    position: &Span<'_>,
+    ops: &mut Vec<CfgOpApply>,
+) -> Result<Binding, CompileError> {
+    let operation = compile_calculation(env, position, calculation)?;
+    let binding = env.anon();
+    ops.push(CfgOpApply::Calculation { binding, operation });
+    Ok(binding)
+}
+
+fn no_closure(
+    closure: &Option<ast::Closure<'_>>,
+    position: &Span<'_>,
+) -> Result<(), CompileError> {
+    if closure.is_some() {
+        Err(CompileError::IllegalClosure {
+            line: position.location_line(),
+        })
+    } else {
+        Ok(())
+    }
+}
+
+enum ApplyAttribute {
+    Fixed(Symbol),
+    Variable(Binding),
+}
+
+impl ApplyAttribute {
+
+    fn add_binding(
+        &self,
+        binding: Binding,
+        value_binding: Binding,
+        mode: AdditionMode,
+    ) -> CfgOpApply {
+        match (self, mode) {
+            (Self::Fixed(attribute), AdditionMode::Add) => CfgOpApply::AddBindingAttribute {
+                binding,
+                attribute: attribute.clone(),
+                value_binding,
+            },
+            (Self::Fixed(attribute), AdditionMode::Replace) => CfgOpApply::SetBindingAttribute {
+                binding,
+                attribute: attribute.clone(),
+                value_binding,
+            },
+            (Self::Variable(attribute_binding), AdditionMode::Add) => {
+                CfgOpApply::AddVariableBindingAttribute {
+                    binding,
+                    attribute_binding: *attribute_binding,
+                    value_binding,
+                }
+            },
+            (Self::Variable(attribute_binding), AdditionMode::Replace) => {
+                CfgOpApply::SetVariableBindingAttribute {
+                    binding,
+                    attribute_binding: *attribute_binding,
+                    value_binding,
+                }
+            },
+        }
+    }
+
+    fn add_value(&self, binding: Binding, value: Value, mode: AdditionMode) -> CfgOpApply {
+        match (self, mode) {
+            (Self::Fixed(attribute), AdditionMode::Add) => CfgOpApply::AddValueAttribute {
+                binding,
+                attribute: attribute.clone(),
+                value,
+            },
+            (Self::Fixed(attribute), AdditionMode::Replace) => CfgOpApply::SetValueAttribute {
+                binding,
+                attribute: attribute.clone(),
+                value,
+            },
+            (Self::Variable(attribute_binding), AdditionMode::Add) => {
+                CfgOpApply::AddVariableValueAttribute {
+                    binding,
+                    attribute_binding: *attribute_binding,
+                    value,
+                }
+            },
+            (Self::Variable(attribute_binding), AdditionMode::Replace) => {
+                CfgOpApply::SetVariableValueAttribute {
+                    binding,
+                    attribute_binding: *attribute_binding,
+                    value,
+                }
+            },
+        }
+    }
+
+    fn remove_binding(
+        &self,
+        binding: Binding,
+        value_binding: Binding,
+        mode: RemovalMode,
+    ) -> CfgOpApply {
+        match *self {
+            Self::Fixed(ref attribute) => CfgOpApply::RemoveBindingAttribute {
+                binding,
+                attribute: attribute.clone(),
+                value_binding,
+                mode,
+            },
+            Self::Variable(attribute_binding) => CfgOpApply::RemoveVariableBindingAttribute {
+                binding,
+                attribute_binding,
+                value_binding,
+                mode,
+            },
+        }
+    }
+
+    fn remove_value(&self, binding: Binding, value: Value, mode: RemovalMode) -> CfgOpApply {
+        match *self {
+            Self::Fixed(ref attribute) => CfgOpApply::RemoveValueAttribute {
+                binding,
+                attribute: attribute.clone(),
+                value,
+                mode,
+            },
+            Self::Variable(attribute_binding) => CfgOpApply::RemoveVariableValueAttribute {
+                binding,
+                attribute_binding,
+                value,
+                mode,
+            },
+        }
+    }
+
+    fn clear(&self, binding: Binding) -> CfgOpApply {
+        match *self {
+            Self::Fixed(ref attribute) => CfgOpApply::ClearAttribute {
+                binding,
+                attribute: attribute.clone(),
+            },
+            Self::Variable(attribute_binding) => CfgOpApply::ClearVariableAttribute {
+                binding,
+                attribute_binding,
+            },
+        }
+    }
+}
+
+fn apply_attribute(
+    env: &mut Env<'_>,
+    attribute: &ast::AttributeName<'_>,
+    position: &Span<'_>,
+) -> Result<ApplyAttribute, CompileError> {
+    match attribute {
+        ast::AttributeName::Fixed(attribute) => Ok(ApplyAttribute::Fixed(attribute.as_str().into())),
+        ast::AttributeName::Variable(variable) => {
+            Ok(ApplyAttribute::Variable(existing_named_binding(env, variable, position)?))
+        },
     }
 }
 
@@ -338,7 +613,12 @@ fn compile_apply_object(
         binding,
     });
     for attribute in attributes {
-        compile_apply_add_attribute(env, binding, attribute, ops)?;
+        if !attribute.path.is_empty() {
+            return Err(CompileError::IllegalAttributePath {
+                line: attribute.position.location_line(),
+            });
+        }
+        compile_apply_add_attribute(env, binding, attribute, AdditionMode::Add, ops)?;
     }
     Ok(())
 }
@@ -352,36 +632,7 @@ fn compile_apply_tuple(
 ) -> Result<(), CompileError> {
     let mut cfg_tuple_items = Vec::new();
     for value_spec in values {
-        match &value_spec.kind {
-            ast::ValueSpecKind::Literal(literal) => {
-                cfg_tuple_items.push(ApplyTupleItem::Value(literal.to_value()));
-            },
-            ast::ValueSpecKind::Variable(variable) => {
-                let value_binding = existing_named_binding(env, variable, &value_spec.position)?;
-                cfg_tuple_items.push(ApplyTupleItem::Binding(value_binding));
-            },
-            ast::ValueSpecKind::Tuple(ast::Bindable { variable: direct, inner: values }) => {
-                let value_binding = nameable_new_binding(env, direct, &value_spec.position)?;
-                compile_apply_tuple(env, value_binding, values, allow_object_construction, ops)?;
-                cfg_tuple_items.push(ApplyTupleItem::Binding(value_binding));
-            },
-            ast::ValueSpecKind::Struct(ast::Bindable { variable: direct, inner: attributes }) => {
-                if allow_object_construction {
-                    let value_binding = nameable_new_binding(env, direct, &value_spec.position)?;
-                    compile_apply_object(env, value_binding, attributes, ops)?;
-                    cfg_tuple_items.push(ApplyTupleItem::Binding(value_binding));
-                } else {
-                    return Err(CompileError::IllegalObjectSpecification {
-                        line: value_spec.position.location_line(),
-                    });
-                }
-            },
-            ast::ValueSpecKind::Enum(_) => {
-                return Err(CompileError::IllegalEnumSpecification {
-                    line: value_spec.position.location_line(),
-                });
-            },
-        }
+        cfg_tuple_items.push(compile_apply_value(env, value_spec, allow_object_construction, ops)?);
     }
     ops.push(CfgOpApply::CreateTuple {
         binding,
@@ -390,6 +641,50 @@ fn compile_apply_tuple(
     Ok(())
 }
 
+fn compile_apply_value(
+    env: &mut Env<'_>,
+    value_spec: &ast::ValueSpec<'_>,
+    allow_object_construction: bool,
+    ops: &mut Vec<CfgOpApply>,
+) -> Result<ApplyTupleItem, CompileError> {
+    match &value_spec.kind {
+        ast::ValueSpecKind::Literal(literal) => {
+            Ok(ApplyTupleItem::Value(literal.to_value()))
+        },
+        ast::ValueSpecKind::Variable(variable) => {
+            let value_binding = existing_named_binding(env, variable, &value_spec.position)?;
+            Ok(ApplyTupleItem::Binding(value_binding))
+        },
+        ast::ValueSpecKind::Tuple(ast::Bindable { variable: direct, inner: values }) => {
+            let value_binding = nameable_new_binding(env, direct, &value_spec.position)?;
+            compile_apply_tuple(env, value_binding, values, allow_object_construction, ops)?;
+            Ok(ApplyTupleItem::Binding(value_binding))
+        },
+        ast::ValueSpecKind::Struct(ast::Bindable { variable: direct, inner: attributes }) => {
+            if allow_object_construction {
+                let value_binding = nameable_new_binding(env, direct, &value_spec.position)?;
+                compile_apply_object(env, value_binding, attributes, ops)?;
+                Ok(ApplyTupleItem::Binding(value_binding))
+            } else {
+                Err(CompileError::IllegalObjectSpecification {
+                    line: value_spec.position.location_line(),
+                })
+            }
+        },
+        ast::ValueSpecKind::Enum(_) => Err(CompileError::IllegalEnumSpecification {
+            line: value_spec.position.location_line(),
+        }),
+        ast::ValueSpecKind::Rest(_) => Err(CompileError::IllegalTupleRest {
+            line: value_spec.position.location_line(),
+        }),
+        ast::ValueSpecKind::Calculation(calculation) => {
+            let value_binding =
+                compile_apply_calculation(env, calculation, &value_spec.position, ops)?;
+            Ok(ApplyTupleItem::Binding(value_binding))
+        },
+    }
+}
+
 fn existing_named_binding_with_name(
     env: &mut Env<'_>,
     variable: &ast::Variable<'_>,
@@ -487,11 +782,30 @@ fn compile_rule_select(
                 ops,
             )
         },
-        ast::RuleSelect::Not(sub_selects) => {
+        ast::RuleSelect::Not(sub_selects, _) => {
+            let mut sub_ops = Vec::new();
+            let mut sub_env = env.clone();
+            let binding_mark = sub_env.binding_sequence.mark();
+            compile_rule_selects(&mut sub_env, &sub_selects.items, &mut sub_ops)?;
+            ops.push(CfgOpSelect::Not {
+                body: sub_ops,
+                binding_mark,
+            });
+            Ok(())
+        },
+        ast::RuleSelect::Optional(optional) => compile_select_optional(env, optional, ops),
+        ast::RuleSelect::ForAll(premise, conclusion, _) => {
             let mut sub_ops = Vec::new();
             let mut sub_env = env.clone();
             let binding_mark = sub_env.binding_sequence.mark();
-            compile_rule_selects(&mut sub_env, sub_selects, &mut sub_ops)?;
+            compile_rule_selects(&mut sub_env, &premise.items, &mut sub_ops)?;
+            let mut conclusion_ops = Vec::new();
+            let conclusion_mark = sub_env.binding_sequence.mark();
+            compile_rule_selects(&mut sub_env, &conclusion.items, &mut conclusion_ops)?;
+            sub_ops.push(CfgOpSelect::Not {
+                body: conclusion_ops,
+                binding_mark: conclusion_mark,
+            });
             ops.push(CfgOpSelect::Not {
                 body: sub_ops,
                 binding_mark,
@@ -510,6 +824,9 @@ fn compile_rule_select(
             });
             Ok(())
         },
+        ast::RuleSelect::Membership(membership) => {
+            compile_select_membership(env, membership, ops)
+        },
     }
 }
 
@@ -523,6 +840,8 @@ fn compile_calculation(
             Ok(Calculation::Value(Value::from(*value))),
         ast::Calculation::Float(value) =>
             Ok(Calculation::Value(Value::from(*value))),
+        ast::Calculation::Symbol(ident) =>
+            Ok(Calculation::Value(Value::from(ident.as_str()))),
         ast::Calculation::Variable(variable) =>
             Ok(Calculation::Binding(existing_named_binding(env, variable, position)?)),
         ast::Calculation::BimOp(op, left, right) =>
@@ -531,7 +850,95 @@ fn compile_calculation(
                 Box::new(compile_calculation(env, position, left)?),
                 Box::new(compile_calculation(env, position, right)?),
             )),
+        ast::Calculation::Concat(left, right) =>
+            Ok(Calculation::Concat(
+                Box::new(compile_calculation(env, position, left)?),
+                Box::new(compile_calculation(env, position, right)?),
+            )),
+        ast::Calculation::Tuple(items) =>
+            Ok(Calculation::Tuple(compile_calculations(env, position, items)?)),
+        ast::Calculation::Member(item, tuple) =>
+            Ok(Calculation::Member(
+                Box::new(compile_calculation(env, position, item)?),
+                Box::new(compile_calculation(env, position, tuple)?),
+            )),
+        ast::Calculation::Index(tuple, index) =>
+            Ok(Calculation::Index(
+                Box::new(compile_calculation(env, position, tuple)?),
+                Box::new(compile_calculation(env, position, index)?),
+            )),
+        ast::Calculation::Slice(tuple, start, end) =>
+            Ok(Calculation::Slice(
+                Box::new(compile_calculation(env, position, tuple)?),
+                match start {
+                    Some(start) => Some(Box::new(compile_calculation(env, position, start)?)),
+                    None => None,
+                },
+                match end {
+                    Some(end) => Some(Box::new(compile_calculation(env, position, end)?)),
+                    None => None,
+                },
+            )),
+        ast::Calculation::Call(function, arguments) => {
+            let mut arguments = compile_calculations(env, position, arguments)?;
+            let invalid_argument_count = || CompileError::InvalidArgumentCount {
+                line: function.span.location_line(),
+                name: function.as_str().into(),
+            };
+            match function.as_str() {
+                "len" => {
+                    let argument = arguments.pop().ok_or_else(invalid_argument_count)?;
+                    if !arguments.is_empty() {
+                        return Err(invalid_argument_count());
+                    }
+                    Ok(Calculation::Len(Box::new(argument)))
+                },
+                "sym" => {
+                    if arguments.is_empty() {
+                        return Err(invalid_argument_count());
+                    }
+                    Ok(Calculation::Symbol(arguments))
+                },
+                _ => Err(CompileError::UnknownFunction {
+                    line: function.span.location_line(),
+                    name: function.as_str().into(),
+                }),
+            }
+        },
+    }
+}
+
+fn compile_calculations(
+    env: &mut Env,
+    position: &Span<'_>,
+    calculations: &[ast::Calculation<'_>],
+) -> Result<Vec<Calculation>, CompileError> {
+    calculations.iter()
+        .map(|calculation| compile_calculation(env, position, calculation))
+        .collect()
+}
+
+fn compile_select_membership(
+    env: &mut Env,
+    membership: &ast::Membership<'_>,
+    ops: &mut Vec<CfgOpSelect>,
+) -> Result<(), CompileError> {
+    let binding = existing_named_binding(env, &membership.tuple, &membership.position)?;
+    match &membership.item {
+        ast::Enumerable::Literal(literal) => {
+            ops.push(CfgOpSelect::RequireTupleMember {
+                binding,
+                value: literal.to_value(),
+            });
+        },
+        ast::Enumerable::Variable(variable) => {
+            ops.push(CfgOpSelect::TupleMemberBinding {
+                binding,
+                value_binding: nameable_binding(env, variable),
+            });
+        },
     }
+    Ok(())
 }
 
 fn compile_comparable(
@@ -548,6 +955,43 @@ fn compile_comparable(
     })
 }
 
+fn compile_select_optional(
+    env: &mut Env,
+    optional: &ast::OptionalSelect,
+    ops: &mut Vec<CfgOpSelect>,
+) -> Result<(), CompileError> {
+
+    let mut sub_ops = Vec::new();
+    let mut sub_env = env.clone();
+    let binding_mark = sub_env.binding_sequence.mark();
+    compile_rule_selects(&mut sub_env, &optional.selects.items, &mut sub_ops)?;
+
+    let mut defaults = Vec::new();
+    for default in &optional.defaults.items {
+        let line = default.position.location_line();
+        let name = match default.variable.as_str() {
+            Some(name) => name,
+            None => return Err(CompileError::IllegalWildcard { line }),
+        };
+        if env.visible_bindings.contains_key(name) {
+            return Err(CompileError::IllegalReuse { line, name: name.into() });
+        }
+        let binding = match sub_env.find(name) {
+            Some(binding) => binding,
+            None => return Err(CompileError::IllegalOptionalDefault { line, name: name.into() }),
+        };
+        env.visible_bindings.insert(name.into(), binding);
+        defaults.push((binding, default.value.to_value()));
+    }
+
+    ops.push(CfgOpSelect::Optional {
+        body: sub_ops,
+        defaults,
+        binding_mark,
+    });
+    Ok(())
+}
+
 fn compile_select_comparison(
     env: &mut Env,
     comparison: &ast::Comparison<'_>,
@@ -590,6 +1034,9 @@ fn compile_select_toplevel_binding(
             ops.push(CfgOpSelect::AssertObjectBinding { binding });
             compile_select_attributes(env, binding, attributes, position, ops)
         },
+        ast::ValueSpecKind::Calculation(_) => Err(CompileError::IllegalCalculation {
+            line: position.location_line(),
+        }),
         _ => Err(CompileError::IllegalBindingMatch {
             line: position.location_line(),
             name: variable_name,
@@ -604,6 +1051,7 @@ fn compile_select_tuple(
     ops: &mut Vec<CfgOpSelect>,
 ) -> Result<(), CompileError> {
     let mut cfg_tuple_items = Vec::new();
+    let mut has_rest = false;
     for ast::ValueSpec { position, kind } in items {
         match kind {
             ast::ValueSpecKind::Literal(literal) => {
@@ -635,6 +1083,27 @@ fn compile_select_tuple(
                 compile_select_attributes(env, item_binding, attributes, position, ops)?;
                 cfg_tuple_items.push(OpenTupleItem::Binding(item_binding));
             },
+            ast::ValueSpecKind::Rest(variable) => {
+                if has_rest {
+                    return Err(CompileError::MultipleTupleRests {
+                        line: position.location_line(),
+                    });
+                }
+                has_rest = true;
+                match optional_binding(env, variable) {
+                    Some(rest_binding) => {
+                        cfg_tuple_items.push(OpenTupleItem::RestBinding(rest_binding));
+                    },
+                    None => {
+                        cfg_tuple_items.push(OpenTupleItem::IgnoreRest);
+                    },
+                }
+            },
+            ast::ValueSpecKind::Calculation(_) => {
+                return Err(CompileError::IllegalCalculation {
+                    line: position.location_line(),
+                });
+            },
         }
     }
     ops.push(CfgOpSelect::TupleBinding {
@@ -651,29 +1120,57 @@ fn compile_select_attribute(
     position: &Span<'_>,
     ops: &mut Vec<CfgOpSelect>,
 ) -> Result<(), CompileError> {
-    let ast::AttributeSpec { attribute, value_spec, .. } = attribute;
+    let ast::AttributeSpec { path, attribute, closure, value_spec, .. } = attribute;
+    let mut binding = binding;
+    for step in path {
+        let value_binding = env.anon();
+        ops.push(CfgOpSelect::AttributeBinding {
+            binding,
+            attribute: step.as_str().into(),
+            value_binding,
+        });
+        ops.push(CfgOpSelect::AssertObjectBinding { binding: value_binding });
+        binding = value_binding;
+    }
+    if let Some(closure) = closure {
+        return compile_select_closure(env, binding, attribute, closure, value_spec, position, ops);
+    }
     match &value_spec.kind {
         ast::ValueSpecKind::Literal(literal) => {
-            ops.push(CfgOpSelect::RequireValueAttribute {
-                binding,
-                attribute: attribute.as_str().into(),
-                value: literal.to_value(),
-            });
+            match attribute {
+                ast::AttributeName::Fixed(attribute) => {
+                    ops.push(CfgOpSelect::RequireValueAttribute {
+                        binding,
+                        attribute: attribute.as_str().into(),
+                        value: literal.to_value(),
+                    });
+                },
+                ast::AttributeName::Variable(_) => {
+                    let value_binding = env.anon();
+                    select_attribute_binding(env, binding, attribute, value_binding, ops);
+                    ops.push(CfgOpSelect::CompareBinding {
+                        binding: value_binding,
+                        value: literal.to_value(),
+                    });
+                },
+            }
             Ok(())
         },
         ast::ValueSpecKind::Variable(variable) => {
-            match optional_binding(env, variable) {
-                Some(value_binding) => {
-                    ops.push(CfgOpSelect::AttributeBinding {
+            match (optional_binding(env, variable), attribute) {
+                (Some(value_binding), _) => {
+                    select_attribute_binding(env, binding, attribute, value_binding, ops);
+                },
+                (None, ast::AttributeName::Fixed(attribute)) => {
+                    ops.push(CfgOpSelect::RequireAttribute {
                         binding,
                         attribute: attribute.as_str().into(),
-                        value_binding,
                     });
                 },
-                None => {
-                    ops.push(CfgOpSelect::RequireAttribute {
+                (None, ast::AttributeName::Variable(attribute)) => {
+                    ops.push(CfgOpSelect::RequireVariableAttribute {
                         binding,
-                        attribute: attribute.as_str().into(),
+                        attribute_binding: nameable_binding(env, attribute),
                     });
                 },
             }
@@ -681,31 +1178,119 @@ fn compile_select_attribute(
         },
         ast::ValueSpecKind::Tuple(ast::Bindable { variable: direct, inner: items }) => {
             let value_binding = nameable_binding(env, direct);
-            ops.push(CfgOpSelect::AttributeBinding {
-                binding,
-                attribute: attribute.as_str().into(),
-                value_binding,
-            });
+            select_attribute_binding(env, binding, attribute, value_binding, ops);
             compile_select_tuple(env, value_binding, items, ops)
         },
         ast::ValueSpecKind::Enum(ast::Bindable { variable: direct, inner: options }) => {
             let value_binding = nameable_binding(env, direct);
-            ops.push(CfgOpSelect::AttributeBinding {
-                binding,
-                attribute: attribute.as_str().into(),
-                value_binding,
-            });
+            select_attribute_binding(env, binding, attribute, value_binding, ops);
             compile_select_enum(env, value_binding, options, position, ops)
         },
         ast::ValueSpecKind::Struct(ast::Bindable { variable: direct, inner: attributes }) => {
             let value_binding = nameable_binding(env, direct);
+            select_attribute_binding(env, binding, attribute, value_binding, ops);
+            ops.push(CfgOpSelect::AssertObjectBinding { binding: value_binding });
+            compile_select_attributes(env, value_binding, attributes, position, ops)
+        },
+        ast::ValueSpecKind::Rest(_) => Err(CompileError::IllegalTupleRest {
+            line: position.location_line(),
+        }),
+        ast::ValueSpecKind::Calculation(_) => Err(CompileError::IllegalCalculation {
+            line: position.location_line(),
+        }),
+    }
+}
+
+fn compile_select_closure(
+    env: &mut Env,
+    binding: Binding,
+    attribute: &ast::AttributeName<'_>,
+    closure: &ast::Closure<'_>,
+    value_spec: &ast::ValueSpec<'_>,
+    position: &Span<'_>,
+    ops: &mut Vec<CfgOpSelect>,
+) -> Result<(), CompileError> {
+    let illegal_closure = || CompileError::IllegalClosure {
+        line: position.location_line(),
+    };
+    let attribute = match attribute {
+        ast::AttributeName::Fixed(attribute) => attribute.as_str().into(),
+        ast::AttributeName::Variable(_) => return Err(illegal_closure()),
+    };
+    let max_depth = match closure.max_depth {
+        Some(max_depth) => Some(max_depth.to_usize().ok_or_else(illegal_closure)?),
+        None => None,
+    };
+    let value_binding = match &value_spec.kind {
+        ast::ValueSpecKind::Literal(_) => env.anon(),
+        ast::ValueSpecKind::Variable(variable) => nameable_binding(env, variable),
+        ast::ValueSpecKind::Tuple(ast::Bindable { variable, .. }) |
+        ast::ValueSpecKind::Enum(ast::Bindable { variable, .. }) |
+        ast::ValueSpecKind::Struct(ast::Bindable { variable, .. }) => {
+            nameable_binding(env, variable)
+        },
+        ast::ValueSpecKind::Rest(_) => return Err(CompileError::IllegalTupleRest {
+            line: position.location_line(),
+        }),
+        ast::ValueSpecKind::Calculation(_) => return Err(CompileError::IllegalCalculation {
+            line: position.location_line(),
+        }),
+    };
+    let depth_binding = closure.depth.as_ref()
+        .and_then(|variable| optional_binding(env, variable));
+    ops.push(CfgOpSelect::AttributeClosure {
+        binding,
+        attribute,
+        mode: closure.mode,
+        max_depth,
+        value_binding,
+        depth_binding,
+    });
+    match &value_spec.kind {
+        ast::ValueSpecKind::Literal(literal) => {
+            ops.push(CfgOpSelect::CompareBinding {
+                binding: value_binding,
+                value: literal.to_value(),
+            });
+            Ok(())
+        },
+        ast::ValueSpecKind::Tuple(ast::Bindable { inner: items, .. }) => {
+            compile_select_tuple(env, value_binding, items, ops)
+        },
+        ast::ValueSpecKind::Enum(ast::Bindable { inner: options, .. }) => {
+            compile_select_enum(env, value_binding, options, position, ops)
+        },
+        ast::ValueSpecKind::Struct(ast::Bindable { inner: attributes, .. }) => {
+            ops.push(CfgOpSelect::AssertObjectBinding { binding: value_binding });
+            compile_select_attributes(env, value_binding, attributes, position, ops)
+        },
+        ast::ValueSpecKind::Variable(_) |
+        ast::ValueSpecKind::Rest(_) |
+        ast::ValueSpecKind::Calculation(_) => Ok(()),
+    }
+}
+
+fn select_attribute_binding(
+    env: &mut Env,
+    binding: Binding,
+    attribute: &ast::AttributeName<'_>,
+    value_binding: Binding,
+    ops: &mut Vec<CfgOpSelect>,
+) {
+    match attribute {
+        ast::AttributeName::Fixed(attribute) => {
             ops.push(CfgOpSelect::AttributeBinding {
                 binding,
                 attribute: attribute.as_str().into(),
                 value_binding,
             });
-            ops.push(CfgOpSelect::AssertObjectBinding { binding: value_binding });
-            compile_select_attributes(env, value_binding, attributes, position, ops)
+        },
+        ast::AttributeName::Variable(attribute) => {
+            ops.push(CfgOpSelect::VariableAttributeBinding {
+                binding,
+                attribute_binding: nameable_binding(env, attribute),
+                value_binding,
+            });
         },
     }
 }
@@ -764,11 +1349,7 @@ fn optional_binding(
     env: &mut Env<'_>,
     variable: &ast::Variable<'_>,
 ) -> Option<Binding> {
-    if let Some(name) = variable.as_str() {
-        Some(env.bind(name))
-    } else {
-        None
-    }
+    variable.as_str().map(|name| env.bind(name))
 }
 
 fn nameable_binding(
@@ -787,7 +1368,7 @@ fn named_new_binding(
     position: &Span<'_>,
 ) -> Result<Binding, CompileError> {
     if let Some(name) = variable.as_str() {
-        if let Some(_) = env.find(name) {
+        if env.find(name).is_some() {
             Err(CompileError::IllegalReuse {
                 line: position.location_line(),
                 name: name.into(),
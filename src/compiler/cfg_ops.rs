@@ -1,4 +1,5 @@
 
+use std::sync::{Arc};
 use crate::{Value, Symbol};
 use crate::data::{CompareOp};
 use super::{
@@ -8,6 +9,9 @@ use super::{
     Calculation,
     CompareValue,
     RemovalMode,
+    DeletionMode,
+    SystemRunMode,
+    ClosureMode,
     ApplyTupleItem,
 };
 
@@ -42,10 +46,32 @@ pub enum CfgOpSelect {
         binding: Binding,
         attribute: Symbol,
     },
+    VariableAttributeBinding {
+        binding: Binding,
+        attribute_binding: Binding,
+        value_binding: Binding,
+    },
+    RequireVariableAttribute {
+        binding: Binding,
+        attribute_binding: Binding,
+    },
+    AttributeClosure {
+        binding: Binding,
+        attribute: Symbol,
+        mode: ClosureMode,
+        max_depth: Option<usize>,
+        value_binding: Binding,
+        depth_binding: Option<Binding>,
+    },
     Not {
         body: Vec<CfgOpSelect>,
         binding_mark: BindingMark,
     },
+    Optional {
+        body: Vec<CfgOpSelect>,
+        defaults: Vec<(Binding, Value)>,
+        binding_mark: BindingMark,
+    },
     Compare {
         operator: CompareOp,
         left: CompareValue,
@@ -55,6 +81,14 @@ pub enum CfgOpSelect {
         result_binding: Binding,
         operation: Calculation,
     },
+    RequireTupleMember {
+        binding: Binding,
+        value: Value,
+    },
+    TupleMemberBinding {
+        binding: Binding,
+        value_binding: Binding,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -62,6 +96,15 @@ pub enum OpenTupleItem {
     Ignore,
     Binding(Binding),
     Compare(Value),
+    IgnoreRest,
+    RestBinding(Binding),
+}
+
+impl OpenTupleItem {
+
+    pub fn is_rest(&self) -> bool {
+        matches!(self, Self::IgnoreRest | Self::RestBinding(_))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -73,6 +116,39 @@ pub enum CfgOpApply {
         binding: Binding,
         items: Vec<ApplyTupleItem>,
     },
+    Calculation {
+        binding: Binding,
+        operation: Calculation,
+    },
+    ClearAttributes {
+        binding: Binding,
+    },
+    ClearAttribute {
+        binding: Binding,
+        attribute: Symbol,
+    },
+    ClearVariableAttribute {
+        binding: Binding,
+        attribute_binding: Binding,
+    },
+    DeleteObject {
+        binding: Binding,
+        mode: DeletionMode,
+    },
+    EmitBinding {
+        binding: Binding,
+    },
+    EmitValue {
+        value: Value,
+    },
+    Halt {
+        reason: Option<ApplyTupleItem>,
+    },
+    RunSystem {
+        system: Arc<str>,
+        mode: SystemRunMode,
+        inputs: Vec<Binding>,
+    },
     AddBindingAttribute {
         binding: Binding,
         attribute: Symbol,
@@ -84,6 +160,21 @@ pub enum CfgOpApply {
         value_binding: Binding,
         mode: RemovalMode,
     },
+    ResolveAttribute {
+        binding: Binding,
+        attribute: Symbol,
+        value_binding: Binding,
+    },
+    SetBindingAttribute {
+        binding: Binding,
+        attribute: Symbol,
+        value_binding: Binding,
+    },
+    SetValueAttribute {
+        binding: Binding,
+        attribute: Symbol,
+        value: Value,
+    },
     AddValueAttribute {
         binding: Binding,
         attribute: Symbol,
@@ -95,9 +186,48 @@ pub enum CfgOpApply {
         value: Value,
         mode: RemovalMode,
     },
+    AddVariableBindingAttribute {
+        binding: Binding,
+        attribute_binding: Binding,
+        value_binding: Binding,
+    },
+    RemoveVariableBindingAttribute {
+        binding: Binding,
+        attribute_binding: Binding,
+        value_binding: Binding,
+        mode: RemovalMode,
+    },
+    AddVariableValueAttribute {
+        binding: Binding,
+        attribute_binding: Binding,
+        value: Value,
+    },
+    SetVariableBindingAttribute {
+        binding: Binding,
+        attribute_binding: Binding,
+        value_binding: Binding,
+    },
+    SetVariableValueAttribute {
+        binding: Binding,
+        attribute_binding: Binding,
+        value: Value,
+    },
+    RemoveVariableValueAttribute {
+        binding: Binding,
+        attribute_binding: Binding,
+        value: Value,
+        mode: RemovalMode,
+    },
     Conditional {
         condition: Vec<CfgOpSelect>,
         then_apply: Vec<CfgOpApply>,
         otherwise_apply: Vec<CfgOpApply>,
     },
+    Match {
+        branches: Vec<(Vec<CfgOpSelect>, Vec<CfgOpApply>)>,
+    },
+    ForEach {
+        selection: Vec<CfgOpSelect>,
+        apply: Vec<CfgOpApply>,
+    },
 }
@@ -0,0 +1,1017 @@
+use std::sync::{Arc};
+use std::collections::{HashMap};
+use crate::{parser, Value, Symbol};
+use crate::data::{ArithBinOp, CompareOp};
+use crate::format::{CalcPrecedence, compare_op, float};
+use super::cfg::{CfgRule};
+use super::cfg_ops::{CfgOpSelect, CfgOpApply, OpenTupleItem};
+use super::{
+    DecompileError,
+    EnumOption,
+    Calculation,
+    CompareValue,
+    ApplyTupleItem,
+    Binding,
+    RemovalMode,
+    DeletionMode,
+    SystemRunMode,
+    ClosureMode,
+};
+
+pub fn rule_source(
+    system_name: &str,
+    input_variables: &[Arc<str>],
+    rule: &CfgRule,
+) -> Result<String, DecompileError> {
+
+    let mut decompiler = Decompiler::new(rule.name.clone());
+    if !parser::is_path(&rule.name) {
+        return Err(decompiler.invalid_name(&rule.name));
+    }
+
+    let mut source = Source::text(&format!("rule {}:{} ", system_name, rule.name));
+    source.append(decompiler.select_block(&rule.select)?);
+    source.push_str(" do ");
+    source.append(decompiler.apply_block(&rule.apply)?);
+    source.push_str("\n");
+
+    decompiler.finish(source, input_variables)
+}
+
+#[derive(Debug, Clone)]
+enum Piece {
+    Text(String),
+    Use(Binding),
+    Def(Binding),
+    NamedDef(Binding),
+    Label(Binding),
+    Rest(Binding),
+    ClosureLimits(Option<usize>, Option<Binding>),
+}
+
+impl Piece {
+
+    fn binding(&self) -> Option<Binding> {
+        match *self {
+            Self::Text(_) | Self::ClosureLimits(_, None) => None,
+            Self::Use(binding)
+            | Self::Def(binding)
+            | Self::NamedDef(binding)
+            | Self::Label(binding)
+            | Self::Rest(binding)
+            | Self::ClosureLimits(_, Some(binding)) => Some(binding),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Source {
+    pieces: Vec<Piece>,
+}
+
+impl Source {
+
+    fn text(text: &str) -> Self {
+        let mut source = Self::default();
+        source.push_str(text);
+        source
+    }
+
+    fn piece(piece: Piece) -> Self {
+        Self { pieces: vec![piece] }
+    }
+
+    fn push_str(&mut self, text: &str) {
+        if let Some(Piece::Text(last)) = self.pieces.last_mut() {
+            last.push_str(text);
+        } else {
+            self.pieces.push(Piece::Text(text.into()));
+        }
+    }
+
+    fn push(&mut self, piece: Piece) {
+        self.pieces.push(piece);
+    }
+
+    fn append(&mut self, other: Source) {
+        for piece in other.pieces {
+            match piece {
+                Piece::Text(text) => self.push_str(&text),
+                piece => self.push(piece),
+            }
+        }
+    }
+}
+
+fn join(sources: Vec<Source>, separator: &str) -> Source {
+    let mut joined = Source::default();
+    for (index, source) in sources.into_iter().enumerate() {
+        if index > 0 {
+            joined.push_str(separator);
+        }
+        joined.append(source);
+    }
+    joined
+}
+
+fn block(items: Vec<Source>) -> Source {
+    if items.is_empty() {
+        Source::text("{}")
+    } else {
+        let mut source = Source::text("{ ");
+        source.append(join(items, ", "));
+        source.push_str(" }");
+        source
+    }
+}
+
+struct Statement {
+    source: Source,
+    following: Vec<Statement>,
+}
+
+impl Statement {
+
+    fn flatten_into(self, sources: &mut Vec<Source>) {
+        sources.push(self.source);
+        for statement in self.following {
+            statement.flatten_into(sources);
+        }
+    }
+}
+
+struct PendingObject {
+    depth: usize,
+    absorbing: bool,
+    attributes: Vec<Source>,
+    deferred: Vec<Statement>,
+}
+
+struct PendingTuple {
+    depth: usize,
+    items: Vec<ApplyTupleItem>,
+}
+
+#[derive(Clone)]
+struct AttributePath {
+    depth: usize,
+    root: Binding,
+    steps: Vec<Symbol>,
+}
+
+enum AttributeRef<'a> {
+    Fixed(&'a Symbol),
+    Variable(Binding),
+}
+
+enum ValueRef<'a> {
+    Value(&'a Value),
+    Binding(Binding),
+}
+
+enum Change<'a> {
+    Add(AttributeRef<'a>, ValueRef<'a>),
+    Set(AttributeRef<'a>, ValueRef<'a>),
+    Remove(AttributeRef<'a>, ValueRef<'a>, RemovalMode),
+    Clear(AttributeRef<'a>),
+    ClearAll,
+}
+
+struct Decompiler {
+    rule: Arc<str>,
+    depth: usize,
+    calculations: HashMap<Binding, Calculation>,
+    objects: HashMap<Binding, PendingObject>,
+    tuples: HashMap<Binding, PendingTuple>,
+    paths: HashMap<Binding, AttributePath>,
+    flushed: Vec<Statement>,
+}
+
+impl Decompiler {
+
+    fn new(rule: Arc<str>) -> Self {
+        Self {
+            rule,
+            depth: 0,
+            calculations: HashMap::new(),
+            objects: HashMap::new(),
+            tuples: HashMap::new(),
+            paths: HashMap::new(),
+            flushed: Vec::new(),
+        }
+    }
+
+    fn invalid_name(&self, name: &str) -> DecompileError {
+        DecompileError::InvalidName { rule: self.rule.clone(), name: name.into() }
+    }
+
+    fn unrepresentable(&self, value: &Value) -> DecompileError {
+        DecompileError::UnrepresentableValue { rule: self.rule.clone(), value: value.clone() }
+    }
+
+    fn unnamed(&self) -> DecompileError {
+        DecompileError::UnnamedValue { rule: self.rule.clone() }
+    }
+
+    fn finish(&self, source: Source, input_variables: &[Arc<str>]) -> Result<String, DecompileError> {
+
+        let mut counts = HashMap::new();
+        for binding in source.pieces.iter().filter_map(Piece::binding) {
+            *counts.entry(binding).or_insert(0usize) += 1;
+        }
+
+        let mut names = input_variables
+            .iter()
+            .enumerate()
+            .map(|(index, name)| (Binding::with_index(index), name.to_string()))
+            .collect::<HashMap<_, _>>();
+        let mut next_index = 0;
+        let mut name = |binding: Binding| {
+            names.entry(binding).or_insert_with(|| loop {
+                next_index += 1;
+                let candidate = format!("v{}", next_index);
+                if !input_variables.iter().any(|input| input.as_ref() == candidate) {
+                    break candidate;
+                }
+            }).clone()
+        };
+        let is_named = |binding: Binding| {
+            binding.index() < input_variables.len() || counts[&binding] > 1
+        };
+
+        let mut output = String::new();
+        for piece in &source.pieces {
+            match *piece {
+                Piece::Text(ref text) => output.push_str(text),
+                Piece::Use(binding) => {
+                    output.push('$');
+                    output.push_str(&name(binding));
+                },
+                Piece::NamedDef(binding) => {
+                    if !is_named(binding) {
+                        return Err(DecompileError::UnusedCalculation { rule: self.rule.clone() });
+                    }
+                    output.push('$');
+                    output.push_str(&name(binding));
+                },
+                Piece::Def(binding) => {
+                    output.push('$');
+                    if is_named(binding) {
+                        output.push_str(&name(binding));
+                    }
+                },
+                Piece::Label(binding) => {
+                    if is_named(binding) {
+                        output.push('$');
+                        output.push_str(&name(binding));
+                        output.push_str(" @ ");
+                    }
+                },
+                Piece::Rest(binding) => {
+                    output.push_str("..");
+                    if is_named(binding) {
+                        output.push('$');
+                        output.push_str(&name(binding));
+                    }
+                },
+                Piece::ClosureLimits(max_depth, depth) => {
+                    let depth = depth.filter(|depth| is_named(*depth)).map(&mut name);
+                    match (max_depth, depth) {
+                        (Some(max_depth), Some(depth)) =>
+                            output.push_str(&format!("({}, ${})", max_depth, depth)),
+                        (Some(max_depth), None) =>
+                            output.push_str(&format!("({})", max_depth)),
+                        (None, Some(depth)) =>
+                            output.push_str(&format!("(${})", depth)),
+                        (None, None) => (),
+                    }
+                },
+            }
+        }
+        Ok(output)
+    }
+
+    fn nested<T, F>(&mut self, callback: F) -> Result<T, DecompileError>
+    where
+        F: FnOnce(&mut Self) -> Result<T, DecompileError>,
+    {
+        self.depth += 1;
+        let result = callback(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn named(&self, binding: Binding) -> Result<Piece, DecompileError> {
+        if self.calculations.contains_key(&binding)
+            || self.objects.contains_key(&binding)
+            || self.tuples.contains_key(&binding)
+            || self.paths.contains_key(&binding)
+        {
+            Err(self.unnamed())
+        } else {
+            Ok(Piece::Use(binding))
+        }
+    }
+
+    fn symbol(&self, symbol: &Symbol) -> Result<Source, DecompileError> {
+        if parser::is_variable_ident(symbol) {
+            Ok(Source::text(symbol))
+        } else {
+            Err(self.invalid_name(symbol))
+        }
+    }
+
+    fn literal(&self, value: &Value) -> Result<String, DecompileError> {
+        match value {
+            Value::Int(value) => Ok(value.to_string()),
+            Value::Float(value) if value.is_finite() => Ok(float(*value)),
+            Value::Symbol(symbol) if parser::is_variable_ident(symbol) => Ok(symbol.to_string()),
+            Value::Symbol(symbol) if parser::is_reserved_ident(symbol) =>
+                Err(self.invalid_name(symbol)),
+            _ => Err(self.unrepresentable(value)),
+        }
+    }
+
+    fn value(&self, value: &Value) -> Result<String, DecompileError> {
+        match value {
+            Value::Tuple(items) => {
+                let items = items
+                    .iter()
+                    .map(|item| self.value(item))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(format!("[{}]", items.join(", ")))
+            },
+            _ => self.literal(value),
+        }
+    }
+
+    fn number(&self, value: &Value) -> Option<String> {
+        match value {
+            Value::Int(value) => Some(value.to_string()),
+            Value::Float(value) if value.is_finite() => Some(float(*value)),
+            _ => None,
+        }
+    }
+
+    fn select_block(&mut self, ops: &[CfgOpSelect]) -> Result<Source, DecompileError> {
+        let items = ops
+            .iter()
+            .map(|op| self.select(op))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(block(items))
+    }
+
+    fn select_attribute(
+        &self,
+        binding: Binding,
+        attribute: &Symbol,
+    ) -> Result<Source, DecompileError> {
+        let mut source = Source::piece(self.named(binding)?);
+        source.push_str(".");
+        source.append(self.symbol(attribute)?);
+        Ok(source)
+    }
+
+    fn select(&mut self, op: &CfgOpSelect) -> Result<Source, DecompileError> {
+        let mut source = Source::default();
+        match op {
+            CfgOpSelect::AssertObjectBinding { binding } => {
+                source.push(self.named(*binding)?);
+                source.push_str(": {}");
+            },
+            CfgOpSelect::CompareBinding { binding, value } => {
+                source.push(self.named(*binding)?);
+                source.push_str(&format!(": {}", self.value(value)?));
+            },
+            CfgOpSelect::TupleBinding { binding, values } => {
+                source.push(self.named(*binding)?);
+                let mut items = Vec::new();
+                for item in values {
+                    items.push(match item {
+                        OpenTupleItem::Ignore => Source::text("$"),
+                        OpenTupleItem::Binding(binding) => Source::piece(Piece::Def(*binding)),
+                        OpenTupleItem::Compare(value) => Source::text(&self.value(value)?),
+                        OpenTupleItem::IgnoreRest => Source::text(".."),
+                        OpenTupleItem::RestBinding(binding) => Source::piece(Piece::Rest(*binding)),
+                    });
+                }
+                source.push_str(": [");
+                source.append(join(items, ", "));
+                source.push_str("]");
+            },
+            CfgOpSelect::EnumBinding { binding, options } => {
+                source.push(self.named(*binding)?);
+                let mut items = Vec::new();
+                for option in options {
+                    items.push(match option {
+                        EnumOption::Value(value) => Source::text(&self.literal(value)?),
+                        EnumOption::Binding(binding) => Source::piece(self.named(*binding)?),
+                    });
+                }
+                source.push_str(": ");
+                source.append(join(items, " | "));
+            },
+            CfgOpSelect::RequireValueAttribute { binding, attribute, value } => {
+                source.append(self.select_attribute(*binding, attribute)?);
+                source.push_str(&format!(": {}", self.value(value)?));
+            },
+            CfgOpSelect::AttributeBinding { binding, attribute, value_binding } => {
+                source.append(self.select_attribute(*binding, attribute)?);
+                source.push_str(": ");
+                source.push(Piece::Def(*value_binding));
+            },
+            CfgOpSelect::RequireAttribute { binding, attribute } => {
+                source.append(self.select_attribute(*binding, attribute)?);
+                source.push_str(": $");
+            },
+            CfgOpSelect::VariableAttributeBinding { binding, attribute_binding, value_binding } => {
+                source.push(self.named(*binding)?);
+                source.push_str(".");
+                source.push(Piece::Def(*attribute_binding));
+                source.push_str(": ");
+                source.push(Piece::Def(*value_binding));
+            },
+            CfgOpSelect::RequireVariableAttribute { binding, attribute_binding } => {
+                source.push(self.named(*binding)?);
+                source.push_str(".");
+                source.push(Piece::Def(*attribute_binding));
+                source.push_str(": $");
+            },
+            CfgOpSelect::AttributeClosure {
+                binding,
+                attribute,
+                mode,
+                max_depth,
+                value_binding,
+                depth_binding,
+            } => {
+                source.append(self.select_attribute(*binding, attribute)?);
+                source.push_str(match mode {
+                    ClosureMode::OneOrMore => "+",
+                    ClosureMode::ZeroOrMore => "*",
+                });
+                source.push(Piece::ClosureLimits(*max_depth, *depth_binding));
+                source.push_str(": ");
+                source.push(Piece::Def(*value_binding));
+            },
+            CfgOpSelect::Not { body, .. } => {
+                source.push_str("not ");
+                source.append(self.select_block(body)?);
+            },
+            CfgOpSelect::Optional { body, defaults, .. } => {
+                source.push_str("optional ");
+                source.append(self.select_block(body)?);
+                let mut items = Vec::new();
+                for (binding, value) in defaults {
+                    let mut item = Source::piece(Piece::NamedDef(*binding));
+                    item.push_str(&format!(": {}", self.literal(value)?));
+                    items.push(item);
+                }
+                source.push_str(" else ");
+                source.append(block(items));
+            },
+            CfgOpSelect::Compare { operator, left, right } => {
+                source.append(self.compare(*operator, left, right)?);
+            },
+            CfgOpSelect::Calculation { result_binding, operation } => {
+                source.push(Piece::NamedDef(*result_binding));
+                source.push_str(" is ");
+                source.append(self.calculation(operation, CalcPrecedence::Member)?);
+            },
+            CfgOpSelect::RequireTupleMember { binding, value } => {
+                source.push_str(&format!("{} in ", self.literal(value)?));
+                source.push(self.named(*binding)?);
+            },
+            CfgOpSelect::TupleMemberBinding { binding, value_binding } => {
+                source.push(Piece::Def(*value_binding));
+                source.push_str(" in ");
+                source.push(self.named(*binding)?);
+            },
+        }
+        Ok(source)
+    }
+
+    fn compare(
+        &self,
+        operator: CompareOp,
+        left: &CompareValue,
+        right: &CompareValue,
+    ) -> Result<Source, DecompileError> {
+        let comparable = |value: &CompareValue| match value {
+            CompareValue::Binding(binding) => self.named(*binding).map(Source::piece).map(Some),
+            CompareValue::Value(value) => Ok(self.number(value).map(|number| Source::text(&number))),
+        };
+        if let (Some(mut source), Some(right)) = (comparable(left)?, comparable(right)?) {
+            source.push_str(&format!(" {} ", compare_op(operator)));
+            source.append(right);
+            return Ok(source);
+        }
+
+        let (binding, value) = match (left, right) {
+            (CompareValue::Binding(binding), CompareValue::Value(value))
+            | (CompareValue::Value(value), CompareValue::Binding(binding)) => (*binding, value),
+            (CompareValue::Value(value), CompareValue::Value(_)) => {
+                return Err(self.unrepresentable(value));
+            },
+            (CompareValue::Binding(_), CompareValue::Binding(_)) => {
+                unreachable!("binding comparisons are always representable")
+            },
+        };
+        let mut equality = Source::piece(self.named(binding)?);
+        equality.push_str(&format!(": {}", self.value(value)?));
+        match operator {
+            CompareOp::Equal => Ok(equality),
+            CompareOp::NotEqual => Ok(block_with_prefix("not ", vec![equality])),
+            _ => Err(self.unrepresentable(value)),
+        }
+    }
+
+    fn calculation_precedence(&self, calculation: &Calculation) -> CalcPrecedence {
+        match calculation {
+            Calculation::Binding(binding) => match self.calculations.get(binding) {
+                Some(inner) => self.calculation_precedence(inner),
+                None => CalcPrecedence::Terminal,
+            },
+            Calculation::Member(_, _) => CalcPrecedence::Member,
+            Calculation::Concat(_, _) => CalcPrecedence::Concat,
+            Calculation::BinOp(ArithBinOp::Add, _, _) |
+            Calculation::BinOp(ArithBinOp::Sub, _, _) => CalcPrecedence::AddSub,
+            Calculation::BinOp(ArithBinOp::Mul, _, _) |
+            Calculation::BinOp(ArithBinOp::Div, _, _) => CalcPrecedence::MulDiv,
+            Calculation::Index(_, _) |
+            Calculation::Slice(_, _, _) => CalcPrecedence::Postfix,
+            Calculation::Value(_) |
+            Calculation::Tuple(_) |
+            Calculation::Len(_) |
+            Calculation::Symbol(_) => CalcPrecedence::Terminal,
+        }
+    }
+
+    fn calculations(&self, calculations: &[Calculation]) -> Result<Source, DecompileError> {
+        let items = calculations
+            .iter()
+            .map(|item| self.calculation(item, CalcPrecedence::Member))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(join(items, ", "))
+    }
+
+    fn calculation(
+        &self,
+        calculation: &Calculation,
+        required: CalcPrecedence,
+    ) -> Result<Source, DecompileError> {
+        let mut source = Source::default();
+        match calculation {
+            Calculation::Value(value) => source.push_str(&self.value(value)?),
+            Calculation::Binding(binding) => match self.calculations.get(binding) {
+                Some(inner) => return self.calculation(inner, required),
+                None => source.push(self.named(*binding)?),
+            },
+            Calculation::Tuple(items) => {
+                source.push_str("[");
+                source.append(self.calculations(items)?);
+                source.push_str("]");
+            },
+            Calculation::Len(inner) => {
+                source.push_str("len(");
+                source.append(self.calculation(inner, CalcPrecedence::Member)?);
+                source.push_str(")");
+            },
+            Calculation::Symbol(parts) => {
+                source.push_str("sym(");
+                source.append(self.calculations(parts)?);
+                source.push_str(")");
+            },
+            Calculation::Index(value, index) => {
+                source.append(self.calculation(value, CalcPrecedence::Postfix)?);
+                source.push_str("[");
+                source.append(self.calculation(index, CalcPrecedence::Member)?);
+                source.push_str("]");
+            },
+            Calculation::Slice(value, start, end) => {
+                source.append(self.calculation(value, CalcPrecedence::Postfix)?);
+                source.push_str("[");
+                if let Some(start) = start {
+                    source.append(self.calculation(start, CalcPrecedence::Member)?);
+                }
+                source.push_str("..");
+                if let Some(end) = end {
+                    source.append(self.calculation(end, CalcPrecedence::Member)?);
+                }
+                source.push_str("]");
+            },
+            Calculation::BinOp(op, left, right) => {
+                let (operator, precedence) = match op {
+                    ArithBinOp::Add => ("+", CalcPrecedence::AddSub),
+                    ArithBinOp::Sub => ("-", CalcPrecedence::AddSub),
+                    ArithBinOp::Mul => ("*", CalcPrecedence::MulDiv),
+                    ArithBinOp::Div => ("/", CalcPrecedence::MulDiv),
+                };
+                let right_precedence = match precedence {
+                    CalcPrecedence::AddSub => CalcPrecedence::MulDiv,
+                    _ => CalcPrecedence::Postfix,
+                };
+                source.append(self.calculation(left, precedence)?);
+                source.push_str(&format!(" {} ", operator));
+                source.append(self.calculation(right, right_precedence)?);
+            },
+            Calculation::Concat(left, right) => {
+                source.append(self.calculation(left, CalcPrecedence::Concat)?);
+                source.push_str(" ++ ");
+                source.append(self.calculation(right, CalcPrecedence::AddSub)?);
+            },
+            Calculation::Member(item, tuple) => {
+                source.append(self.calculation(item, CalcPrecedence::Concat)?);
+                source.push_str(" in ");
+                source.append(self.calculation(tuple, CalcPrecedence::Concat)?);
+            },
+        }
+        if self.calculation_precedence(calculation) < required {
+            let mut wrapped = Source::text("(");
+            wrapped.append(source);
+            wrapped.push_str(")");
+            Ok(wrapped)
+        } else {
+            Ok(source)
+        }
+    }
+
+    fn apply_block(&mut self, ops: &[CfgOpApply]) -> Result<Source, DecompileError> {
+        let mut statements = Vec::new();
+        for op in ops {
+            self.apply(op, &mut statements)?;
+        }
+
+        let depth = self.depth;
+        if self.objects.values().any(|object| object.depth == depth) {
+            return Err(DecompileError::DetachedObject { rule: self.rule.clone() });
+        }
+        self.tuples.retain(|_, tuple| tuple.depth != depth);
+        self.paths.retain(|_, path| path.depth != depth);
+
+        let mut items = Vec::new();
+        for statement in statements {
+            statement.flatten_into(&mut items);
+        }
+        Ok(block(items))
+    }
+
+    fn statement(&mut self, source: Source) -> Statement {
+        Statement { source, following: std::mem::take(&mut self.flushed) }
+    }
+
+    fn apply(
+        &mut self,
+        op: &CfgOpApply,
+        statements: &mut Vec<Statement>,
+    ) -> Result<(), DecompileError> {
+        use AttributeRef::{Fixed, Variable};
+
+        match op {
+            CfgOpApply::CreateObject { binding } => {
+                self.objects.insert(*binding, PendingObject {
+                    depth: self.depth,
+                    absorbing: true,
+                    attributes: Vec::new(),
+                    deferred: Vec::new(),
+                });
+            },
+            CfgOpApply::CreateTuple { binding, items } => {
+                self.tuples.insert(*binding, PendingTuple {
+                    depth: self.depth,
+                    items: items.clone(),
+                });
+            },
+            CfgOpApply::Calculation { binding, operation } => {
+                self.calculations.insert(*binding, operation.clone());
+            },
+            CfgOpApply::ResolveAttribute { binding, attribute, value_binding } => {
+                let mut path = match self.paths.get(binding) {
+                    Some(path) => path.clone(),
+                    None => AttributePath { depth: self.depth, root: *binding, steps: Vec::new() },
+                };
+                path.steps.push(attribute.clone());
+                if let Some(object) = self.objects.get_mut(&path.root) {
+                    object.absorbing = false;
+                }
+                self.paths.insert(*value_binding, path);
+            },
+            CfgOpApply::ClearAttributes { binding } =>
+                self.change(statements, *binding, Change::ClearAll)?,
+            CfgOpApply::ClearAttribute { binding, attribute } =>
+                self.change(statements, *binding, Change::Clear(Fixed(attribute)))?,
+            CfgOpApply::ClearVariableAttribute { binding, attribute_binding } =>
+                self.change(statements, *binding, Change::Clear(Variable(*attribute_binding)))?,
+            CfgOpApply::AddBindingAttribute { binding, attribute, value_binding } =>
+                self.change(statements, *binding, Change::Add(
+                    Fixed(attribute),
+                    ValueRef::Binding(*value_binding),
+                ))?,
+            CfgOpApply::AddValueAttribute { binding, attribute, value } =>
+                self.change(statements, *binding, Change::Add(Fixed(attribute), ValueRef::Value(value)))?,
+            CfgOpApply::AddVariableBindingAttribute { binding, attribute_binding, value_binding } =>
+                self.change(statements, *binding, Change::Add(
+                    Variable(*attribute_binding),
+                    ValueRef::Binding(*value_binding),
+                ))?,
+            CfgOpApply::AddVariableValueAttribute { binding, attribute_binding, value } =>
+                self.change(statements, *binding, Change::Add(
+                    Variable(*attribute_binding),
+                    ValueRef::Value(value),
+                ))?,
+            CfgOpApply::SetBindingAttribute { binding, attribute, value_binding } =>
+                self.change(statements, *binding, Change::Set(
+                    Fixed(attribute),
+                    ValueRef::Binding(*value_binding),
+                ))?,
+            CfgOpApply::SetValueAttribute { binding, attribute, value } =>
+                self.change(statements, *binding, Change::Set(Fixed(attribute), ValueRef::Value(value)))?,
+            CfgOpApply::SetVariableBindingAttribute { binding, attribute_binding, value_binding } =>
+                self.change(statements, *binding, Change::Set(
+                    Variable(*attribute_binding),
+                    ValueRef::Binding(*value_binding),
+                ))?,
+            CfgOpApply::SetVariableValueAttribute { binding, attribute_binding, value } =>
+                self.change(statements, *binding, Change::Set(
+                    Variable(*attribute_binding),
+                    ValueRef::Value(value),
+                ))?,
+            CfgOpApply::RemoveBindingAttribute { binding, attribute, value_binding, mode } =>
+                self.change(statements, *binding, Change::Remove(
+                    Fixed(attribute),
+                    ValueRef::Binding(*value_binding),
+                    *mode,
+                ))?,
+            CfgOpApply::RemoveValueAttribute { binding, attribute, value, mode } =>
+                self.change(statements, *binding, Change::Remove(
+                    Fixed(attribute),
+                    ValueRef::Value(value),
+                    *mode,
+                ))?,
+            CfgOpApply::RemoveVariableBindingAttribute {
+                binding,
+                attribute_binding,
+                value_binding,
+                mode,
+            } =>
+                self.change(statements, *binding, Change::Remove(
+                    Variable(*attribute_binding),
+                    ValueRef::Binding(*value_binding),
+                    *mode,
+                ))?,
+            CfgOpApply::RemoveVariableValueAttribute { binding, attribute_binding, value, mode } =>
+                self.change(statements, *binding, Change::Remove(
+                    Variable(*attribute_binding),
+                    ValueRef::Value(value),
+                    *mode,
+                ))?,
+            CfgOpApply::DeleteObject { binding, mode } => {
+                let mut source = Source::text(match mode {
+                    DeletionMode::Object => "delete ",
+                    DeletionMode::WithReferences => "purge ",
+                });
+                source.push(self.named(*binding)?);
+                statements.push(self.statement(source));
+            },
+            CfgOpApply::EmitBinding { binding } => {
+                let mut source = Source::text("emit ");
+                source.append(self.value_binding(*binding, true)?);
+                statements.push(self.statement(source));
+            },
+            CfgOpApply::EmitValue { value } => {
+                let source = Source::text(&format!("emit {}", self.value(value)?));
+                statements.push(self.statement(source));
+            },
+            CfgOpApply::Halt { reason } => {
+                let mut source = Source::text("halt");
+                match reason {
+                    Some(ApplyTupleItem::Value(value)) => {
+                        source.push_str(&format!(" {}", self.value(value)?));
+                    },
+                    Some(ApplyTupleItem::Binding(binding)) => {
+                        source.push_str(" ");
+                        source.append(self.value_binding(*binding, true)?);
+                    },
+                    None => (),
+                }
+                statements.push(self.statement(source));
+            },
+            CfgOpApply::RunSystem { system, mode, inputs } => {
+                if !parser::is_path(system) {
+                    return Err(self.invalid_name(system));
+                }
+                let inputs = inputs
+                    .iter()
+                    .map(|input| self.named(*input).map(Source::piece))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mut source = Source::text(match mode {
+                    SystemRunMode::ToFirst => "run first ",
+                    SystemRunMode::Saturation => "run ",
+                });
+                source.push_str(&format!("{}(", system));
+                source.append(join(inputs, ", "));
+                source.push_str(")");
+                statements.push(self.statement(source));
+            },
+            CfgOpApply::Conditional { condition, then_apply, otherwise_apply } => {
+                let mut source = Source::text("if ");
+                source.append(self.select_block(condition)?);
+                source.push_str(" then ");
+                source.append(self.nested(|this| this.apply_block(then_apply))?);
+                if !otherwise_apply.is_empty() {
+                    source.push_str(" else ");
+                    source.append(self.nested(|this| this.apply_block(otherwise_apply))?);
+                }
+                statements.push(self.statement(source));
+            },
+            CfgOpApply::Match { branches } => {
+                let mut items = Vec::new();
+                for (condition, apply) in branches {
+                    let mut item = if condition.is_empty() {
+                        Source::text("_")
+                    } else {
+                        self.select_block(condition)?
+                    };
+                    item.push_str(" => ");
+                    item.append(self.nested(|this| this.apply_block(apply))?);
+                    items.push(item);
+                }
+                let source = block_with_prefix("match ", items);
+                statements.push(self.statement(source));
+            },
+            CfgOpApply::ForEach { selection, apply } => {
+                let mut source = Source::text("for ");
+                source.append(self.select_block(selection)?);
+                source.push_str(" do ");
+                source.append(self.nested(|this| this.apply_block(apply))?);
+                statements.push(self.statement(source));
+            },
+        }
+        Ok(())
+    }
+
+    fn change(
+        &mut self,
+        statements: &mut Vec<Statement>,
+        binding: Binding,
+        change: Change<'_>,
+    ) -> Result<(), DecompileError> {
+
+        let (root, steps) = match self.paths.get(&binding) {
+            Some(path) if path.depth == self.depth => (path.root, path.steps.clone()),
+            Some(_) => return Err(self.unnamed()),
+            None => (binding, Vec::new()),
+        };
+        let is_pending = match self.objects.get(&root) {
+            Some(object) if object.depth == self.depth => true,
+            Some(_) => return Err(self.unnamed()),
+            None => {
+                self.named(root)?;
+                false
+            },
+        };
+        let is_absorbed = is_pending
+            && steps.is_empty()
+            && matches!(change, Change::Add(_, _))
+            && self.objects[&root].absorbing;
+
+        let mut target = Source::piece(Piece::Use(root));
+        for step in &steps {
+            target.push_str(".");
+            target.append(self.symbol(step)?);
+        }
+
+        let source = match change {
+            Change::Add(attribute, value) if is_absorbed => {
+                let mut source = self.attribute(&attribute)?;
+                source.push_str(": ");
+                source.append(self.value_ref(&value, true)?);
+                source
+            },
+            Change::Add(attribute, value) => {
+                self.attribute_change("+ ", target, &attribute, &value, true)?
+            },
+            Change::Set(attribute, value) => {
+                self.attribute_change("= ", target, &attribute, &value, true)?
+            },
+            Change::Remove(attribute, value, mode) => {
+                let operator = match mode {
+                    RemovalMode::Required => "- ",
+                    RemovalMode::Optional => "! ",
+                };
+                self.attribute_change(operator, target, &attribute, &value, false)?
+            },
+            Change::Clear(attribute) => {
+                let mut source = Source::text("clear ");
+                source.append(target);
+                source.push_str(".");
+                source.append(self.attribute(&attribute)?);
+                source
+            },
+            Change::ClearAll => {
+                if !steps.is_empty() {
+                    return Err(self.unnamed());
+                }
+                let mut source = Source::text("clear ");
+                source.append(target);
+                source
+            },
+        };
+
+        let statement = self.statement(source);
+        if is_absorbed {
+            let object = self.objects.get_mut(&root).expect("pending object");
+            object.attributes.push(statement.source);
+            object.deferred.extend(statement.following);
+        } else if is_pending {
+            let object = self.objects.get_mut(&root).expect("pending object");
+            object.absorbing = false;
+            object.deferred.push(statement);
+        } else {
+            statements.push(statement);
+        }
+        Ok(())
+    }
+
+    fn attribute(&self, attribute: &AttributeRef<'_>) -> Result<Source, DecompileError> {
+        match *attribute {
+            AttributeRef::Fixed(attribute) => self.symbol(attribute),
+            AttributeRef::Variable(binding) => Ok(Source::piece(self.named(binding)?)),
+        }
+    }
+
+    fn attribute_change(
+        &mut self,
+        operator: &str,
+        target: Source,
+        attribute: &AttributeRef<'_>,
+        value: &ValueRef<'_>,
+        allow_objects: bool,
+    ) -> Result<Source, DecompileError> {
+        let mut source = Source::text(operator);
+        source.append(target);
+        source.push_str(".");
+        source.append(self.attribute(attribute)?);
+        source.push_str(": ");
+        source.append(self.value_ref(value, allow_objects)?);
+        Ok(source)
+    }
+
+    fn value_ref(
+        &mut self,
+        value: &ValueRef<'_>,
+        allow_objects: bool,
+    ) -> Result<Source, DecompileError> {
+        match *value {
+            ValueRef::Value(value) => Ok(Source::text(&self.value(value)?)),
+            ValueRef::Binding(binding) => self.value_binding(binding, allow_objects),
+        }
+    }
+
+    fn value_binding(
+        &mut self,
+        binding: Binding,
+        allow_objects: bool,
+    ) -> Result<Source, DecompileError> {
+        if let Some(calculation) = self.calculations.get(&binding) {
+            return self.calculation(calculation, CalcPrecedence::Member);
+        }
+
+        let depth = self.depth;
+        if matches!(self.tuples.get(&binding), Some(tuple) if tuple.depth == depth) {
+            let tuple = self.tuples.remove(&binding).expect("pending tuple");
+            let mut items = Vec::new();
+            for item in &tuple.items {
+                items.push(match *item {
+                    ApplyTupleItem::Value(ref value) => Source::text(&self.value(value)?),
+                    ApplyTupleItem::Binding(item) => self.value_binding(item, allow_objects)?,
+                });
+            }
+            let mut source = Source::piece(Piece::Label(binding));
+            source.push_str("[");
+            source.append(join(items, ", "));
+            source.push_str("]");
+            return Ok(source);
+        }
+
+        let is_pending_object = matches!(
+            self.objects.get(&binding),
+            Some(object) if object.depth == depth
+        );
+        if allow_objects && is_pending_object {
+            let object = self.objects.remove(&binding).expect("pending object");
+            self.flushed.extend(object.deferred);
+            let mut source = Source::piece(Piece::Label(binding));
+            source.append(block(object.attributes));
+            return Ok(source);
+        }
+
+        Ok(Source::piece(self.named(binding)?))
+    }
+}
+
+fn block_with_prefix(prefix: &str, items: Vec<Source>) -> Source {
+    let mut source = Source::text(prefix);
+    source.append(block(items));
+    source
+}
@@ -0,0 +1,542 @@
+use std::sync::{Arc};
+use std::fmt::{Write};
+use crate::{Value};
+use crate::data::{ArithBinOp};
+use crate::format::{CalcPrecedence, compare_op};
+use super::ops::{Op, OpApply, TupleItem, AttributeClosure, ClosureDepth};
+use super::optimizer::{SelectPlan, Elimination};
+use super::{
+    CompiledRule,
+    Binding,
+    Calculation,
+    CompareValue,
+    EnumOption,
+    ApplyTupleItem,
+    ClosureMode,
+    RemovalMode,
+    DeletionMode,
+    SystemRunMode,
+};
+
+pub fn explain_rule(rule: &CompiledRule, input_variables: &[Arc<str>]) -> String {
+    let mut explainer = Explainer {
+        rule,
+        input_variables,
+        plans: rule.plans.iter(),
+        output: String::new(),
+    };
+    explainer.line(0, &format!("rule {}", rule.name));
+    explainer.select(1, "select", &rule.ops);
+    if !rule.apply_ops.is_empty() {
+        explainer.apply(1, "apply", &rule.apply_ops);
+    }
+    explainer.output
+}
+
+struct Explainer<'a> {
+    rule: &'a CompiledRule,
+    input_variables: &'a [Arc<str>],
+    plans: std::slice::Iter<'a, SelectPlan>,
+    output: String,
+}
+
+impl Explainer<'_> {
+
+    fn line(&mut self, depth: usize, text: &str) {
+        for _ in 0..depth {
+            self.output.push_str("  ");
+        }
+        self.output.push_str(text);
+        self.output.push('\n');
+    }
+
+    fn select(&mut self, depth: usize, label: &str, ops: &[Op]) {
+        let plan = self.plans.next().expect("select plan for every op sequence");
+        assert_eq!(plan.costs.len(), ops.len(), "one estimated cost per op");
+
+        let total = plan.costs.last().copied().unwrap_or_default();
+        self.line(depth, &format!("{}, estimated cost {:.1}:", label, total));
+        let mut nesting = 0;
+        for (index, (op, cost)) in ops.iter().zip(plan.costs.iter()).enumerate() {
+            if let Op::EndNot { .. } | Op::EndOptional { .. } = op {
+                nesting -= 1;
+            }
+            let text = format!(
+                "{:>3} {:>10.1}  {}{}",
+                index,
+                cost,
+                "  ".repeat(nesting),
+                self.op(op),
+            );
+            self.line(depth + 1, &text);
+            if let Op::BeginNot { .. } | Op::BeginOptional { .. } = op {
+                nesting += 1;
+            }
+        }
+
+        if !plan.eliminated.is_empty() {
+            self.line(depth + 1, "eliminated:");
+            for elimination in &plan.eliminated {
+                let text = match *elimination {
+                    Elimination::ImpliedObject(binding) => format!(
+                        "assert {} is object, implied by attribute access",
+                        self.name(binding),
+                    ),
+                    Elimination::RepeatedObject(binding) => format!(
+                        "assert {} is object, already asserted",
+                        self.name(binding),
+                    ),
+                };
+                self.line(depth + 2, &text);
+            }
+        }
+    }
+
+    fn apply(&mut self, depth: usize, label: &str, ops: &[OpApply]) {
+        self.line(depth, &format!("{}:", label));
+        for op in ops {
+            match op {
+                OpApply::Conditional { condition, then_apply, otherwise_apply } => {
+                    self.line(depth + 1, "conditional");
+                    self.select(depth + 2, "condition", condition);
+                    self.apply(depth + 2, "then", then_apply);
+                    if !otherwise_apply.is_empty() {
+                        self.apply(depth + 2, "otherwise", otherwise_apply);
+                    }
+                },
+                OpApply::Match { branches } => {
+                    self.line(depth + 1, "match");
+                    for (index, (condition, apply)) in branches.iter().enumerate() {
+                        self.select(depth + 2, &format!("branch {}", index + 1), condition);
+                        self.apply(depth + 2, "then", apply);
+                    }
+                },
+                OpApply::ForEach { selection, apply } => {
+                    self.line(depth + 1, "for each");
+                    self.select(depth + 2, "selection", selection);
+                    self.apply(depth + 2, "apply", apply);
+                },
+                _ => {
+                    let text = self.apply_op(op);
+                    self.line(depth + 1, &text);
+                },
+            }
+        }
+    }
+
+    fn op(&self, op: &Op) -> String {
+        match op {
+            Op::End => "end".into(),
+            Op::BeginNot { .. } => "not".into(),
+            Op::EndNot { .. } => "end not".into(),
+            Op::BeginOptional { defaults, .. } => {
+                let defaults = defaults.iter()
+                    .map(|(binding, value)| {
+                        format!("{} = {}", self.name(*binding), value_text(value))
+                    })
+                    .collect::<Vec<_>>();
+                if defaults.is_empty() {
+                    "optional".into()
+                } else {
+                    format!("optional, defaults {}", defaults.join(", "))
+                }
+            },
+            Op::EndOptional { .. } => "end optional".into(),
+            Op::SearchAttributeBinding { binding, attribute, value_binding } => format!(
+                "search {}.{} -> {}",
+                self.name(*binding),
+                attribute,
+                self.name(*value_binding),
+            ),
+            Op::RequireAttributeBinding { binding, attribute, value_binding } => format!(
+                "require {}.{} == {}",
+                self.name(*binding),
+                attribute,
+                self.name(*value_binding),
+            ),
+            Op::RequireAttributeValue { binding, attribute, value } =>
+                format!("require {}.{} == {}", self.name(*binding), attribute, value_text(value)),
+            Op::RequireAttribute { binding, attribute } =>
+                format!("require {}.{}", self.name(*binding), attribute),
+            Op::SearchAttributeNames { binding, attribute_binding } => format!(
+                "search attribute names of {} -> {}",
+                self.name(*binding),
+                self.name(*attribute_binding),
+            ),
+            Op::SearchAttributeNamesWithValue { binding, attribute_binding, value_binding } =>
+                format!(
+                    "search attribute names of {} with value {} -> {}",
+                    self.name(*binding),
+                    self.name(*value_binding),
+                    self.name(*attribute_binding),
+                ),
+            Op::SearchAttributePairs { binding, attribute_binding, value_binding } => format!(
+                "search attributes of {} -> {}, {}",
+                self.name(*binding),
+                self.name(*attribute_binding),
+                self.name(*value_binding),
+            ),
+            Op::SearchVariableAttributeBinding { binding, attribute_binding, value_binding } =>
+                format!(
+                    "search {}.{} -> {}",
+                    self.name(*binding),
+                    self.name(*attribute_binding),
+                    self.name(*value_binding),
+                ),
+            Op::RequireVariableAttributeBinding { binding, attribute_binding, value_binding } =>
+                format!(
+                    "require {}.{} == {}",
+                    self.name(*binding),
+                    self.name(*attribute_binding),
+                    self.name(*value_binding),
+                ),
+            Op::RequireVariableAttribute { binding, attribute_binding } => format!(
+                "require {}.{}",
+                self.name(*binding),
+                self.name(*attribute_binding),
+            ),
+            Op::SearchAttributeClosure { closure } =>
+                format!("search {}", self.closure(closure, "->")),
+            Op::RequireAttributeClosure { closure } =>
+                format!("require {}", self.closure(closure, "==")),
+            Op::AssertObjectBinding { binding } =>
+                format!("assert {} is object", self.name(*binding)),
+            Op::CompareBinding { binding, value } =>
+                format!("compare {} == {}", self.name(*binding), value_text(value)),
+            Op::UnpackTupleBinding { binding, values } => {
+                let items = values.iter()
+                    .map(|item| match item {
+                        TupleItem::Ignore => "_".into(),
+                        TupleItem::Bind(binding) => format!("-> {}", self.name(*binding)),
+                        TupleItem::CompareBinding(binding) => format!("== {}", self.name(*binding)),
+                        TupleItem::CompareValue(value) => format!("== {}", value_text(value)),
+                        TupleItem::IgnoreRest => "..".into(),
+                        TupleItem::BindRest(binding) => format!(".. -> {}", self.name(*binding)),
+                        TupleItem::CompareRest(binding) => format!(".. == {}", self.name(*binding)),
+                    })
+                    .collect::<Vec<_>>();
+                format!("unpack {} as [{}]", self.name(*binding), items.join(", "))
+            },
+            Op::MatchEnumBinding { binding, options } => {
+                let options = options.iter()
+                    .map(|option| match option {
+                        EnumOption::Binding(binding) => self.name(*binding),
+                        EnumOption::Value(value) => value_text(value),
+                    })
+                    .collect::<Vec<_>>();
+                format!("match {} in {}", self.name(*binding), options.join(" | "))
+            },
+            Op::Calculation { binding, operation } => format!(
+                "calculate {} = {}",
+                self.name(*binding),
+                self.calculation(operation, CalcPrecedence::Member),
+            ),
+            Op::CalculationCompare { binding, operation } => format!(
+                "compare {} == {}",
+                self.name(*binding),
+                self.calculation(operation, CalcPrecedence::Member),
+            ),
+            Op::Compare { comparison } => format!(
+                "compare {} {} {}",
+                self.compare_value(&comparison.left),
+                compare_op(comparison.operator),
+                self.compare_value(&comparison.right),
+            ),
+            Op::SearchTupleMemberBinding { binding, value_binding } => format!(
+                "search members of {} -> {}",
+                self.name(*binding),
+                self.name(*value_binding),
+            ),
+            Op::RequireTupleMemberBinding { binding, value_binding } => format!(
+                "require {} in {}",
+                self.name(*value_binding),
+                self.name(*binding),
+            ),
+            Op::RequireTupleMemberValue { binding, value } =>
+                format!("require {} in {}", value_text(value), self.name(*binding)),
+        }
+    }
+
+    fn apply_op(&self, op: &OpApply) -> String {
+        match op {
+            OpApply::CreateObject { binding } =>
+                format!("create object {}", self.name(*binding)),
+            OpApply::CreateTuple { binding, items } =>
+                format!("create tuple {} = [{}]", self.name(*binding), self.tuple_items(items)),
+            OpApply::Calculation { binding, operation } => format!(
+                "calculate {} = {}",
+                self.name(*binding),
+                self.calculation(operation, CalcPrecedence::Member),
+            ),
+            OpApply::ClearAttributes { binding } =>
+                format!("clear all attributes of {}", self.name(*binding)),
+            OpApply::ClearAttribute { binding, attribute } =>
+                format!("clear {}.{}", self.name(*binding), attribute),
+            OpApply::ClearVariableAttribute { binding, attribute_binding } =>
+                format!("clear {}.{}", self.name(*binding), self.name(*attribute_binding)),
+            OpApply::DeleteObject { binding, mode } => match mode {
+                DeletionMode::Object =>
+                    format!("delete object {}", self.name(*binding)),
+                DeletionMode::WithReferences =>
+                    format!("delete object {} with references", self.name(*binding)),
+            },
+            OpApply::EmitBinding { binding } => format!("emit {}", self.name(*binding)),
+            OpApply::EmitValue { value } => format!("emit {}", value_text(value)),
+            OpApply::Halt { reason: None } => "halt".into(),
+            OpApply::Halt { reason: Some(reason) } =>
+                format!("halt with {}", self.tuple_item(reason)),
+            OpApply::RunSystem { system, mode, inputs } => {
+                let inputs = inputs.iter()
+                    .map(|binding| self.name(*binding))
+                    .collect::<Vec<_>>();
+                let mode = match mode {
+                    SystemRunMode::ToFirst => "to first",
+                    SystemRunMode::Saturation => "to saturation",
+                };
+                format!("run system {}({}) {}", system, inputs.join(", "), mode)
+            },
+            OpApply::AddBindingAttribute { binding, attribute, value_binding } => format!(
+                "add {} to {}.{}",
+                self.name(*value_binding),
+                self.name(*binding),
+                attribute,
+            ),
+            OpApply::RemoveBindingAttribute { binding, attribute, value_binding, mode } => format!(
+                "remove {} from {}.{}{}",
+                self.name(*value_binding),
+                self.name(*binding),
+                attribute,
+                removal(*mode),
+            ),
+            OpApply::ResolveAttribute { binding, attribute, value_binding } => format!(
+                "resolve {}.{} -> {}",
+                self.name(*binding),
+                attribute,
+                self.name(*value_binding),
+            ),
+            OpApply::SetBindingAttribute { binding, attribute, value_binding } => format!(
+                "set {}.{} = {}",
+                self.name(*binding),
+                attribute,
+                self.name(*value_binding),
+            ),
+            OpApply::SetValueAttribute { binding, attribute, value } =>
+                format!("set {}.{} = {}", self.name(*binding), attribute, value_text(value)),
+            OpApply::AddValueAttribute { binding, attribute, value } =>
+                format!("add {} to {}.{}", value_text(value), self.name(*binding), attribute),
+            OpApply::RemoveValueAttribute { binding, attribute, value, mode } => format!(
+                "remove {} from {}.{}{}",
+                value_text(value),
+                self.name(*binding),
+                attribute,
+                removal(*mode),
+            ),
+            OpApply::AddVariableBindingAttribute { binding, attribute_binding, value_binding } =>
+                format!(
+                    "add {} to {}.{}",
+                    self.name(*value_binding),
+                    self.name(*binding),
+                    self.name(*attribute_binding),
+                ),
+            OpApply::RemoveVariableBindingAttribute {
+                binding,
+                attribute_binding,
+                value_binding,
+                mode,
+            } => format!(
+                "remove {} from {}.{}{}",
+                self.name(*value_binding),
+                self.name(*binding),
+                self.name(*attribute_binding),
+                removal(*mode),
+            ),
+            OpApply::AddVariableValueAttribute { binding, attribute_binding, value } => format!(
+                "add {} to {}.{}",
+                value_text(value),
+                self.name(*binding),
+                self.name(*attribute_binding),
+            ),
+            OpApply::SetVariableBindingAttribute { binding, attribute_binding, value_binding } =>
+                format!(
+                    "set {}.{} = {}",
+                    self.name(*binding),
+                    self.name(*attribute_binding),
+                    self.name(*value_binding),
+                ),
+            OpApply::SetVariableValueAttribute { binding, attribute_binding, value } => format!(
+                "set {}.{} = {}",
+                self.name(*binding),
+                self.name(*attribute_binding),
+                value_text(value),
+            ),
+            OpApply::RemoveVariableValueAttribute { binding, attribute_binding, value, mode } =>
+                format!(
+                    "remove {} from {}.{}{}",
+                    value_text(value),
+                    self.name(*binding),
+                    self.name(*attribute_binding),
+                    removal(*mode),
+                ),
+            OpApply::Conditional { .. } |
+            OpApply::Match { .. } |
+            OpApply::ForEach { .. } => unreachable!("nested blocks are explained separately"),
+        }
+    }
+
+    fn name(&self, binding: Binding) -> String {
+        if let Some(name) = self.rule.cfg.binding_names.get(&binding) {
+            format!("${}", name)
+        } else if let Some(name) = self.input_variables.get(binding.index()) {
+            format!("${}", name)
+        } else {
+            format!("$#{}", binding.index())
+        }
+    }
+
+    fn closure(&self, closure: &AttributeClosure, relation: &str) -> String {
+        let mut text = format!(
+            "{}.{}{} {} {}",
+            self.name(closure.binding),
+            closure.attribute,
+            match closure.mode {
+                ClosureMode::OneOrMore => '+',
+                ClosureMode::ZeroOrMore => '*',
+            },
+            relation,
+            self.name(closure.value_binding),
+        );
+        if let Some(max_depth) = closure.max_depth {
+            let _ = write!(text, ", max depth {}", max_depth);
+        }
+        match closure.depth {
+            ClosureDepth::Ignore => (),
+            ClosureDepth::Bind(binding) =>
+                { let _ = write!(text, ", depth -> {}", self.name(binding)); },
+            ClosureDepth::Compare(binding) =>
+                { let _ = write!(text, ", depth == {}", self.name(binding)); },
+        }
+        text
+    }
+
+    fn compare_value(&self, value: &CompareValue) -> String {
+        match value {
+            CompareValue::Binding(binding) => self.name(*binding),
+            CompareValue::Value(value) => value_text(value),
+        }
+    }
+
+    fn tuple_item(&self, item: &ApplyTupleItem) -> String {
+        match item {
+            ApplyTupleItem::Binding(binding) => self.name(*binding),
+            ApplyTupleItem::Value(value) => value_text(value),
+        }
+    }
+
+    fn tuple_items(&self, items: &[ApplyTupleItem]) -> String {
+        items.iter()
+            .map(|item| self.tuple_item(item))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn calculations(&self, calcs: &[Calculation]) -> String {
+        calcs.iter()
+            .map(|calc| self.calculation(calc, CalcPrecedence::Member))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn calculation(&self, calc: &Calculation, required: CalcPrecedence) -> String {
+        let (text, precedence) = match calc {
+            Calculation::Value(value) => (value_text(value), CalcPrecedence::Terminal),
+            Calculation::Binding(binding) => (self.name(*binding), CalcPrecedence::Terminal),
+            Calculation::Tuple(items) =>
+                (format!("[{}]", self.calculations(items)), CalcPrecedence::Terminal),
+            Calculation::Len(inner) => (
+                format!("len({})", self.calculation(inner, CalcPrecedence::Member)),
+                CalcPrecedence::Terminal,
+            ),
+            Calculation::Symbol(parts) =>
+                (format!("sym({})", self.calculations(parts)), CalcPrecedence::Terminal),
+            Calculation::Index(value, index) => (
+                format!(
+                    "{}[{}]",
+                    self.calculation(value, CalcPrecedence::Postfix),
+                    self.calculation(index, CalcPrecedence::Member),
+                ),
+                CalcPrecedence::Postfix,
+            ),
+            Calculation::Slice(value, start, end) => {
+                let bound = |bound: &Option<Box<Calculation>>| bound
+                    .as_ref()
+                    .map(|bound| self.calculation(bound, CalcPrecedence::Member))
+                    .unwrap_or_default();
+                (
+                    format!(
+                        "{}[{}..{}]",
+                        self.calculation(value, CalcPrecedence::Postfix),
+                        bound(start),
+                        bound(end),
+                    ),
+                    CalcPrecedence::Postfix,
+                )
+            },
+            Calculation::BinOp(op, left, right) => {
+                let (operator, precedence) = match op {
+                    ArithBinOp::Add => ('+', CalcPrecedence::AddSub),
+                    ArithBinOp::Sub => ('-', CalcPrecedence::AddSub),
+                    ArithBinOp::Mul => ('*', CalcPrecedence::MulDiv),
+                    ArithBinOp::Div => ('/', CalcPrecedence::MulDiv),
+                };
+                let right_precedence = match precedence {
+                    CalcPrecedence::AddSub => CalcPrecedence::MulDiv,
+                    _ => CalcPrecedence::Postfix,
+                };
+                (
+                    format!(
+                        "{} {} {}",
+                        self.calculation(left, precedence),
+                        operator,
+                        self.calculation(right, right_precedence),
+                    ),
+                    precedence,
+                )
+            },
+            Calculation::Concat(left, right) => (
+                format!(
+                    "{} ++ {}",
+                    self.calculation(left, CalcPrecedence::Concat),
+                    self.calculation(right, CalcPrecedence::AddSub),
+                ),
+                CalcPrecedence::Concat,
+            ),
+            Calculation::Member(item, tuple) => (
+                format!(
+                    "{} in {}",
+                    self.calculation(item, CalcPrecedence::Concat),
+                    self.calculation(tuple, CalcPrecedence::Concat),
+                ),
+                CalcPrecedence::Member,
+            ),
+        };
+        if precedence < required {
+            format!("({})", text)
+        } else {
+            text
+        }
+    }
+}
+
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::Float(value) => crate::format::float(*value),
+        _ => value.to_string(),
+    }
+}
+
+fn removal(mode: RemovalMode) -> &'static str {
+    match mode {
+        RemovalMode::Required => "",
+        RemovalMode::Optional => " if present",
+    }
+}
@@ -1,7 +1,18 @@
 
+use std::sync::{Arc};
 use crate::{Symbol, Value};
 use crate::data::{CompareOp};
-use super::{EnumOption, Calculation, CompareValue, Binding, RemovalMode, ApplyTupleItem};
+use super::{
+    EnumOption,
+    Calculation,
+    CompareValue,
+    Binding,
+    RemovalMode,
+    DeletionMode,
+    SystemRunMode,
+    ClosureMode,
+    ApplyTupleItem,
+};
 
 #[derive(Debug, Clone)]
 pub enum Op {
@@ -13,6 +24,14 @@ pub enum Op {
     EndNot {
         index: usize,
     },
+    BeginOptional {
+        index: usize,
+        sequence_len: usize,
+        defaults: Vec<(Binding, Value)>,
+    },
+    EndOptional {
+        index: usize,
+    },
     SearchAttributeBinding {
         binding: Binding,
         attribute: Symbol,
@@ -32,6 +51,40 @@ pub enum Op {
         binding: Binding,
         attribute: Symbol,
     },
+    SearchAttributeNames {
+        binding: Binding,
+        attribute_binding: Binding,
+    },
+    SearchAttributeNamesWithValue {
+        binding: Binding,
+        attribute_binding: Binding,
+        value_binding: Binding,
+    },
+    SearchAttributePairs {
+        binding: Binding,
+        attribute_binding: Binding,
+        value_binding: Binding,
+    },
+    SearchVariableAttributeBinding {
+        binding: Binding,
+        attribute_binding: Binding,
+        value_binding: Binding,
+    },
+    RequireVariableAttributeBinding {
+        binding: Binding,
+        attribute_binding: Binding,
+        value_binding: Binding,
+    },
+    RequireVariableAttribute {
+        binding: Binding,
+        attribute_binding: Binding,
+    },
+    SearchAttributeClosure {
+        closure: Box<AttributeClosure>,
+    },
+    RequireAttributeClosure {
+        closure: Box<AttributeClosure>,
+    },
     AssertObjectBinding {
         binding: Binding,
     },
@@ -58,6 +111,18 @@ pub enum Op {
     Compare {
         comparison: Box<Comparison>,
     },
+    SearchTupleMemberBinding {
+        binding: Binding,
+        value_binding: Binding,
+    },
+    RequireTupleMemberBinding {
+        binding: Binding,
+        value_binding: Binding,
+    },
+    RequireTupleMemberValue {
+        binding: Binding,
+        value: Value,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -67,12 +132,39 @@ pub struct Comparison {
     pub right: CompareValue,
 }
 
+#[derive(Debug, Clone)]
+pub struct AttributeClosure {
+    pub binding: Binding,
+    pub attribute: Symbol,
+    pub mode: ClosureMode,
+    pub max_depth: Option<usize>,
+    pub value_binding: Binding,
+    pub depth: ClosureDepth,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ClosureDepth {
+    Ignore,
+    Bind(Binding),
+    Compare(Binding),
+}
+
 #[derive(Debug, Clone)]
 pub enum TupleItem {
     Ignore,
     Bind(Binding),
     CompareBinding(Binding),
     CompareValue(Value),
+    IgnoreRest,
+    BindRest(Binding),
+    CompareRest(Binding),
+}
+
+impl TupleItem {
+
+    pub fn is_rest(&self) -> bool {
+        matches!(self, Self::IgnoreRest | Self::BindRest(_) | Self::CompareRest(_))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -84,6 +176,39 @@ pub enum OpApply {
         binding: Binding,
         items: Vec<ApplyTupleItem>,
     },
+    Calculation {
+        binding: Binding,
+        operation: Calculation,
+    },
+    ClearAttributes {
+        binding: Binding,
+    },
+    ClearAttribute {
+        binding: Binding,
+        attribute: Symbol,
+    },
+    ClearVariableAttribute {
+        binding: Binding,
+        attribute_binding: Binding,
+    },
+    DeleteObject {
+        binding: Binding,
+        mode: DeletionMode,
+    },
+    EmitBinding {
+        binding: Binding,
+    },
+    EmitValue {
+        value: Value,
+    },
+    Halt {
+        reason: Option<ApplyTupleItem>,
+    },
+    RunSystem {
+        system: Arc<str>,
+        mode: SystemRunMode,
+        inputs: Vec<Binding>,
+    },
     AddBindingAttribute {
         binding: Binding,
         attribute: Symbol,
@@ -95,6 +220,21 @@ pub enum OpApply {
         value_binding: Binding,
         mode: RemovalMode,
     },
+    ResolveAttribute {
+        binding: Binding,
+        attribute: Symbol,
+        value_binding: Binding,
+    },
+    SetBindingAttribute {
+        binding: Binding,
+        attribute: Symbol,
+        value_binding: Binding,
+    },
+    SetValueAttribute {
+        binding: Binding,
+        attribute: Symbol,
+        value: Value,
+    },
     AddValueAttribute {
         binding: Binding,
         attribute: Symbol,
@@ -106,9 +246,48 @@ pub enum OpApply {
         value: Value,
         mode: RemovalMode,
     },
+    AddVariableBindingAttribute {
+        binding: Binding,
+        attribute_binding: Binding,
+        value_binding: Binding,
+    },
+    RemoveVariableBindingAttribute {
+        binding: Binding,
+        attribute_binding: Binding,
+        value_binding: Binding,
+        mode: RemovalMode,
+    },
+    AddVariableValueAttribute {
+        binding: Binding,
+        attribute_binding: Binding,
+        value: Value,
+    },
+    SetVariableBindingAttribute {
+        binding: Binding,
+        attribute_binding: Binding,
+        value_binding: Binding,
+    },
+    SetVariableValueAttribute {
+        binding: Binding,
+        attribute_binding: Binding,
+        value: Value,
+    },
+    RemoveVariableValueAttribute {
+        binding: Binding,
+        attribute_binding: Binding,
+        value: Value,
+        mode: RemovalMode,
+    },
     Conditional {
         condition: Vec<Op>,
         then_apply: Vec<OpApply>,
         otherwise_apply: Vec<OpApply>,
     },
+    Match {
+        branches: Vec<(Vec<Op>, Vec<OpApply>)>,
+    },
+    ForEach {
+        selection: Vec<Op>,
+        apply: Vec<OpApply>,
+    },
 }
@@ -5,18 +5,31 @@ use super::cfg::{CfgRule};
 use super::cfg_ops::{CfgOpSelect, CfgOpApply, OpenTupleItem};
 use super::ops::{Op, OpApply};
 use super::{ops, EnumOption, Binding, CompareValue};
+use crate::{Statistics};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
-struct JumpIndex(usize);
+#[derive(Debug, Clone)]
+pub struct SelectPlan {
+    pub costs: Vec<f64>,
+    pub eliminated: Vec<Elimination>,
+}
 
-fn eliminate_object_assertions(select: &mut Vec<CfgOpSelect>) {
+#[derive(Debug, Clone, Copy)]
+pub enum Elimination {
+    ImpliedObject(Binding),
+    RepeatedObject(Binding),
+}
+
+fn eliminate_object_assertions(select: &mut Vec<CfgOpSelect>, eliminated: &mut Vec<Elimination>) {
 
     let mut known_object_bindings = FnvHashSet::default();
     for op in select.iter() {
         let binding = match *op {
             CfgOpSelect::AttributeBinding { binding, .. } |
             CfgOpSelect::RequireAttribute { binding, .. } |
-            CfgOpSelect::RequireValueAttribute { binding, .. } => binding,
+            CfgOpSelect::RequireValueAttribute { binding, .. } |
+            CfgOpSelect::VariableAttributeBinding { binding, .. } |
+            CfgOpSelect::RequireVariableAttribute { binding, .. } |
+            CfgOpSelect::AttributeClosure { binding, .. } => binding,
             _ => {
                 continue;
             },
@@ -27,9 +40,11 @@ fn eliminate_object_assertions(select: &mut Vec<CfgOpSelect>) {
     select.retain(|op| match *op {
         CfgOpSelect::AssertObjectBinding { binding } => {
             if known_object_bindings.contains(&binding) {
+                eliminated.push(Elimination::ImpliedObject(binding));
                 false
             } else {
                 if asserted_objected_bindings.contains(&binding) {
+                    eliminated.push(Elimination::RepeatedObject(binding));
                     false
                 } else {
                     asserted_objected_bindings.insert(binding);
@@ -41,8 +56,8 @@ fn eliminate_object_assertions(select: &mut Vec<CfgOpSelect>) {
     });
 
     for op in select.iter_mut() {
-        if let CfgOpSelect::Not { body, .. } = op {
-            eliminate_object_assertions(body);
+        if let CfgOpSelect::Not { body, .. } | CfgOpSelect::Optional { body, .. } = op {
+            eliminate_object_assertions(body, eliminated);
         }
     }
 }
@@ -66,21 +81,30 @@ impl Sequence {
     }
 }
 
-pub fn optimize(rule: &CfgRule, input_bindings_len: usize) -> (Vec<Op>, Vec<OpApply>) {
+pub fn optimize(
+    rule: &CfgRule,
+    input_bindings_len: usize,
+    statistics: Option<&Statistics>,
+) -> (Vec<Op>, Vec<OpApply>, Vec<SelectPlan>) {
 
     let mut sequence = Sequence::new();
+    let mut plans = Vec::new();
     let provided = (0..input_bindings_len)
         .map(Binding::with_index)
         .collect::<Vec<Binding>>();
 
-    let (st_provided, select_ops) = optimize_select(&mut sequence, &rule.select, &provided);
-    let apply_ops = optimize_apply(&mut sequence, &rule.apply, &st_provided);
+    let (st_provided, select_ops)
+        = optimize_select(&mut sequence, statistics, &mut plans, &rule.select, &provided);
+    let apply_ops
+        = optimize_apply(&mut sequence, statistics, &mut plans, &rule.apply, &st_provided);
 
-    (select_ops, apply_ops)
+    (select_ops, apply_ops, plans)
 }
 
 fn optimize_apply(
     sequence: &mut Sequence,
+    statistics: Option<&Statistics>,
+    plans: &mut Vec<SelectPlan>,
     cfg_ops: &[CfgOpApply],
     provided: &[Binding],
 ) -> Vec<OpApply> {
@@ -92,6 +116,24 @@ fn optimize_apply(
                 OpApply::CreateObject { binding },
             CfgOpApply::CreateTuple { binding, ref items } =>
                 OpApply::CreateTuple { binding, items: items.clone() },
+            CfgOpApply::Calculation { binding, ref operation } =>
+                OpApply::Calculation { binding, operation: operation.clone() },
+            CfgOpApply::ClearAttributes { binding } =>
+                OpApply::ClearAttributes { binding },
+            CfgOpApply::ClearAttribute { binding, ref attribute } =>
+                OpApply::ClearAttribute { binding, attribute: attribute.clone() },
+            CfgOpApply::ClearVariableAttribute { binding, attribute_binding } =>
+                OpApply::ClearVariableAttribute { binding, attribute_binding },
+            CfgOpApply::DeleteObject { binding, mode } =>
+                OpApply::DeleteObject { binding, mode },
+            CfgOpApply::EmitBinding { binding } =>
+                OpApply::EmitBinding { binding },
+            CfgOpApply::EmitValue { ref value } =>
+                OpApply::EmitValue { value: value.clone() },
+            CfgOpApply::Halt { ref reason } =>
+                OpApply::Halt { reason: reason.clone() },
+            CfgOpApply::RunSystem { ref system, mode, ref inputs } =>
+                OpApply::RunSystem { system: system.clone(), mode, inputs: inputs.clone() },
             CfgOpApply::AddBindingAttribute { binding, ref attribute, value_binding } =>
                 OpApply::AddBindingAttribute {
                     binding,
@@ -105,6 +147,24 @@ fn optimize_apply(
                     value_binding,
                     mode,
                 },
+            CfgOpApply::ResolveAttribute { binding, ref attribute, value_binding } =>
+                OpApply::ResolveAttribute {
+                    binding,
+                    attribute: attribute.clone(),
+                    value_binding,
+                },
+            CfgOpApply::SetBindingAttribute { binding, ref attribute, value_binding } =>
+                OpApply::SetBindingAttribute {
+                    binding,
+                    attribute: attribute.clone(),
+                    value_binding,
+                },
+            CfgOpApply::SetValueAttribute { binding, ref attribute, ref value } =>
+                OpApply::SetValueAttribute {
+                    binding,
+                    attribute: attribute.clone(),
+                    value: value.clone(),
+                },
             CfgOpApply::AddValueAttribute { binding, ref attribute, ref value } =>
                 OpApply::AddValueAttribute {
                     binding,
@@ -118,12 +178,72 @@ fn optimize_apply(
                     value: value.clone(),
                     mode,
                 },
+            CfgOpApply::AddVariableBindingAttribute { binding, attribute_binding, value_binding } =>
+                OpApply::AddVariableBindingAttribute { binding, attribute_binding, value_binding },
+            CfgOpApply::RemoveVariableBindingAttribute {
+                binding,
+                attribute_binding,
+                value_binding,
+                mode,
+            } =>
+                OpApply::RemoveVariableBindingAttribute {
+                    binding,
+                    attribute_binding,
+                    value_binding,
+                    mode,
+                },
+            CfgOpApply::AddVariableValueAttribute { binding, attribute_binding, ref value } =>
+                OpApply::AddVariableValueAttribute {
+                    binding,
+                    attribute_binding,
+                    value: value.clone(),
+                },
+            CfgOpApply::SetVariableBindingAttribute { binding, attribute_binding, value_binding } =>
+                OpApply::SetVariableBindingAttribute { binding, attribute_binding, value_binding },
+            CfgOpApply::SetVariableValueAttribute { binding, attribute_binding, ref value } =>
+                OpApply::SetVariableValueAttribute {
+                    binding,
+                    attribute_binding,
+                    value: value.clone(),
+                },
+            CfgOpApply::RemoveVariableValueAttribute {
+                binding,
+                attribute_binding,
+                ref value,
+                mode,
+            } =>
+                OpApply::RemoveVariableValueAttribute {
+                    binding,
+                    attribute_binding,
+                    value: value.clone(),
+                    mode,
+                },
             CfgOpApply::Conditional { ref condition, ref then_apply, ref otherwise_apply } => {
-                let (_, condition) = optimize_select(sequence, condition, provided);
-                let then_apply = optimize_apply(sequence, then_apply, provided);
-                let otherwise_apply = optimize_apply(sequence, otherwise_apply, provided);
+                let (then_provided, condition)
+                    = optimize_select(sequence, statistics, plans, condition, provided);
+                let then_apply
+                    = optimize_apply(sequence, statistics, plans, then_apply, &then_provided);
+                let otherwise_apply
+                    = optimize_apply(sequence, statistics, plans, otherwise_apply, provided);
                 OpApply::Conditional { condition, then_apply, otherwise_apply }
             },
+            CfgOpApply::Match { ref branches } => {
+                let branches = branches.iter()
+                    .map(|(condition, apply)| {
+                        let (provided, condition)
+                            = optimize_select(sequence, statistics, plans, condition, provided);
+                        let apply = optimize_apply(sequence, statistics, plans, apply, &provided);
+                        (condition, apply)
+                    })
+                    .collect();
+                OpApply::Match { branches }
+            },
+            CfgOpApply::ForEach { ref selection, ref apply } => {
+                let (loop_provided, selection)
+                    = optimize_select(sequence, statistics, plans, selection, provided);
+                let apply = optimize_apply(sequence, statistics, plans, apply, &loop_provided);
+                OpApply::ForEach { selection, apply }
+            },
         });
     }
     ops
@@ -131,21 +251,32 @@ fn optimize_apply(
 
 fn optimize_select(
     sequence: &mut Sequence,
+    statistics: Option<&Statistics>,
+    plans: &mut Vec<SelectPlan>,
     cfg_ops: &[CfgOpSelect],
     provided: &[Binding],
 ) -> (Vec<Binding>, Vec<Op>) {
 
     let mut cfg_ops = cfg_ops.to_vec();
-    eliminate_object_assertions(&mut cfg_ops);
+    let mut eliminated = Vec::new();
+    eliminate_object_assertions(&mut cfg_ops, &mut eliminated);
 
-    let mut state = assemble_ops(&cfg_ops, &OpState::new(provided), sequence)
+    let mut state = assemble_ops(&cfg_ops, &OpState::new(provided), sequence, statistics)
         .expect("select op order solution");
     state.ops.push(Op::End);
+    state.costs.push(state.cost);
+
+    plans.push(SelectPlan { costs: state.costs, eliminated });
 
     (state.provided, state.ops)
 }
 
-fn assemble_ops(select: &[CfgOpSelect], prev: &OpState, seq: &mut Sequence) -> Option<OpState> {
+fn assemble_ops(
+    select: &[CfgOpSelect],
+    prev: &OpState,
+    seq: &mut Sequence,
+    statistics: Option<&Statistics>,
+) -> Option<OpState> {
     let mut branches = Vec::new();
     branches.push((prev.clone(), select.to_vec()));
 
@@ -156,7 +287,9 @@ fn assemble_ops(select: &[CfgOpSelect], prev: &OpState, seq: &mut Sequence) -> O
         }
         for (branch, rest_ops) in branches.drain(..) {
             for next_op_index in 0..rest_ops.len() {
-                if let Some(next_state) = transform_op(&rest_ops[next_op_index], &branch, seq) {
+                if let Some(next_state)
+                    = transform_op(&rest_ops[next_op_index], &branch, seq, statistics)
+                {
                     branches_next.push((
                         next_state,
                         rest_ops[0..next_op_index]
@@ -184,7 +317,12 @@ fn assemble_ops(select: &[CfgOpSelect], prev: &OpState, seq: &mut Sequence) -> O
     }
 }
 
-fn transform_op(op: &CfgOpSelect, prev: &OpState, seq: &mut Sequence) -> Option<OpState> {
+fn transform_op(
+    op: &CfgOpSelect,
+    prev: &OpState,
+    seq: &mut Sequence,
+    statistics: Option<&Statistics>,
+) -> Option<OpState> {
     use std::iter::{empty, once};
 
     match op {
@@ -226,6 +364,17 @@ fn transform_op(op: &CfgOpSelect, prev: &OpState, seq: &mut Sequence) -> Option<
                                 new_values.push(ops::TupleItem::Bind(*binding));
                             }
                         },
+                        OpenTupleItem::IgnoreRest => {
+                            new_values.push(ops::TupleItem::IgnoreRest);
+                        },
+                        OpenTupleItem::RestBinding(binding) => {
+                            if prev.bound(*binding) || new_bindings.contains(binding) {
+                                new_values.push(ops::TupleItem::CompareRest(*binding));
+                            } else {
+                                new_bindings.push(*binding);
+                                new_values.push(ops::TupleItem::BindRest(*binding));
+                            }
+                        },
                     }
                 }
                 let no_new_bindings = new_bindings.is_empty();
@@ -306,12 +455,119 @@ fn transform_op(op: &CfgOpSelect, prev: &OpState, seq: &mut Sequence) -> Option<
                             attribute: attribute.clone(),
                             value_binding: *value_binding,
                         },
-                        |cost| cost * 1.4,
+                        |cost| cost * scaled(statistics, 1.4, |stats| stats.fan_out(attribute)),
                         once(*value_binding),
                     )
                 }
             })
         },
+        CfgOpSelect::VariableAttributeBinding { binding, attribute_binding, value_binding } => {
+            prev.bound(*binding).then(|| {
+                match (prev.bound(*attribute_binding), prev.bound(*value_binding)) {
+                    (true, true) => prev.advance(
+                        Op::RequireVariableAttributeBinding {
+                            binding: *binding,
+                            attribute_binding: *attribute_binding,
+                            value_binding: *value_binding,
+                        },
+                        |cost| cost - 1.2,
+                        empty(),
+                    ),
+                    (true, false) => prev.advance(
+                        Op::SearchVariableAttributeBinding {
+                            binding: *binding,
+                            attribute_binding: *attribute_binding,
+                            value_binding: *value_binding,
+                        },
+                        |cost| cost * scaled(statistics, 1.4, Statistics::mean_fan_out),
+                        once(*value_binding),
+                    ),
+                    (false, true) => prev.advance(
+                        Op::SearchAttributeNamesWithValue {
+                            binding: *binding,
+                            attribute_binding: *attribute_binding,
+                            value_binding: *value_binding,
+                        },
+                        |cost| cost * scaled(statistics, 1.6, Statistics::attributes_per_object),
+                        once(*attribute_binding),
+                    ),
+                    (false, false) => prev.advance(
+                        Op::SearchAttributePairs {
+                            binding: *binding,
+                            attribute_binding: *attribute_binding,
+                            value_binding: *value_binding,
+                        },
+                        |cost| cost * scaled(statistics, 2.0, Statistics::values_per_object),
+                        [*attribute_binding, *value_binding].iter().copied(),
+                    ),
+                }
+            })
+        },
+        CfgOpSelect::RequireVariableAttribute { binding, attribute_binding } => {
+            prev.bound(*binding).then(|| {
+                if prev.bound(*attribute_binding) {
+                    prev.advance(
+                        Op::RequireVariableAttribute {
+                            binding: *binding,
+                            attribute_binding: *attribute_binding,
+                        },
+                        |cost| cost - 2.0,
+                        empty(),
+                    )
+                } else {
+                    prev.advance(
+                        Op::SearchAttributeNames {
+                            binding: *binding,
+                            attribute_binding: *attribute_binding,
+                        },
+                        |cost| cost * scaled(statistics, 1.6, Statistics::attributes_per_object),
+                        once(*attribute_binding),
+                    )
+                }
+            })
+        },
+        CfgOpSelect::AttributeClosure {
+            binding,
+            attribute,
+            mode,
+            max_depth,
+            value_binding,
+            depth_binding,
+        } => {
+            prev.bound(*binding).then(|| {
+                let depth = match *depth_binding {
+                    Some(depth_binding) if prev.bound(depth_binding) =>
+                        ops::ClosureDepth::Compare(depth_binding),
+                    Some(depth_binding) => ops::ClosureDepth::Bind(depth_binding),
+                    None => ops::ClosureDepth::Ignore,
+                };
+                let closure = Box::new(ops::AttributeClosure {
+                    binding: *binding,
+                    attribute: attribute.clone(),
+                    mode: *mode,
+                    max_depth: *max_depth,
+                    value_binding: *value_binding,
+                    depth,
+                });
+                let new_depth_binding = match depth {
+                    ops::ClosureDepth::Bind(depth_binding) => Some(depth_binding),
+                    _ => None,
+                };
+                if prev.bound(*value_binding) {
+                    prev.advance(
+                        Op::RequireAttributeClosure { closure },
+                        |cost| cost - 1.0,
+                        new_depth_binding.into_iter(),
+                    )
+                } else {
+                    prev.advance(
+                        Op::SearchAttributeClosure { closure },
+                        |cost| cost * scaled(statistics, 1.8, |stats| stats.fan_out(attribute)),
+                        once(*value_binding).chain(new_depth_binding),
+                    )
+                }
+            })
+        },
         CfgOpSelect::Compare { operator, left, right } => {
             if (
                 left.to_binding().map(|binding| prev.bound(binding)).unwrap_or(true)
@@ -356,6 +612,41 @@ fn transform_op(op: &CfgOpSelect, prev: &OpState, seq: &mut Sequence) -> Option<
                 }
             })
         }
+        CfgOpSelect::RequireTupleMember { binding, value } => {
+            prev.bound(*binding).then(|| {
+                prev.advance(
+                    Op::RequireTupleMemberValue {
+                        binding: *binding,
+                        value: value.clone(),
+                    },
+                    |cost| cost - 1.5,
+                    empty(),
+                )
+            })
+        },
+        CfgOpSelect::TupleMemberBinding { binding, value_binding } => {
+            prev.bound(*binding).then(|| {
+                if prev.bound(*value_binding) {
+                    prev.advance(
+                        Op::RequireTupleMemberBinding {
+                            binding: *binding,
+                            value_binding: *value_binding,
+                        },
+                        |cost| cost - 1.5,
+                        empty(),
+                    )
+                } else {
+                    prev.advance(
+                        Op::SearchTupleMemberBinding {
+                            binding: *binding,
+                            value_binding: *value_binding,
+                        },
+                        |cost| cost * 1.4,
+                        once(*value_binding),
+                    )
+                }
+            })
+        },
         CfgOpSelect::Not { body, binding_mark } => {
             let mut required = Vec::new();
             collect_bindings(body, &mut |binding| {
@@ -364,11 +655,41 @@ fn transform_op(op: &CfgOpSelect, prev: &OpState, seq: &mut Sequence) -> Option<
                 }
             });
             if prev.all_bound(required.into_iter()) {
-                if let Some(mut body_state) = assemble_ops(&body, prev, seq) {
+                if let Some(mut body_state) = assemble_ops(body, prev, seq, statistics) {
                     let index = seq.next();
                     body_state.ops.push(Op::EndNot { index });
+                    body_state.costs.push(body_state.cost);
                     let sequence_len = body_state.ops.len() - prev.ops.len();
                     body_state.ops.insert(prev.ops.len(), Op::BeginNot { index, sequence_len });
+                    body_state.costs.insert(prev.ops.len(), prev.cost);
+                    Some(body_state)
+                } else {
+                    None
+                }
+            } else {
+                None
+            }
+        },
+        CfgOpSelect::Optional { body, defaults, binding_mark } => {
+            let mut required = Vec::new();
+            collect_bindings(body, &mut |binding| {
+                if binding.before_mark(*binding_mark) && !required.contains(&binding) {
+                    required.push(binding);
+                }
+            });
+            let defaults_unbound = defaults.iter().all(|(binding, _)| !prev.bound(*binding));
+            if defaults_unbound && prev.all_bound(required.into_iter()) {
+                if let Some(mut body_state) = assemble_ops(body, prev, seq, statistics) {
+                    let index = seq.next();
+                    body_state.ops.push(Op::EndOptional { index });
+                    body_state.costs.push(body_state.cost);
+                    let sequence_len = body_state.ops.len() - prev.ops.len();
+                    body_state.ops.insert(prev.ops.len(), Op::BeginOptional {
+                        index,
+                        sequence_len,
+                        defaults: defaults.clone(),
+                    });
+                    body_state.costs.insert(prev.ops.len(), prev.cost);
                     Some(body_state)
                 } else {
                     None
@@ -380,6 +701,22 @@ fn transform_op(op: &CfgOpSelect, prev: &OpState, seq: &mut Sequence) -> Option<
     }
 }
 
+// the fixed factors of the searches are tuned around a search over a single
+// named attribute, costed at 1.4, as if it produced that many candidates.
+// Statistics replace this assumed count with the measured one, so a search
+// that measures as assumed keeps its fixed factor
+const ASSUMED_CANDIDATES: f64 = 1.4;
+
+fn scaled<F>(statistics: Option<&Statistics>, factor: f64, candidates: F) -> f64
+where
+    F: FnOnce(&Statistics) -> f64,
+{
+    match statistics {
+        Some(statistics) => factor * candidates(statistics) / ASSUMED_CANDIDATES,
+        None => factor,
+    }
+}
+
 fn collect_bindings<F>(
     ops: &[CfgOpSelect],
     collect: &mut F,
@@ -394,18 +731,18 @@ where
             CfgOpSelect::TupleBinding { binding, values } => {
                 collect(*binding);
                 for value in values {
-                    match value {
-                        OpenTupleItem::Binding(binding) => collect(*binding),
-                        _ => (),
+                    if let OpenTupleItem::Binding(binding) | OpenTupleItem::RestBinding(binding)
+                        = value
+                    {
+                        collect(*binding);
                     }
                 }
             },
             CfgOpSelect::EnumBinding { binding, options } => {
                 collect(*binding);
                 for option in options {
-                    match option {
-                        EnumOption::Binding(binding) => collect(*binding),
-                        _ => (),
+                    if let EnumOption::Binding(binding) = option {
+                        collect(*binding);
                     }
                 }
             },
@@ -415,7 +752,29 @@ where
                 collect(*value_binding);
             },
             CfgOpSelect::RequireAttribute { binding, .. } => collect(*binding),
+            CfgOpSelect::VariableAttributeBinding { binding, attribute_binding, value_binding } => {
+                collect(*binding);
+                collect(*attribute_binding);
+                collect(*value_binding);
+            },
+            CfgOpSelect::RequireVariableAttribute { binding, attribute_binding } => {
+                collect(*binding);
+                collect(*attribute_binding);
+            },
+            CfgOpSelect::AttributeClosure { binding, value_binding, depth_binding, .. } => {
+                collect(*binding);
+                collect(*value_binding);
+                if let Some(depth_binding) = depth_binding {
+                    collect(*depth_binding);
+                }
+            },
             CfgOpSelect::Not { body, .. } => collect_bindings(body, collect),
+            CfgOpSelect::Optional { body, defaults, .. } => {
+                collect_bindings(body, collect);
+                for (binding, _) in defaults {
+                    collect(*binding);
+                }
+            },
             CfgOpSelect::Compare { left, right, .. } => {
                 if let CompareValue::Binding(binding) = left {
                     collect(*binding);
@@ -428,6 +787,11 @@ where
                 collect(*result_binding);
                 operation.for_each_binding(collect);
             },
+            CfgOpSelect::RequireTupleMember { binding, .. } => collect(*binding),
+            CfgOpSelect::TupleMemberBinding { binding, value_binding } => {
+                collect(*binding);
+                collect(*value_binding);
+            },
         }
     }
 }
@@ -435,6 +799,7 @@ where
 #[derive(Debug, Clone)]
 struct OpState {
     ops: Vec<Op>,
+    costs: Vec<f64>,
     cost: f64,
     provided: Vec<Binding>,
 }
@@ -444,6 +809,7 @@ impl OpState {
     fn new(provided: &[Binding]) -> Self {
         Self {
             ops: Vec::new(),
+            costs: Vec::new(),
             cost: 1000.0,
             provided: provided.into(),
         }
@@ -465,8 +831,10 @@ impl OpState {
         }
 
         let cost = adjust_cost(self.cost);
+        let mut costs = self.costs.clone();
+        costs.push(cost);
 
-        Self { ops, provided, cost }
+        Self { ops, costs, provided, cost }
     }
 
     fn bound(&self, binding: Binding) -> bool {
@@ -0,0 +1,136 @@
+use std::collections::{HashMap};
+use std::ops::{Range};
+use super::{CompiledRule, Op};
+
+#[derive(Debug, Clone, Default)]
+pub struct SharedPrefixes {
+    nodes: Vec<PrefixNode>,
+    rules: Vec<Option<RulePrefix>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PrefixNode {
+    pub parent: Option<usize>,
+    pub ops: Vec<Op>,
+    pub ops_len: usize,
+    pub rules: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RulePrefix {
+    pub node: usize,
+    pub ops_len: usize,
+}
+
+#[derive(Debug, Default)]
+struct TrieNode {
+    children: HashMap<String, usize>,
+    rules: Vec<usize>,
+}
+
+impl SharedPrefixes {
+
+    // rules are inserted into a trie of their top-level selects. Chains of
+    // trie nodes that are passed by the same two or more rules become one
+    // prefix node, which runs its ops once for all of those rules.
+    pub fn new(rules: &[CompiledRule]) -> Self {
+        let mut trie = vec![TrieNode::default()];
+        let mut paths = Vec::with_capacity(rules.len());
+        for (rule_index, rule) in rules.iter().enumerate() {
+            let ops = rule.ops();
+            let mut node = 0;
+            let mut path = Vec::new();
+            for unit in top_level_units(ops) {
+                // keyed by the debug representation since value equality
+                // doesn't keep integers and floats apart
+                let key = format!("{:?}", &ops[unit.clone()]);
+                let next_node = trie.len();
+                let child = *trie[node].children.entry(key).or_insert(next_node);
+                if child == next_node {
+                    trie.push(TrieNode::default());
+                }
+                trie[child].rules.push(rule_index);
+                path.push((child, unit.end));
+                node = child;
+            }
+            paths.push(path);
+        }
+
+        let mut nodes = Vec::new();
+        let mut node_indices = HashMap::new();
+        let mut rule_prefixes = Vec::with_capacity(rules.len());
+        for (rule, path) in rules.iter().zip(&paths) {
+            let shared_len = path
+                .iter()
+                .take_while(|(node, _)| trie[*node].rules.len() > 1)
+                .count();
+            let shared = &path[..shared_len];
+            let mut prefix = None;
+            let mut parent = None;
+            let mut start = 0;
+            for (position, &(trie_node, end)) in shared.iter().enumerate() {
+                let rules_len = trie[trie_node].rules.len();
+                let continues = shared
+                    .get(position + 1)
+                    .is_some_and(|(next, _)| trie[*next].rules.len() == rules_len);
+                if continues {
+                    continue;
+                }
+                let index = *node_indices.entry(trie_node).or_insert_with(|| {
+                    let mut ops = rule.ops()[start..end].to_vec();
+                    ops.push(Op::End);
+                    nodes.push(PrefixNode {
+                        parent,
+                        ops,
+                        ops_len: end,
+                        rules: trie[trie_node].rules.clone(),
+                    });
+                    nodes.len() - 1
+                });
+                prefix = Some(RulePrefix { node: index, ops_len: end });
+                parent = Some(index);
+                start = end;
+            }
+            rule_prefixes.push(prefix);
+        }
+
+        Self { nodes, rules: rule_prefixes }
+    }
+
+    pub fn nodes(&self) -> &[PrefixNode] {
+        &self.nodes
+    }
+
+    pub fn node(&self, index: usize) -> &PrefixNode {
+        &self.nodes[index]
+    }
+
+    pub fn rule(&self, index: usize) -> Option<RulePrefix> {
+        self.rules.get(index).copied().flatten()
+    }
+}
+
+// splits the ops before the final `End` into single ops and whole
+// not/optional scopes
+fn top_level_units(ops: &[Op]) -> Vec<Range<usize>> {
+    let mut units = Vec::new();
+    let mut start = 0;
+    let mut depth = 0usize;
+    for (index, op) in ops.iter().enumerate() {
+        match op {
+            Op::End => break,
+            Op::BeginNot { .. } | Op::BeginOptional { .. } => {
+                depth += 1;
+            },
+            Op::EndNot { .. } | Op::EndOptional { .. } => {
+                depth -= 1;
+            },
+            _ => (),
+        }
+        if depth == 0 {
+            units.push(start..(index + 1));
+            start = index + 1;
+        }
+    }
+    units
+}
@@ -53,7 +53,7 @@ impl std::cmp::Eq for Value {}
 impl std::cmp::PartialOrd for Value {
 
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(value_compare(self, other))
+        Some(self.cmp(other))
     }
 }
 
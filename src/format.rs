@@ -0,0 +1,776 @@
+
+use std::fmt::{Write};
+use crate::{parser, ast, LoadError, RemovalMode, DeletionMode, SystemRunMode, ClosureMode};
+use crate::data::{ArithBinOp, CompareOp};
+use crate::parser::{Span};
+
+const MAX_WIDTH: usize = 80;
+const INDENT: &str = "    ";
+
+pub fn format_source(source: &str) -> Result<String, LoadError> {
+    let document = parser::parse(source).map_err(LoadError::Parse)?;
+    Ok(document_source(&document))
+}
+
+fn document_source(document: &ast::Document<'_>) -> String {
+    let mut formatter = Formatter::new(&document.comments);
+    let mut previous: Option<&ast::DocumentItem<'_>> = None;
+    for item in &document.items {
+        let is_grouped = matches!(
+            (previous, item),
+            (Some(ast::DocumentItem::System(_)), ast::DocumentItem::System(_))
+        );
+        if previous.is_some() && !is_grouped {
+            formatter.out.push('\n');
+        }
+        match item {
+            ast::DocumentItem::System(system) => {
+                formatter.comments_before(offset(&system.position));
+                formatter.system(system);
+            },
+            ast::DocumentItem::Rule(rule) => {
+                formatter.comments_before(offset(&rule.position));
+                formatter.rule(rule);
+            },
+        }
+        previous = Some(item);
+    }
+    if !formatter.comments.is_empty() && previous.is_some() {
+        formatter.out.push('\n');
+    }
+    formatter.comments_before(usize::MAX);
+    formatter.out
+}
+
+struct Formatter<'c, 'a> {
+    out: String,
+    indent: usize,
+    comments: &'c [ast::Comment<'a>],
+}
+
+impl<'c, 'a> Formatter<'c, 'a> {
+
+    fn new(comments: &'c [ast::Comment<'a>]) -> Self {
+        Self { out: String::new(), indent: 0, comments }
+    }
+
+    fn write_indent(&mut self) {
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    // comments are placed by their byte offset, so they stay in front of
+    // the element or closing brace that followed them in the source
+    fn comments_before(&mut self, position: usize) {
+        while let Some((comment, rest)) = self.comments.split_first() {
+            if offset(&comment.span) >= position {
+                break;
+            }
+            self.comments = rest;
+            self.write_indent();
+            self.out.push_str(comment.span.fragment().trim_end());
+            self.out.push('\n');
+        }
+    }
+
+    // comments on the line an element ends on stay there, unless the next
+    // element starts on the same line and they lead into it
+    fn trailing_comments(&mut self, line: u32, next_line: u32) {
+        while let Some((comment, rest)) = self.comments.split_first() {
+            if comment.span.location_line() != line || next_line <= line {
+                break;
+            }
+            self.comments = rest;
+            self.out.push(' ');
+            self.out.push_str(comment.span.fragment().trim_end());
+        }
+    }
+
+    // all comments before the current element have been written already,
+    // so this tells whether any are left inside an element ending here
+    fn has_comments_before(&self, range: SourceRange) -> bool {
+        match (self.comments.first(), range.end) {
+            (Some(comment), Some(end)) => offset(&comment.span) < end.offset,
+            _ => false,
+        }
+    }
+
+    fn fits(&self, inline: &str) -> bool {
+        let trailing_comma = 1;
+        self.indent * INDENT.len() + inline.len() + trailing_comma <= MAX_WIDTH
+    }
+
+    fn system(&mut self, system: &ast::SystemDeclaration<'_>) {
+        let inputs = system.inputs
+            .iter()
+            .map(|input| format!("${}", input.as_str()))
+            .collect::<Vec<_>>();
+        let _ = writeln!(self.out, "system {}({});", system.name.as_str(), inputs.join(", "));
+    }
+
+    fn rule(&mut self, rule: &ast::Rule<'_>) {
+        let _ = write!(self.out, "rule {}:{} ", rule.system_name.as_str(), rule.name.as_str());
+        self.select_block(&rule.select);
+        self.out.push_str(" do ");
+        self.apply_block(&rule.apply);
+        self.out.push('\n');
+    }
+
+    fn compact_select_block(
+        &mut self,
+        selects: &ast::Block<'_, ast::RuleSelect<'_>>,
+        tail_len: usize,
+    ) {
+        let inline = inline_block(&selects.items, select_inline);
+        let column = self.out.len() - self.out.rfind('\n').map_or(0, |index| index + 1);
+        if column + inline.len() + tail_len <= MAX_WIDTH
+            && !self.has_comments_before(SourceRange::from_span(&selects.end))
+        {
+            self.out.push_str(&inline);
+        } else {
+            self.select_block(selects);
+        }
+    }
+
+    fn select_block(&mut self, selects: &ast::Block<'_, ast::RuleSelect<'_>>) {
+        self.block(selects, |formatter, select| formatter.select(select), select_range);
+    }
+
+    fn apply_block(&mut self, applys: &ast::Block<'_, ast::RuleApply<'_>>) {
+        self.block(applys, |formatter, apply| formatter.apply(apply), apply_range);
+    }
+
+    fn block<T, W, R>(&mut self, block: &ast::Block<'_, T>, mut write: W, range: R)
+    where
+        W: FnMut(&mut Self, &T),
+        R: Fn(&T) -> SourceRange,
+    {
+        let end = offset(&block.end);
+        if block.items.is_empty() && !self.has_comments_before(SourceRange::from_span(&block.end)) {
+            self.out.push_str("{}");
+            return;
+        }
+        self.out.push_str("{\n");
+        self.indent += 1;
+        for (index, item) in block.items.iter().enumerate() {
+            let item_range = range(item);
+            if let Some(start) = item_range.start {
+                self.comments_before(start.offset);
+            }
+            self.write_indent();
+            write(self, item);
+            self.out.push(',');
+            let next_line = block.items
+                .get(index + 1)
+                .and_then(|next| range(next).start)
+                .map_or(block.end.location_line(), |start| start.line);
+            if let Some(end) = item_range.end {
+                self.trailing_comments(end.line, next_line);
+            }
+            self.out.push('\n');
+        }
+        self.comments_before(end);
+        self.indent -= 1;
+        self.write_indent();
+        self.out.push('}');
+    }
+
+    fn select(&mut self, select: &ast::RuleSelect<'_>) {
+        let inline = select_inline(select);
+        if !is_compound_select(select)
+            || (self.fits(&inline) && !self.has_comments_before(select_range(select)))
+        {
+            self.out.push_str(&inline);
+            return;
+        }
+        match select {
+            ast::RuleSelect::Not(selects, _) => {
+                self.out.push_str("not ");
+                self.select_block(selects);
+            },
+            ast::RuleSelect::ForAll(premise, conclusion, _) => {
+                self.out.push_str("forall ");
+                self.compact_select_block(premise, " => {".len());
+                self.out.push_str(" => ");
+                self.select_block(conclusion);
+            },
+            ast::RuleSelect::Optional(optional) => {
+                self.out.push_str("optional ");
+                self.compact_select_block(&optional.selects, " else {".len());
+                self.out.push_str(" else ");
+                self.block(
+                    &optional.defaults,
+                    |formatter, default| formatter.out.push_str(&optional_default_inline(default)),
+                    |default| SourceRange::from_span(&default.position),
+                );
+            },
+            _ => self.out.push_str(&inline),
+        }
+    }
+
+    fn apply(&mut self, apply: &ast::RuleApply<'_>) {
+        let inline = apply_inline(apply);
+        if !is_compound_apply(apply)
+            || (self.fits(&inline) && !self.has_comments_before(apply_range(apply)))
+        {
+            self.out.push_str(&inline);
+            return;
+        }
+        match apply {
+            ast::RuleApply::Conditional(conditional) => {
+                self.out.push_str("if ");
+                self.compact_select_block(&conditional.condition, " then {".len());
+                self.out.push_str(" then ");
+                self.apply_block(&conditional.then_apply);
+                if let Some(otherwise_apply) = &conditional.otherwise_apply {
+                    let has_comments =
+                        self.has_comments_before(SourceRange::from_span(&otherwise_apply.end));
+                    if !otherwise_apply.items.is_empty() || has_comments {
+                        self.out.push_str(" else ");
+                        self.apply_block(otherwise_apply);
+                    }
+                }
+            },
+            ast::RuleApply::Match(branches, _) => {
+                self.out.push_str("match ");
+                self.block(
+                    branches,
+                    |formatter, branch| formatter.match_branch(branch),
+                    match_branch_range,
+                );
+            },
+            ast::RuleApply::ForEach(for_each) => {
+                self.out.push_str("for ");
+                self.compact_select_block(&for_each.selection, " do {".len());
+                self.out.push_str(" do ");
+                self.apply_block(&for_each.apply);
+            },
+            _ => self.out.push_str(&inline),
+        }
+    }
+
+    fn match_branch(&mut self, branch: &ast::MatchBranch<'_>) {
+        let inline = match_branch_inline(branch);
+        if self.fits(&inline) && !self.has_comments_before(match_branch_range(branch)) {
+            self.out.push_str(&inline);
+            return;
+        }
+        if branch.condition.items.is_empty() {
+            self.out.push('_');
+        } else {
+            self.compact_select_block(&branch.condition, " => {".len());
+        }
+        self.out.push_str(" => ");
+        self.apply_block(&branch.apply);
+    }
+}
+
+fn is_compound_select(select: &ast::RuleSelect<'_>) -> bool {
+    matches!(select,
+        ast::RuleSelect::Not(_, _) |
+        ast::RuleSelect::ForAll(_, _, _) |
+        ast::RuleSelect::Optional(_)
+    )
+}
+
+fn is_compound_apply(apply: &ast::RuleApply<'_>) -> bool {
+    matches!(apply,
+        ast::RuleApply::Conditional(_) |
+        ast::RuleApply::Match(_, _) |
+        ast::RuleApply::ForEach(_)
+    )
+}
+
+fn inline_block<T, F>(items: &[T], item_inline: F) -> String
+where
+    F: Fn(&T) -> String,
+{
+    if items.is_empty() {
+        "{}".into()
+    } else {
+        let items = items.iter().map(item_inline).collect::<Vec<_>>();
+        format!("{{ {} }}", items.join(", "))
+    }
+}
+
+fn select_inline(select: &ast::RuleSelect<'_>) -> String {
+    match select {
+        ast::RuleSelect::Binding(spec) =>
+            format!("{}: {}", variable(&spec.variable), value_spec(&spec.value_spec)),
+        ast::RuleSelect::BindingAttribute(spec) =>
+            binding_attribute_spec(spec),
+        ast::RuleSelect::Comparison(comparison) =>
+            format!(
+                "{} {} {}",
+                comparable(&comparison.left),
+                compare_op(comparison.ordering),
+                comparable(&comparison.right),
+            ),
+        ast::RuleSelect::Not(selects, _) =>
+            format!("not {}", inline_block(&selects.items, select_inline)),
+        ast::RuleSelect::ForAll(premise, conclusion, _) =>
+            format!(
+                "forall {} => {}",
+                inline_block(&premise.items, select_inline),
+                inline_block(&conclusion.items, select_inline),
+            ),
+        ast::RuleSelect::Optional(optional) =>
+            format!(
+                "optional {} else {}",
+                inline_block(&optional.selects.items, select_inline),
+                inline_block(&optional.defaults.items, optional_default_inline),
+            ),
+        ast::RuleSelect::Calculation(var, calc, _) =>
+            format!("{} is {}", variable(var), calculation(calc, CalcPrecedence::Member)),
+        ast::RuleSelect::Membership(membership) =>
+            format!("{} in {}", enumerable(&membership.item), variable(&membership.tuple)),
+    }
+}
+
+fn optional_default_inline(default: &ast::OptionalDefault<'_>) -> String {
+    format!("{}: {}", variable(&default.variable), literal(&default.value))
+}
+
+fn apply_inline(apply: &ast::RuleApply<'_>) -> String {
+    match apply {
+        ast::RuleApply::Add(spec) =>
+            format!("+ {}", binding_attribute_spec(spec)),
+        ast::RuleApply::Set(spec) =>
+            format!("= {}", binding_attribute_spec(spec)),
+        ast::RuleApply::Remove(spec, mode) => {
+            let operator = match mode {
+                RemovalMode::Required => '-',
+                RemovalMode::Optional => '!',
+            };
+            format!("{} {}", operator, binding_attribute_spec(spec))
+        },
+        ast::RuleApply::Clear(spec) => match &spec.attribute {
+            Some((path, name)) =>
+                format!("clear {}.{}", variable(&spec.variable), attribute_path(path, name)),
+            None =>
+                format!("clear {}", variable(&spec.variable)),
+        },
+        ast::RuleApply::Delete(var, mode, _) => {
+            let keyword = match mode {
+                DeletionMode::Object => "delete",
+                DeletionMode::WithReferences => "purge",
+            };
+            format!("{} {}", keyword, variable(var))
+        },
+        ast::RuleApply::Emit(spec) =>
+            format!("emit {}", value_spec(spec)),
+        ast::RuleApply::Halt(None, _) =>
+            "halt".into(),
+        ast::RuleApply::Halt(Some(spec), _) =>
+            format!("halt {}", value_spec(spec)),
+        ast::RuleApply::Run(spec) => {
+            let inputs = spec.inputs.iter().map(variable).collect::<Vec<_>>();
+            let mode = match spec.mode {
+                SystemRunMode::ToFirst => "first ",
+                SystemRunMode::Saturation => "",
+            };
+            format!("run {}{}({})", mode, spec.system.as_str(), inputs.join(", "))
+        },
+        ast::RuleApply::Conditional(conditional) => {
+            let mut inline = format!(
+                "if {} then {}",
+                inline_block(&conditional.condition.items, select_inline),
+                inline_block(&conditional.then_apply.items, apply_inline),
+            );
+            if let Some(otherwise_apply) = &conditional.otherwise_apply {
+                if !otherwise_apply.items.is_empty() {
+                    inline.push_str(" else ");
+                    inline.push_str(&inline_block(&otherwise_apply.items, apply_inline));
+                }
+            }
+            inline
+        },
+        ast::RuleApply::Match(branches, _) =>
+            format!("match {}", inline_block(&branches.items, match_branch_inline)),
+        ast::RuleApply::ForEach(for_each) =>
+            format!(
+                "for {} do {}",
+                inline_block(&for_each.selection.items, select_inline),
+                inline_block(&for_each.apply.items, apply_inline),
+            ),
+    }
+}
+
+fn match_branch_inline(branch: &ast::MatchBranch<'_>) -> String {
+    let condition = if branch.condition.items.is_empty() {
+        "_".into()
+    } else {
+        inline_block(&branch.condition.items, select_inline)
+    };
+    format!("{} => {}", condition, inline_block(&branch.apply.items, apply_inline))
+}
+
+fn binding_attribute_spec(spec: &ast::BindingAttributeSpec<'_>) -> String {
+    format!("{}.{}", variable(&spec.variable), attribute_spec(&spec.attribute_spec))
+}
+
+fn attribute_spec(spec: &ast::AttributeSpec<'_>) -> String {
+    let mut source = attribute_path(&spec.path, &spec.attribute);
+    if let Some(closure) = &spec.closure {
+        source.push(match closure.mode {
+            ClosureMode::OneOrMore => '+',
+            ClosureMode::ZeroOrMore => '*',
+        });
+        match (closure.max_depth, &closure.depth) {
+            (Some(max_depth), Some(depth)) =>
+                { let _ = write!(source, "({}, {})", max_depth, variable(depth)); },
+            (Some(max_depth), None) =>
+                { let _ = write!(source, "({})", max_depth); },
+            (None, Some(depth)) =>
+                { let _ = write!(source, "({})", variable(depth)); },
+            (None, None) => (),
+        }
+    }
+    let _ = write!(source, ": {}", value_spec(&spec.value_spec));
+    source
+}
+
+fn attribute_path(path: &[ast::Ident<'_>], name: &ast::AttributeName<'_>) -> String {
+    let mut parts = path.iter().map(|ident| ident.as_str().to_string()).collect::<Vec<_>>();
+    parts.push(match name {
+        ast::AttributeName::Fixed(ident) => ident.as_str().into(),
+        ast::AttributeName::Variable(var) => variable(var),
+    });
+    parts.join(".")
+}
+
+fn value_spec(spec: &ast::ValueSpec<'_>) -> String {
+    match &spec.kind {
+        ast::ValueSpecKind::Literal(lit) => literal(lit),
+        ast::ValueSpecKind::Variable(var) => variable(var),
+        ast::ValueSpecKind::Tuple(bindable) => {
+            let items = bindable.inner.iter().map(value_spec).collect::<Vec<_>>();
+            format!("{}[{}]", bindable_prefix(&bindable.variable), items.join(", "))
+        },
+        ast::ValueSpecKind::Enum(bindable) => {
+            let options = bindable.inner.iter().map(enumerable).collect::<Vec<_>>();
+            format!("{}{}", bindable_prefix(&bindable.variable), options.join(" | "))
+        },
+        ast::ValueSpecKind::Struct(bindable) => {
+            let attributes = inline_block(&bindable.inner, attribute_spec);
+            format!("{}{}", bindable_prefix(&bindable.variable), attributes)
+        },
+        ast::ValueSpecKind::Rest(ast::Variable::Wildcard) => "..".into(),
+        ast::ValueSpecKind::Rest(var) => format!("..{}", variable(var)),
+        ast::ValueSpecKind::Calculation(calc) => calculation(calc, CalcPrecedence::Member),
+    }
+}
+
+fn bindable_prefix(var: &ast::Variable<'_>) -> String {
+    match var {
+        ast::Variable::Wildcard => String::new(),
+        ast::Variable::Ident(_) => format!("{} @ ", variable(var)),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum CalcPrecedence {
+    Member,
+    Concat,
+    AddSub,
+    MulDiv,
+    Postfix,
+    Terminal,
+}
+
+fn calculation_precedence(calc: &ast::Calculation<'_>) -> CalcPrecedence {
+    match calc {
+        ast::Calculation::Member(_, _) => CalcPrecedence::Member,
+        ast::Calculation::Concat(_, _) => CalcPrecedence::Concat,
+        ast::Calculation::BimOp(ArithBinOp::Add, _, _) |
+        ast::Calculation::BimOp(ArithBinOp::Sub, _, _) => CalcPrecedence::AddSub,
+        ast::Calculation::BimOp(ArithBinOp::Mul, _, _) |
+        ast::Calculation::BimOp(ArithBinOp::Div, _, _) => CalcPrecedence::MulDiv,
+        ast::Calculation::Index(_, _) |
+        ast::Calculation::Slice(_, _, _) => CalcPrecedence::Postfix,
+        ast::Calculation::Int(_) |
+        ast::Calculation::Float(_) |
+        ast::Calculation::Symbol(_) |
+        ast::Calculation::Variable(_) |
+        ast::Calculation::Tuple(_) |
+        ast::Calculation::Call(_, _) => CalcPrecedence::Terminal,
+    }
+}
+
+fn calculation(calc: &ast::Calculation<'_>, required: CalcPrecedence) -> String {
+    let source = match calc {
+        ast::Calculation::Int(value) => value.to_string(),
+        ast::Calculation::Float(value) => float(*value),
+        ast::Calculation::Symbol(ident) => ident.as_str().into(),
+        ast::Calculation::Variable(var) => variable(var),
+        ast::Calculation::Tuple(items) => {
+            let items = items
+                .iter()
+                .map(|item| calculation(item, CalcPrecedence::Member))
+                .collect::<Vec<_>>();
+            format!("[{}]", items.join(", "))
+        },
+        ast::Calculation::Call(function, arguments) => {
+            let arguments = arguments
+                .iter()
+                .map(|argument| calculation(argument, CalcPrecedence::Member))
+                .collect::<Vec<_>>();
+            format!("{}({})", function.as_str(), arguments.join(", "))
+        },
+        ast::Calculation::Index(value, index) =>
+            format!(
+                "{}[{}]",
+                calculation(value, CalcPrecedence::Postfix),
+                calculation(index, CalcPrecedence::Member),
+            ),
+        ast::Calculation::Slice(value, start, end) => {
+            let bound = |bound: &Option<Box<ast::Calculation<'_>>>| bound
+                .as_ref()
+                .map(|bound| calculation(bound, CalcPrecedence::Member))
+                .unwrap_or_default();
+            format!(
+                "{}[{}..{}]",
+                calculation(value, CalcPrecedence::Postfix),
+                bound(start),
+                bound(end),
+            )
+        },
+        ast::Calculation::BimOp(op, left, right) => {
+            let (operator, precedence) = match op {
+                ArithBinOp::Add => ('+', CalcPrecedence::AddSub),
+                ArithBinOp::Sub => ('-', CalcPrecedence::AddSub),
+                ArithBinOp::Mul => ('*', CalcPrecedence::MulDiv),
+                ArithBinOp::Div => ('/', CalcPrecedence::MulDiv),
+            };
+            let right_precedence = match precedence {
+                CalcPrecedence::AddSub => CalcPrecedence::MulDiv,
+                _ => CalcPrecedence::Postfix,
+            };
+            format!(
+                "{} {} {}",
+                calculation(left, precedence),
+                operator,
+                calculation(right, right_precedence),
+            )
+        },
+        ast::Calculation::Concat(left, right) =>
+            format!(
+                "{} ++ {}",
+                calculation(left, CalcPrecedence::Concat),
+                calculation(right, CalcPrecedence::AddSub),
+            ),
+        ast::Calculation::Member(item, tuple) =>
+            format!(
+                "{} in {}",
+                calculation(item, CalcPrecedence::Concat),
+                calculation(tuple, CalcPrecedence::Concat),
+            ),
+    };
+    if calculation_precedence(calc) < required {
+        format!("({})", source)
+    } else {
+        source
+    }
+}
+
+fn comparable(comparable: &ast::Comparable<'_>) -> String {
+    match comparable {
+        ast::Comparable::Int(value) => value.to_string(),
+        ast::Comparable::Float(value) => float(*value),
+        ast::Comparable::Variable(var) => variable(var),
+    }
+}
+
+pub(crate) fn compare_op(op: CompareOp) -> &'static str {
+    match op {
+        CompareOp::Equal => "==",
+        CompareOp::NotEqual => "!=",
+        CompareOp::Less => "<",
+        CompareOp::LessOrEqual => "<=",
+        CompareOp::Greater => ">",
+        CompareOp::GreaterOrEqual => ">=",
+    }
+}
+
+fn enumerable(enumerable: &ast::Enumerable<'_>) -> String {
+    match enumerable {
+        ast::Enumerable::Literal(lit) => literal(lit),
+        ast::Enumerable::Variable(var) => variable(var),
+    }
+}
+
+fn literal(literal: &ast::Literal<'_>) -> String {
+    match literal {
+        ast::Literal::Symbol(ident) => ident.as_str().into(),
+        ast::Literal::Int(value) => value.to_string(),
+        ast::Literal::Float(value) => float(*value),
+    }
+}
+
+pub(crate) fn float(value: f64) -> String {
+    let source = value.to_string();
+    if source.contains('.') {
+        source
+    } else {
+        format!("{}.0", source)
+    }
+}
+
+fn variable(var: &ast::Variable<'_>) -> String {
+    match var {
+        ast::Variable::Wildcard => "$".into(),
+        ast::Variable::Ident(ident) => format!("${}", ident.as_str()),
+    }
+}
+
+fn offset(span: &Span<'_>) -> usize {
+    span.location_offset()
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct SourceRange {
+    start: Option<Location>,
+    end: Option<Location>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Location {
+    offset: usize,
+    line: u32,
+}
+
+impl SourceRange {
+
+    fn from_span(span: &Span<'_>) -> Self {
+        let mut range = Self::default();
+        range.add(span);
+        range
+    }
+
+    fn add(&mut self, span: &Span<'_>) {
+        let start = Location { offset: offset(span), line: span.location_line() };
+        let end = Location {
+            offset: start.offset + span.fragment().len(),
+            line: start.line + span.fragment().matches('\n').count() as u32,
+        };
+        self.start = Some(self.start.map_or(start, |current| current.min(start)));
+        self.end = Some(self.end.map_or(end, |current| current.max(end)));
+    }
+
+    fn extend(&mut self, other: SourceRange) {
+        if let Some(start) = other.start {
+            self.start = Some(self.start.map_or(start, |current| current.min(start)));
+        }
+        if let Some(end) = other.end {
+            self.end = Some(self.end.map_or(end, |current| current.max(end)));
+        }
+    }
+
+    fn add_block<T, R>(&mut self, block: &ast::Block<'_, T>, range: R)
+    where
+        R: Fn(&T) -> SourceRange,
+    {
+        block.items.iter().for_each(|item| self.extend(range(item)));
+        self.add(&block.end);
+    }
+}
+
+fn select_range(select: &ast::RuleSelect<'_>) -> SourceRange {
+    let mut range = SourceRange::default();
+    match select {
+        ast::RuleSelect::Binding(spec) => {
+            range.add(&spec.position);
+            range.extend(value_spec_range(&spec.value_spec));
+        },
+        ast::RuleSelect::BindingAttribute(spec) => {
+            range.add(&spec.position);
+            range.extend(value_spec_range(&spec.attribute_spec.value_spec));
+        },
+        ast::RuleSelect::Comparison(comparison) => range.add(&comparison.position),
+        ast::RuleSelect::Not(selects, position) => {
+            range.add(position);
+            range.add_block(selects, select_range);
+        },
+        ast::RuleSelect::ForAll(premise, conclusion, position) => {
+            range.add(position);
+            range.add_block(premise, select_range);
+            range.add_block(conclusion, select_range);
+        },
+        ast::RuleSelect::Optional(optional) => {
+            range.add(&optional.position);
+            range.add_block(&optional.selects, select_range);
+            range.add_block(
+                &optional.defaults,
+                |default| SourceRange::from_span(&default.position),
+            );
+        },
+        ast::RuleSelect::Calculation(_, _, position) => range.add(position),
+        ast::RuleSelect::Membership(membership) => range.add(&membership.position),
+    }
+    range
+}
+
+fn apply_range(apply: &ast::RuleApply<'_>) -> SourceRange {
+    let mut range = SourceRange::default();
+    match apply {
+        ast::RuleApply::Add(spec) |
+        ast::RuleApply::Set(spec) |
+        ast::RuleApply::Remove(spec, _) => {
+            range.add(&spec.position);
+            range.extend(value_spec_range(&spec.attribute_spec.value_spec));
+        },
+        ast::RuleApply::Clear(spec) => range.add(&spec.position),
+        ast::RuleApply::Delete(_, _, position) => range.add(position),
+        ast::RuleApply::Emit(spec) => range.extend(value_spec_range(spec)),
+        ast::RuleApply::Halt(reason, position) => {
+            range.add(position);
+            if let Some(spec) = reason {
+                range.extend(value_spec_range(spec));
+            }
+        },
+        ast::RuleApply::Run(spec) => range.add(&spec.position),
+        ast::RuleApply::Conditional(conditional) => {
+            range.add(&conditional.position);
+            range.add_block(&conditional.condition, select_range);
+            range.add_block(&conditional.then_apply, apply_range);
+            if let Some(otherwise_apply) = &conditional.otherwise_apply {
+                range.add_block(otherwise_apply, apply_range);
+            }
+        },
+        ast::RuleApply::Match(branches, position) => {
+            range.add(position);
+            range.add_block(branches, match_branch_range);
+        },
+        ast::RuleApply::ForEach(for_each) => {
+            range.add(&for_each.position);
+            range.add_block(&for_each.selection, select_range);
+            range.add_block(&for_each.apply, apply_range);
+        },
+    }
+    range
+}
+
+fn match_branch_range(branch: &ast::MatchBranch<'_>) -> SourceRange {
+    let mut range = SourceRange::from_span(&branch.position);
+    range.add_block(&branch.condition, select_range);
+    range.add_block(&branch.apply, apply_range);
+    range
+}
+
+fn value_spec_range(spec: &ast::ValueSpec<'_>) -> SourceRange {
+    let mut range = SourceRange::from_span(&spec.position);
+    match &spec.kind {
+        ast::ValueSpecKind::Tuple(bindable) => {
+            bindable.inner.iter().for_each(|item| range.extend(value_spec_range(item)));
+        },
+        ast::ValueSpecKind::Struct(bindable) => {
+            bindable.inner.iter().for_each(|attribute| {
+                range.add(&attribute.position);
+                range.extend(value_spec_range(&attribute.value_spec));
+            });
+        },
+        _ => (),
+    }
+    range
+}
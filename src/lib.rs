@@ -7,6 +7,7 @@ mod ast;
 mod system;
 mod compiler;
 mod runtime;
+mod format;
 
 pub use data::{
     Value,
@@ -24,11 +25,16 @@ pub use space::{
     Transaction,
     AttributesIter,
     ValuesIter,
+    NamesIter,
+    Statistics,
+    AttributeStatistics,
 };
 
 pub use system::{
     System,
+    SystemRegistry,
     SystemLoader,
+    SharedPrefix,
     SystemError,
     RuntimeError,
     LoadError,
@@ -41,6 +47,7 @@ pub use system::{
 
 pub use compiler::{
     CompileError,
+    DecompileError,
     SelectBuilder,
     TupleBuilder,
     EnumBuilder,
@@ -51,8 +58,16 @@ pub use compiler::{
     ApplyBuilder,
     ApplyTupleBuilder,
     RemovalMode,
+    DeletionMode,
+    SystemRunMode,
+    ClosureMode,
 };
 
 pub use runtime::{
     RuntimeControl,
+    Matcher,
+};
+
+pub use format::{
+    format_source,
 };
\ No newline at end of file
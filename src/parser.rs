@@ -1,6 +1,6 @@
 
 use crate::data::{ArithBinOp, CompareOp};
-use crate::{ast, RemovalMode};
+use crate::{ast, RemovalMode, DeletionMode, SystemRunMode, ClosureMode};
 use nom_locate::{position};
 
 mod nc {
@@ -18,13 +18,15 @@ pub type Span<'a> = nom_locate::LocatedSpan<&'a str>;
 pub type Error<'a> = nom_greedyerror::GreedyError<Span<'a>, nom::error::ErrorKind>;
 pub type Parsed<'a, T> = nom::IResult<Span<'a>, T, Error<'a>>;
 
+const END_MARKER: &str = "__END__";
+
 // main
 
-pub fn parse(input: &str) -> Result<Vec<ast::Rule<'_>>, String> {
+pub fn parse(input: &str) -> Result<ast::Document<'_>, String> {
     let input = Span::new(input);
     match document(input) {
-        Ok((_, rules)) =>
-            Ok(rules),
+        Ok((_, items)) =>
+            Ok(ast::Document { items, comments: comments(input) }),
         Err(nom::Err::Error(err)) | Err(nom::Err::Failure(err)) =>
             Err(nom_greedyerror::convert_error(input, err)),
         Err(nom::Err::Incomplete(_)) =>
@@ -33,8 +35,12 @@ pub fn parse(input: &str) -> Result<Vec<ast::Rule<'_>>, String> {
 }
 
 pub fn is_variable_ident(input: &str) -> bool {
-    let input = Span::new(input);
-    nc::complete(nc::all_consuming(ident))(input).is_ok()
+    let span = Span::new(input);
+    !is_reserved_ident(input) && nc::complete(nc::all_consuming(ident))(span).is_ok()
+}
+
+pub fn is_reserved_ident(input: &str) -> bool {
+    input == END_MARKER
 }
 
 pub fn is_path(input: &str) -> bool {
@@ -42,15 +48,41 @@ pub fn is_path(input: &str) -> bool {
     nc::complete(nc::all_consuming(path))(input).is_ok()
 }
 
-fn document(input: Span<'_>) -> Parsed<'_, Vec<ast::Rule<'_>>> {
+fn document(input: Span<'_>) -> Parsed<'_, Vec<ast::DocumentItem<'_>>> {
     nc::complete(nc::all_consuming(
         nc::preceded(
             nc::opt(ws_or_comment),
-            nc::many0(wsc_after(rule)),
+            nc::many0(wsc_after(nc::alt((
+                nc::map(system_declaration, ast::DocumentItem::System),
+                nc::map(rule, ast::DocumentItem::Rule),
+            )))),
         ),
     ))(input)
 }
 
+fn comments(input: Span<'_>) -> Vec<ast::Comment<'_>> {
+    let mut comments = Vec::new();
+    let mut rest = input;
+    let mut after_ident = false;
+    while !rest.fragment().is_empty() {
+        let comment = if after_ident {
+            nc::recognize(nc::alt((comment_sl, comment_ml)))(rest)
+        } else {
+            nc::recognize(nc::alt((comment_sl, comment_ml, comment_rest)))(rest)
+        };
+        if let Ok((next, span)) = comment {
+            comments.push(ast::Comment { span });
+            rest = next;
+            after_ident = false;
+            continue;
+        }
+        let (next, c) = nc::anychar::<_, Error<'_>>(rest).expect("remaining character");
+        after_ident = c.is_alphanumeric() || c == '_';
+        rest = next;
+    }
+    comments
+}
+
 // non-significant parses
 
 fn comment_sl(input: Span<'_>) -> Parsed<'_, ()> {
@@ -65,7 +97,7 @@ fn comment_rest(input: Span<'_>) -> Parsed<'_, ()> {
     nc::value(
         (),
         nc::pair(
-            nc::verify(ident, |value| value.as_str() == "__END__"),
+            nc::verify(ident, |value| value.as_str() == END_MARKER),
             nc::rest,
         ),
     )(input)
@@ -135,14 +167,17 @@ where
     )
 }
 
-fn block<'r, R, F>(inner: F) -> impl FnMut(Span<'r>) -> Parsed<'r, Vec<R>>
+fn block<'r, R, F>(inner: F) -> impl FnMut(Span<'r>) -> Parsed<'r, ast::Block<'r, R>>
 where
     F: FnMut(Span<'r>) -> Parsed<'r, R>,
 {
-    delimited_cut(
-        nc::char('{'),
-        wsc(comma_sep0(inner)),
-        nc::char('}'),
+    nc::map(
+        delimited_cut(
+            nc::char('{'),
+            nc::pair(wsc(comma_sep0(inner)), nc::peek(nc::recognize(nc::char('}')))),
+            nc::char('}'),
+        ),
+        |(items, end)| ast::Block { items, end },
     )
 }
 
@@ -200,7 +235,7 @@ fn float(input: Span<'_>) -> Parsed<'_, f64> {
                 nc::many1_count(nc::digit1),
                 nc::many0_count(nc::pair(nc::char('_'), nc::many1_count(nc::digit1))),
             ),
-            nc::char('.'),
+            nc::terminated(nc::char('.'), nc::not(nc::char('.'))),
             nc::cut(nc::pair(
                 nc::many1_count(nc::digit1),
                 nc::many0_count(nc::pair(nc::char('_'), nc::many1_count(nc::digit1))),
@@ -311,16 +346,65 @@ fn attribute_spec(input: Span<'_>) -> Parsed<'_, ast::AttributeSpec<'_>> {
     nc::map(
         nc::tuple((
             position,
-            ident,
+            attribute_path,
+            nc::opt(closure),
             nc::preceded(
                 wsc(nc::char(':')),
                 nc::cut(value_spec),
             ),
         )),
-        |(position, attribute, value_spec)| ast::AttributeSpec { position, attribute, value_spec },
+        |(position, (path, attribute), closure, value_spec)| {
+            ast::AttributeSpec { position, path, attribute, closure, value_spec }
+        },
     )(input)
 }
 
+fn closure(input: Span<'_>) -> Parsed<'_, ast::Closure<'_>> {
+    nc::map(
+        nc::pair(
+            nc::alt((
+                nc::value(ClosureMode::OneOrMore, nc::char('+')),
+                nc::value(ClosureMode::ZeroOrMore, nc::char('*')),
+            )),
+            nc::opt(delimited_cut(
+                nc::char('('),
+                wsc(nc::alt((
+                    nc::map(
+                        nc::separated_pair(int, wsc(nc::char(',')), variable),
+                        |(max_depth, depth)| (Some(max_depth), Some(depth)),
+                    ),
+                    nc::map(int, |max_depth| (Some(max_depth), None)),
+                    nc::map(variable, |depth| (None, Some(depth))),
+                ))),
+                nc::char(')'),
+            )),
+        ),
+        |(mode, limits)| {
+            let (max_depth, depth) = limits.unwrap_or((None, None));
+            ast::Closure { mode, max_depth, depth }
+        },
+    )(input)
+}
+
+fn attribute_path(input: Span<'_>) -> Parsed<'_, (Vec<ast::Ident<'_>>, ast::AttributeName<'_>)> {
+    nc::alt((
+        nc::map(variable, |variable| (Vec::new(), ast::AttributeName::Variable(variable))),
+        nc::map(
+            nc::pair(
+                nc::separated_list1(wsc(nc::char('.')), ident),
+                nc::opt(nc::preceded(wsc(nc::char('.')), variable)),
+            ),
+            |(mut path, variable)| match variable {
+                Some(variable) => (path, ast::AttributeName::Variable(variable)),
+                None => {
+                    let ident = path.pop().expect("at least one attribute in path");
+                    (path, ast::AttributeName::Fixed(ident))
+                },
+            },
+        ),
+    ))(input)
+}
+
 fn value_spec(input: Span<'_>) -> Parsed<'_, ast::ValueSpec<'_>> {
     nc::map(
         nc::pair(position, nc::alt((
@@ -331,10 +415,13 @@ fn value_spec(input: Span<'_>) -> Parsed<'_, ast::ValueSpec<'_>> {
                     let variable_enum = variable_tuple.clone();
                     let variable_struct = variable_tuple.clone();
                     nc::alt((
-                        nc::map(value_spec_tuple, move |inner| ast::ValueSpecKind::Tuple(ast::Bindable {
-                            variable: variable_tuple.clone(),
-                            inner,
-                        })),
+                        nc::map(
+                            nc::terminated(value_spec_tuple, nc::not(calculation_operator)),
+                            move |inner| ast::ValueSpecKind::Tuple(ast::Bindable {
+                                variable: variable_tuple.clone(),
+                                inner,
+                            }),
+                        ),
                         nc::map(value_spec_enum, move |inner| ast::ValueSpecKind::Enum(ast::Bindable {
                             variable: variable_enum.clone(),
                             inner,
@@ -343,12 +430,16 @@ fn value_spec(input: Span<'_>) -> Parsed<'_, ast::ValueSpec<'_>> {
                             block(attribute_spec),
                             move |inner| ast::ValueSpecKind::Struct(ast::Bindable {
                                 variable: variable_struct.clone(),
-                                inner,
+                                inner: inner.items,
                             }),
                         ),
                     ))
                 },
             ),
+            nc::map(
+                nc::verify(calculation, is_compound_calculation),
+                ast::ValueSpecKind::Calculation,
+            ),
             nc::map(variable, ast::ValueSpecKind::Variable),
             nc::map(literal, ast::ValueSpecKind::Literal),
         ))),
@@ -356,6 +447,28 @@ fn value_spec(input: Span<'_>) -> Parsed<'_, ast::ValueSpec<'_>> {
     )(input)
 }
 
+fn is_compound_calculation(calculation: &ast::Calculation<'_>) -> bool {
+    !matches!(calculation,
+        ast::Calculation::Int(_) |
+        ast::Calculation::Float(_) |
+        ast::Calculation::Symbol(_) |
+        ast::Calculation::Variable(_) |
+        ast::Calculation::Tuple(_)
+    )
+}
+
+fn calculation_operator(input: Span<'_>) -> Parsed<'_, ()> {
+    nc::value((), wsc_before(nc::alt((
+        nc::tag("++"),
+        nc::tag("+"),
+        nc::tag("-"),
+        nc::tag("*"),
+        nc::tag("/"),
+        nc::tag("["),
+        nc::recognize(keyword("in")),
+    ))))(input)
+}
+
 fn value_spec_enumerable(input: Span<'_>) -> Parsed<'_, ast::Enumerable<'_>> {
     nc::alt((
         nc::map(literal, ast::Enumerable::Literal),
@@ -385,17 +498,42 @@ fn value_spec_enum(input: Span<'_>) -> Parsed<'_, Vec<ast::Enumerable<'_>>> {
 fn value_spec_tuple(input: Span<'_>) -> Parsed<'_, Vec<ast::ValueSpec<'_>>> {
     delimited_cut(
         nc::char('['),
-        comma_sep0(value_spec),
+        comma_sep0(nc::alt((value_spec_tuple_rest, value_spec))),
         nc::char(']'),
     )(input)
 }
 
+fn value_spec_tuple_rest(input: Span<'_>) -> Parsed<'_, ast::ValueSpec<'_>> {
+    nc::map(
+        nc::pair(
+            position,
+            nc::preceded(nc::tag(".."), nc::opt(variable)),
+        ),
+        |(position, variable)| ast::ValueSpec {
+            position,
+            kind: ast::ValueSpecKind::Rest(variable.unwrap_or(ast::Variable::Wildcard)),
+        },
+    )(input)
+}
+
+fn calculation_concat(input: Span<'_>) -> Parsed<'_, ast::Calculation<'_>> {
+    let (input, first) = calculation_add_sub(input)?;
+    nc::fold_many0(
+        nc::preceded(
+            wsc(nc::tag("++")),
+            nc::cut(calculation_add_sub),
+        ),
+        first,
+        |left, right| ast::Calculation::Concat(Box::new(left), Box::new(right)),
+    )(input)
+}
+
 fn calculation_add_sub(input: Span<'_>) -> Parsed<'_, ast::Calculation<'_>> {
     let (input, first) = calculation_mul_div(input)?;
     nc::fold_many0(
         nc::pair(
             wsc(nc::alt((
-                nc::value(ArithBinOp::Add, nc::char('+')),
+                nc::value(ArithBinOp::Add, nc::terminated(nc::char('+'), nc::not(nc::char('+')))),
                 nc::value(ArithBinOp::Sub, nc::char('-')),
             ))),
             nc::cut(calculation_mul_div),
@@ -406,62 +544,191 @@ fn calculation_add_sub(input: Span<'_>) -> Parsed<'_, ast::Calculation<'_>> {
 }
 
 fn calculation_mul_div(input: Span<'_>) -> Parsed<'_, ast::Calculation<'_>> {
-    let (input, first) = calculation_terminal(input)?;
+    let (input, first) = calculation_postfix(input)?;
     nc::fold_many0(
         nc::pair(
             wsc(nc::alt((
                 nc::value(ArithBinOp::Mul, nc::char('*')),
                 nc::value(ArithBinOp::Div, nc::char('/')),
             ))),
-            nc::cut(calculation_terminal),
+            nc::cut(calculation_postfix),
         ),
         first,
         |left, (op, right)| ast::Calculation::BimOp(op, Box::new(left), Box::new(right)),
     )(input)
 }
 
+enum CalculationAccess<'a> {
+    Index(ast::Calculation<'a>),
+    Slice(Option<ast::Calculation<'a>>, Option<ast::Calculation<'a>>),
+}
+
+fn calculation_access(input: Span<'_>) -> Parsed<'_, CalculationAccess<'_>> {
+    delimited_cut(
+        nc::char('['),
+        wsc(nc::alt((
+            nc::map(
+                nc::separated_pair(
+                    nc::opt(wsc_after(calculation)),
+                    nc::tag(".."),
+                    nc::opt(wsc_before(calculation)),
+                ),
+                |(start, end)| CalculationAccess::Slice(start, end),
+            ),
+            nc::map(calculation, CalculationAccess::Index),
+        ))),
+        nc::char(']'),
+    )(input)
+}
+
+fn calculation_postfix(input: Span<'_>) -> Parsed<'_, ast::Calculation<'_>> {
+    let (input, first) = calculation_terminal(input)?;
+    nc::fold_many0(
+        calculation_access,
+        first,
+        |value, access| match access {
+            CalculationAccess::Index(index) =>
+                ast::Calculation::Index(Box::new(value), Box::new(index)),
+            CalculationAccess::Slice(start, end) =>
+                ast::Calculation::Slice(Box::new(value), start.map(Box::new), end.map(Box::new)),
+        },
+    )(input)
+}
+
 fn calculation_terminal(input: Span<'_>) -> Parsed<'_, ast::Calculation<'_>> {
     nc::alt((
         nc::map(float, ast::Calculation::Float),
         nc::map(int, ast::Calculation::Int),
         nc::map(variable, ast::Calculation::Variable),
+        nc::map(
+            delimited_cut(nc::char('['), wsc(comma_sep0(calculation)), nc::char(']')),
+            ast::Calculation::Tuple,
+        ),
+        nc::map(
+            nc::pair(
+                ident,
+                delimited_cut(nc::char('('), wsc(comma_sep0(calculation)), nc::char(')')),
+            ),
+            |(function, arguments)| ast::Calculation::Call(function, arguments),
+        ),
+        nc::map(ident, ast::Calculation::Symbol),
         delimited_cut(nc::char('('), wsc(calculation), nc::char(')')),
     ))(input)
 }
 
+fn calculation_member(input: Span<'_>) -> Parsed<'_, ast::Calculation<'_>> {
+    let (input, item) = calculation_concat(input)?;
+    let (input, tuple) = nc::opt(nc::preceded(
+        wsc(keyword("in")),
+        nc::cut(calculation_concat),
+    ))(input)?;
+    let calculation = match tuple {
+        Some(tuple) => ast::Calculation::Member(Box::new(item), Box::new(tuple)),
+        None => item,
+    };
+    Ok((input, calculation))
+}
+
 fn calculation(input: Span<'_>) -> Parsed<'_, ast::Calculation<'_>> {
-    calculation_add_sub(input)
+    calculation_member(input)
+}
+
+fn membership(input: Span<'_>) -> Parsed<'_, ast::Membership<'_>> {
+    nc::map(
+        nc::tuple((
+            position,
+            value_spec_enumerable,
+            nc::preceded(
+                wsc(keyword("in")),
+                nc::cut(variable),
+            ),
+        )),
+        |(position, item, tuple)| ast::Membership { position, item, tuple },
+    )(input)
+}
+
+fn match_branch(input: Span<'_>) -> Parsed<'_, ast::MatchBranch<'_>> {
+    nc::map(
+        nc::tuple((
+            position,
+            nc::alt((
+                block(rule_select),
+                nc::map(nc::recognize(keyword("_")), |end| ast::Block { items: Vec::new(), end }),
+            )),
+            nc::preceded(
+                wsc(nc::tag("=>")),
+                block(rule_apply),
+            ),
+        )),
+        |(position, condition, apply)| ast::MatchBranch { position, condition, apply },
+    )(input)
 }
 
 fn rule_apply(input: Span<'_>) -> Parsed<'_, ast::RuleApply<'_>> {
     nc::alt((
         nc::map(
-            nc::preceded(
-                keyword("if"),
-                nc::tuple((
-                    wsc_before(block(rule_select)),
-                    nc::preceded(
-                        wsc(keyword("then")),
-                        block(rule_apply),
-                    ),
-                    nc::opt(nc::preceded(
-                        wsc(keyword("else")),
-                        block(rule_apply),
+            nc::pair(
+                position,
+                nc::preceded(
+                    keyword("if"),
+                    nc::tuple((
+                        wsc_before(block(rule_select)),
+                        nc::preceded(
+                            wsc(keyword("then")),
+                            block(rule_apply),
+                        ),
+                        nc::opt(nc::preceded(
+                            wsc(keyword("else")),
+                            block(rule_apply),
+                        )),
                     )),
-                )),
+                ),
             ),
-            |(condition, then_apply, otherwise_apply)| {
+            |(position, (condition, then_apply, otherwise_apply))| {
                 ast::RuleApply::Conditional(ast::ConditionalApply {
+                    position,
                     condition,
                     then_apply,
-                    otherwise_apply: otherwise_apply.unwrap_or_else(Vec::new),
+                    otherwise_apply,
                 })
             },
         ),
+        nc::map(
+            nc::pair(
+                position,
+                nc::preceded(
+                    keyword("match"),
+                    wsc_before(block(match_branch)),
+                ),
+            ),
+            |(position, branches)| ast::RuleApply::Match(branches, position),
+        ),
+        nc::map(
+            nc::pair(
+                position,
+                nc::preceded(
+                    keyword("for"),
+                    nc::pair(
+                        wsc_before(block(rule_select)),
+                        nc::preceded(
+                            wsc(keyword("do")),
+                            block(rule_apply),
+                        ),
+                    ),
+                ),
+            ),
+            |(position, (selection, apply))| {
+                ast::RuleApply::ForEach(ast::ForEachApply { position, selection, apply })
+            },
+        ),
         nc::map(
             nc::preceded(wsc_after(nc::char('+')), binding_attribute_spec),
             ast::RuleApply::Add,
         ),
+        nc::map(
+            nc::preceded(wsc_after(nc::char('=')), binding_attribute_spec),
+            ast::RuleApply::Set,
+        ),
         nc::map(
             nc::pair(
                 wsc_after(nc::alt((
@@ -472,6 +739,57 @@ fn rule_apply(input: Span<'_>) -> Parsed<'_, ast::RuleApply<'_>> {
             ),
             |(mode, spec)| ast::RuleApply::Remove(spec, mode),
         ),
+        nc::map(
+            nc::preceded(
+                wsc_after(keyword("clear")),
+                nc::cut(nc::tuple((
+                    position,
+                    variable,
+                    nc::opt(nc::preceded(wsc(nc::char('.')), attribute_path)),
+                ))),
+            ),
+            |(position, variable, attribute)| {
+                ast::RuleApply::Clear(ast::ClearSpec { position, variable, attribute })
+            },
+        ),
+        nc::map(
+            nc::pair(
+                wsc_after(nc::alt((
+                    nc::value(DeletionMode::Object, keyword("delete")),
+                    nc::value(DeletionMode::WithReferences, keyword("purge")),
+                ))),
+                nc::cut(nc::pair(position, variable)),
+            ),
+            |(mode, (position, variable))| ast::RuleApply::Delete(variable, mode, position),
+        ),
+        nc::map(
+            nc::preceded(wsc_after(keyword("emit")), nc::cut(value_spec)),
+            ast::RuleApply::Emit,
+        ),
+        nc::map(
+            nc::pair(position, nc::preceded(keyword("halt"), nc::opt(wsc_before(value_spec)))),
+            |(position, reason)| ast::RuleApply::Halt(reason, position),
+        ),
+        nc::map(
+            nc::preceded(
+                wsc_after(keyword("run")),
+                nc::cut(nc::tuple((
+                    position,
+                    nc::map(
+                        nc::opt(nc::terminated(keyword("first"), wsc(nc::peek(path)))),
+                        |first| match first {
+                            Some(()) => SystemRunMode::ToFirst,
+                            None => SystemRunMode::Saturation,
+                        },
+                    ),
+                    path,
+                    delimited_cut(nc::char('('), wsc(comma_sep0(variable)), nc::char(')')),
+                ))),
+            ),
+            |(position, mode, system, inputs)| {
+                ast::RuleApply::Run(ast::RunSpec { position, system, mode, inputs })
+            },
+        ),
     ))(input)
 }
 
@@ -492,29 +810,110 @@ fn rule_select(input: Span<'_>) -> Parsed<'_, ast::RuleSelect<'_>> {
             |(position, variable, calc)| ast::RuleSelect::Calculation(variable, calc, position),
         ),
         nc::map(
-            nc::preceded(
-                wsc_after(keyword("not")),
-                nc::cut(block(rule_select)),
+            nc::pair(
+                position,
+                nc::preceded(
+                    wsc_after(keyword("not")),
+                    nc::cut(block(rule_select)),
+                ),
+            ),
+            |(position, selects)| ast::RuleSelect::Not(selects, position),
+        ),
+        nc::map(
+            nc::pair(
+                position,
+                nc::preceded(
+                    wsc_after(keyword("forall")),
+                    nc::cut(nc::pair(
+                        block(rule_select),
+                        nc::preceded(
+                            wsc(nc::tag("=>")),
+                            block(rule_select),
+                        ),
+                    )),
+                ),
             ),
-            ast::RuleSelect::Not,
+            |(position, (premise, conclusion))| {
+                ast::RuleSelect::ForAll(premise, conclusion, position)
+            },
         ),
+        nc::map(
+            nc::pair(
+                position,
+                nc::preceded(
+                    wsc_after(keyword("optional")),
+                    nc::cut(nc::pair(
+                        block(rule_select),
+                        nc::preceded(
+                            wsc(keyword("else")),
+                            block(optional_default),
+                        ),
+                    )),
+                ),
+            ),
+            |(position, (selects, defaults))| {
+                ast::RuleSelect::Optional(ast::OptionalSelect { position, selects, defaults })
+            },
+        ),
+        nc::map(membership, ast::RuleSelect::Membership),
     ))(input)
 }
 
-fn rule(input: Span<'_>) -> Parsed<'_, ast::Rule<'_>> {
+fn optional_default(input: Span<'_>) -> Parsed<'_, ast::OptionalDefault<'_>> {
     nc::map(
-        nc::preceded(
-            wsc_after(keyword("rule")),
-            nc::cut(nc::tuple((
-                wsc_after(rule_identity),
-                block(rule_select),
-                nc::preceded(
-                    wsc(keyword("do")),
-                    block(rule_apply),
-                ),
-            ))),
+        nc::tuple((
+            position,
+            variable,
+            nc::preceded(
+                wsc(nc::char(':')),
+                nc::cut(literal),
+            ),
+        )),
+        |(position, variable, value)| ast::OptionalDefault { position, variable, value },
+    )(input)
+}
+
+fn system_declaration(input: Span<'_>) -> Parsed<'_, ast::SystemDeclaration<'_>> {
+    nc::map(
+        nc::pair(
+            position,
+            nc::preceded(
+                wsc_after(keyword("system")),
+                nc::cut(nc::terminated(
+                    nc::pair(
+                        path,
+                        wsc_before(delimited_cut(
+                            nc::char('('),
+                            wsc(comma_sep0(nc::preceded(nc::char('$'), ident))),
+                            nc::char(')'),
+                        )),
+                    ),
+                    wsc_before(nc::char(';')),
+                )),
+            ),
         ),
-        |((system_name, name), select, apply)| ast::Rule {
+        |(position, (name, inputs))| ast::SystemDeclaration { position, name, inputs },
+    )(input)
+}
+
+fn rule(input: Span<'_>) -> Parsed<'_, ast::Rule<'_>> {
+    nc::map(
+        nc::tuple((
+            position,
+            nc::preceded(
+                wsc_after(keyword("rule")),
+                nc::cut(nc::tuple((
+                    wsc_after(rule_identity),
+                    block(rule_select),
+                    nc::preceded(
+                        wsc(keyword("do")),
+                        block(rule_apply),
+                    ),
+                ))),
+            ),
+        )),
+        |(position, ((system_name, name), select, apply))| ast::Rule {
+            position,
             system_name,
             name,
             select,
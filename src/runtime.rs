@@ -1,7 +1,23 @@
 
+use std::sync::{Arc};
 use std::cmp::{Ordering};
+use std::collections::{BTreeSet};
 use num_traits::{ToPrimitive};
-use crate::{Value, Access, Transaction, ValuesIter, RemovalMode};
+use crate::{
+    Value,
+    Symbol,
+    Tuple,
+    Id,
+    Access,
+    Transaction,
+    Attributes,
+    MatchValue,
+    RemovalMode,
+    DeletionMode,
+    SystemRunMode,
+    SystemRegistry,
+    RuntimeError,
+};
 use crate::data::{CompareOp, ArithBinOp};
 use crate::compiler::{
     CompiledRule,
@@ -11,73 +27,200 @@ use crate::compiler::{
     ApplyTupleItem,
     EnumOption,
     Calculation,
+    ClosureMode,
+    AttributeClosure,
+    ClosureDepth,
+    Comparison,
 };
 
+mod incremental;
+mod shared;
+mod compiled;
+
+pub use incremental::{IncrementalMatcher};
+pub use shared::{SharedMatcher};
+pub use compiled::{CompiledSearch, CompiledMatcher};
+
+use incremental::{Reads};
+
+#[derive(Debug, Default)]
+pub struct ApplyEffects {
+    pub emitted: Vec<Value>,
+    pub halted: bool,
+    pub halt_reason: Option<Value>,
+}
+
+impl ApplyEffects {
+
+    pub fn clear(&mut self) {
+        self.emitted.clear();
+        self.halted = false;
+        self.halt_reason = None;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RunContext<'a> {
+    registry: Option<&'a SystemRegistry>,
+    depth: usize,
+}
+
+impl<'a> RunContext<'a> {
+
+    pub fn new(registry: Option<&'a SystemRegistry>) -> Self {
+        Self { registry, depth: 0 }
+    }
+
+    pub fn nested(registry: &'a SystemRegistry, depth: usize) -> Self {
+        Self { registry: Some(registry), depth }
+    }
+
+    fn run_system(
+        &self,
+        name: &str,
+        mode: SystemRunMode,
+        space: &mut dyn Access,
+        inputs: &[Id],
+        emitted: &mut Vec<Value>,
+    ) -> Result<bool, RuntimeError> {
+        match self.registry {
+            Some(registry) => registry.run_nested(name, mode, space, inputs, self.depth + 1, emitted),
+            None => Err(RuntimeError::UnknownSystem { name: name.into() }),
+        }
+    }
+}
+
 pub fn splinter_rule<'space, F>(
     rule: &CompiledRule,
     tx: &Transaction<'space>,
     bindings: &mut [Value],
+    context: RunContext<'_>,
     mut collect: F,
-) -> usize
+) -> Result<usize, RuntimeError>
 where
-    F: FnMut(Transaction<'space>) -> RuntimeControl,
+    F: FnMut(Transaction<'space>, ApplyEffects) -> RuntimeControl,
 {
     let mut count = 0;
+    let mut error = None;
     search_bindings(rule.ops(), tx, bindings, |bindings| {
         let mut new_tx = tx.clone();
-        if apply_changes(rule.apply_ops(), &mut new_tx, bindings) {
-            count += 1;
+        let mut effects = ApplyEffects::default();
+        let apply_ops = rule.apply_ops();
+        match apply_changes(apply_ops, &mut new_tx, tx, bindings, &mut effects, context) {
+            Ok(true) => {
+                count += 1;
 
-            #[cfg(feature = "tracing")]
-            tracing::trace!(rule = rule.name().as_ref(), outcome = "produced-transaction");
+                #[cfg(feature = "tracing")]
+                tracing::trace!(rule = rule.name().as_ref(), outcome = "produced-transaction");
 
-            collect(new_tx)
-        } else {
+                collect(new_tx, effects)
+            },
+            Ok(false) => {
 
-            #[cfg(feature = "tracing")]
-            tracing::trace!(rule = rule.name().as_ref(), outcome = "failed application");
+                #[cfg(feature = "tracing")]
+                tracing::trace!(rule = rule.name().as_ref(), outcome = "failed application");
 
-            RuntimeControl::Continue
+                RuntimeControl::Continue
+            },
+            Err(apply_error) => {
+                error = Some(apply_error);
+                RuntimeControl::Stop
+            },
         }
     });
-    count
+    match error {
+        Some(error) => Err(error),
+        None => Ok(count),
+    }
 }
 
 pub fn attempt_rule_firing(
     rule: &CompiledRule,
     space: &mut dyn Access,
     bindings: &mut [Value],
-) -> bool {
-    space.transaction(&mut |mut tx| {
-        if find_first_bindings(rule.ops(), &mut tx, bindings) {
-            if apply_changes(rule.apply_ops(), &mut tx, bindings) {
+    effects: &mut ApplyEffects,
+    context: RunContext<'_>,
+) -> Result<bool, RuntimeError> {
+    effects.clear();
+    if !find_first_bindings(rule.ops(), space, bindings) {
+
+        #[cfg(feature = "tracing")]
+        tracing::trace!(rule = rule.name().as_ref(), outcome = "failed match");
+
+        return Ok(false);
+    }
+    apply_rule(rule, space, bindings, effects, context)
+}
+
+fn apply_rule(
+    rule: &CompiledRule,
+    space: &mut dyn Access,
+    bindings: &mut [Value],
+    effects: &mut ApplyEffects,
+    context: RunContext<'_>,
+) -> Result<bool, RuntimeError> {
+    let mut error = None;
+    let fired = space.transaction(&mut |mut tx| {
+        let before = tx.outer();
+        match apply_changes(rule.apply_ops(), &mut tx, before, bindings, effects, context) {
+            Ok(true) => {
 
                 #[cfg(feature = "tracing")]
                 tracing::trace!(rule = rule.name().as_ref(), outcome = "applied");
 
                 Some(tx)
-            } else {
+            },
+            Ok(false) => {
 
                 #[cfg(feature = "tracing")]
                 tracing::trace!(rule = rule.name().as_ref(), outcome = "failed application");
 
                 None
-            }
-        } else {
-
-            #[cfg(feature = "tracing")]
-            tracing::trace!(rule = rule.name().as_ref(), outcome = "failed match");
-
-            None
+            },
+            Err(apply_error) => {
+                error = Some(apply_error);
+                None
+            },
         }
-    })
+    });
+    if !fired {
+        effects.clear();
+    }
+    match error {
+        Some(error) => Err(error),
+        None => Ok(fired),
+    }
 }
 
+// `before` is the space as it was before the apply block started
 fn apply_changes(
     apply_ops: &[OpApply],
     space: &mut dyn Access,
+    before: &dyn Access,
     bindings: &mut [Value],
-) -> bool {
+    effects: &mut ApplyEffects,
+    context: RunContext<'_>,
+) -> Result<bool, RuntimeError> {
+    // finding the references to a purged object means scanning every object
+    // in the space, so the purges of a block are collected and removed in a
+    // single scan once the block has been applied
+    let mut purged = Vec::new();
+    let applied = apply_block(apply_ops, space, before, bindings, effects, context, &mut purged)?;
+    if applied {
+        remove_references(space, &purged);
+    }
+    Ok(applied)
+}
+
+fn apply_block(
+    apply_ops: &[OpApply],
+    space: &mut dyn Access,
+    before: &dyn Access,
+    bindings: &mut [Value],
+    effects: &mut ApplyEffects,
+    context: RunContext<'_>,
+    purged: &mut Vec<Id>,
+) -> Result<bool, RuntimeError> {
     for op in apply_ops {
         match op {
             OpApply::CreateObject { binding } => {
@@ -94,12 +237,88 @@ fn apply_changes(
                     .collect();
                 bindings[binding.index()] = Value::Tuple(values);
             },
+            OpApply::Calculation { binding, operation } => {
+                if let Some(value) = perform_calculation(bindings, operation) {
+                    bindings[binding.index()] = value;
+                } else {
+                    return Ok(false);
+                }
+            },
+            OpApply::ClearAttributes { binding } => {
+                if let Some(id) = bindings[binding.index()].object() {
+                    space.attributes_mut(id).clear_all();
+                } else {
+                    return Ok(false);
+                }
+            },
+            OpApply::ClearAttribute { binding, attribute } => {
+                if let Some(id) = bindings[binding.index()].object() {
+                    space.attributes_mut(id).clear_named(attribute);
+                } else {
+                    return Ok(false);
+                }
+            },
+            OpApply::ClearVariableAttribute { binding, attribute_binding } => {
+                match (
+                    bindings[binding.index()].object(),
+                    bindings[attribute_binding.index()].symbol(),
+                ) {
+                    (Some(id), Some(attribute)) => {
+                        space.attributes_mut(id).clear_named(attribute);
+                    },
+                    _ => {
+                        return Ok(false);
+                    },
+                }
+            },
+            OpApply::DeleteObject { binding, mode } => {
+                if let Some(id) = bindings[binding.index()].object() {
+                    space.attributes_mut(id).clear_all();
+                    space.unregister_root(id);
+                    if *mode == DeletionMode::WithReferences {
+                        purged.push(id);
+                    }
+                } else {
+                    return Ok(false);
+                }
+            },
+            OpApply::EmitBinding { binding } => {
+                effects.emitted.push(bindings[binding.index()].clone());
+            },
+            OpApply::EmitValue { value } => {
+                effects.emitted.push(value.clone());
+            },
+            OpApply::RunSystem { system, mode, inputs } => {
+                let mut input_ids = Vec::with_capacity(inputs.len());
+                for input in inputs {
+                    match bindings[input.index()].object() {
+                        Some(id) => input_ids.push(id),
+                        None => {
+                            return Ok(false);
+                        },
+                    }
+                }
+                // nested systems must not see references to purged objects
+                remove_references(space, &std::mem::take(purged));
+                if !context.run_system(system, *mode, space, &input_ids, &mut effects.emitted)? {
+                    return Ok(false);
+                }
+            },
+            OpApply::Halt { reason } => {
+                if !effects.halted {
+                    effects.halted = true;
+                    effects.halt_reason = reason.as_ref().map(|reason| match reason {
+                        ApplyTupleItem::Value(value) => value.clone(),
+                        ApplyTupleItem::Binding(binding) => bindings[binding.index()].clone(),
+                    });
+                }
+            },
             OpApply::AddBindingAttribute { binding, attribute, value_binding } => {
                 if let Some(id) = bindings[binding.index()].object() {
                     space.attributes_mut(id)
                         .add(attribute.clone(), bindings[value_binding.index()].clone());
                 } else {
-                    return false;
+                    return Ok(false);
                 }
             },
             OpApply::RemoveBindingAttribute { binding, attribute, value_binding, mode } => {
@@ -108,11 +327,44 @@ fn apply_changes(
                         .remove_single(attribute, &bindings[value_binding.index()]);
                     if removed.is_none() {
                         if let RemovalMode::Required = mode {
-                            return false;
+                            return Ok(false);
                         }
                     }
                 } else {
-                    return false;
+                    return Ok(false);
+                }
+            },
+            OpApply::ResolveAttribute { binding, attribute, value_binding } => {
+                if let Some(id) = bindings[binding.index()].object() {
+                    let mut values = space.attributes(id).iter_named(attribute);
+                    match (values.next().and_then(Value::object), values.next()) {
+                        (Some(value_id), None) => {
+                            bindings[value_binding.index()] = Value::Object(value_id);
+                        },
+                        _ => {
+                            return Ok(false);
+                        },
+                    }
+                } else {
+                    return Ok(false);
+                }
+            },
+            OpApply::SetBindingAttribute { binding, attribute, value_binding } => {
+                if let Some(id) = bindings[binding.index()].object() {
+                    let mut attributes = space.attributes_mut(id);
+                    attributes.clear_named(attribute);
+                    attributes.add(attribute.clone(), bindings[value_binding.index()].clone());
+                } else {
+                    return Ok(false);
+                }
+            },
+            OpApply::SetValueAttribute { binding, attribute, value } => {
+                if let Some(id) = bindings[binding.index()].object() {
+                    let mut attributes = space.attributes_mut(id);
+                    attributes.clear_named(attribute);
+                    attributes.add(attribute.clone(), value.clone());
+                } else {
+                    return Ok(false);
                 }
             },
             OpApply::AddValueAttribute { binding, attribute, value } => {
@@ -120,7 +372,7 @@ fn apply_changes(
                     space.attributes_mut(id)
                         .add(attribute.clone(), value.clone());
                 } else {
-                    return false;
+                    return Ok(false);
                 }
             },
             OpApply::RemoveValueAttribute { binding, attribute, value, mode } => {
@@ -129,28 +381,151 @@ fn apply_changes(
                         .remove_single(attribute, value);
                     if removed.is_none() {
                         if let RemovalMode::Required = mode {
-                            return false;
+                            return Ok(false);
                         }
                     }
                 } else {
-                    return false;
+                    return Ok(false);
+                }
+            },
+            OpApply::AddVariableBindingAttribute { binding, attribute_binding, value_binding } => {
+                match (
+                    bindings[binding.index()].object(),
+                    bindings[attribute_binding.index()].to_symbol(),
+                ) {
+                    (Some(id), Some(attribute)) => {
+                        space.attributes_mut(id)
+                            .add(attribute, bindings[value_binding.index()].clone());
+                    },
+                    _ => {
+                        return Ok(false);
+                    },
+                }
+            },
+            OpApply::RemoveVariableBindingAttribute {
+                binding,
+                attribute_binding,
+                value_binding,
+                mode,
+            } => {
+                match (
+                    bindings[binding.index()].object(),
+                    bindings[attribute_binding.index()].symbol(),
+                ) {
+                    (Some(id), Some(attribute)) => {
+                        let removed = space.attributes_mut(id)
+                            .remove_single(attribute, &bindings[value_binding.index()]);
+                        if removed.is_none() {
+                            if let RemovalMode::Required = mode {
+                                return Ok(false);
+                            }
+                        }
+                    },
+                    _ => {
+                        return Ok(false);
+                    },
+                }
+            },
+            OpApply::AddVariableValueAttribute { binding, attribute_binding, value } => {
+                match (
+                    bindings[binding.index()].object(),
+                    bindings[attribute_binding.index()].to_symbol(),
+                ) {
+                    (Some(id), Some(attribute)) => {
+                        space.attributes_mut(id).add(attribute, value.clone());
+                    },
+                    _ => {
+                        return Ok(false);
+                    },
+                }
+            },
+            OpApply::SetVariableBindingAttribute { binding, attribute_binding, value_binding } => {
+                match (
+                    bindings[binding.index()].object(),
+                    bindings[attribute_binding.index()].to_symbol(),
+                ) {
+                    (Some(id), Some(attribute)) => {
+                        let mut attributes = space.attributes_mut(id);
+                        attributes.clear_named(&attribute);
+                        attributes.add(attribute, bindings[value_binding.index()].clone());
+                    },
+                    _ => {
+                        return Ok(false);
+                    },
+                }
+            },
+            OpApply::SetVariableValueAttribute { binding, attribute_binding, value } => {
+                match (
+                    bindings[binding.index()].object(),
+                    bindings[attribute_binding.index()].to_symbol(),
+                ) {
+                    (Some(id), Some(attribute)) => {
+                        let mut attributes = space.attributes_mut(id);
+                        attributes.clear_named(&attribute);
+                        attributes.add(attribute, value.clone());
+                    },
+                    _ => {
+                        return Ok(false);
+                    },
+                }
+            },
+            OpApply::RemoveVariableValueAttribute { binding, attribute_binding, value, mode } => {
+                match (
+                    bindings[binding.index()].object(),
+                    bindings[attribute_binding.index()].symbol(),
+                ) {
+                    (Some(id), Some(attribute)) => {
+                        let removed = space.attributes_mut(id).remove_single(attribute, value);
+                        if removed.is_none() {
+                            if let RemovalMode::Required = mode {
+                                return Ok(false);
+                            }
+                        }
+                    },
+                    _ => {
+                        return Ok(false);
+                    },
                 }
             },
             OpApply::Conditional { condition, then_apply, otherwise_apply } => {
-                let mut local_bindings = bindings.to_vec();
-                let continue_apply =
-                    if find_first_bindings(condition, space, &mut local_bindings) {
-                        apply_changes(then_apply, space, bindings)
+                let branch =
+                    if find_first_bindings(condition, space, bindings) {
+                        then_apply
                     } else {
-                        apply_changes(otherwise_apply, space, bindings)
+                        otherwise_apply
                     };
+                let continue_apply =
+                    apply_block(branch, space, before, bindings, effects, context, purged)?;
                 if !continue_apply {
-                    return false;
+                    return Ok(false);
+                }
+            },
+            OpApply::Match { branches } => {
+                for (condition, apply) in branches {
+                    if find_first_bindings(condition, space, bindings) {
+                        if !apply_block(apply, space, before, bindings, effects, context, purged)? {
+                            return Ok(false);
+                        }
+                        break;
+                    }
+                }
+            },
+            OpApply::ForEach { selection, apply } => {
+                let mut found = Vec::new();
+                search_bindings(selection, before, bindings, |bindings| {
+                    found.push(bindings.to_vec());
+                    RuntimeControl::Continue
+                });
+                for found_bindings in found {
+                    bindings.clone_from_slice(&found_bindings);
+                    if !apply_block(apply, space, before, bindings, effects, context, purged)? {
+                        return Ok(false);
+                    }
                 }
             },
         }
     }
-    true
+    Ok(true)
 }
 
 pub fn find_first_bindings(
@@ -167,102 +542,258 @@ pub enum RuntimeControl {
     Stop,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Matcher {
+    #[default]
+    Search,
+    Incremental,
+    Compiled,
+}
+
+fn remove_references(space: &mut dyn Access, objects: &[Id]) {
+    if objects.is_empty() {
+        return;
+    }
+    let mut objects = objects.to_vec();
+    objects.sort_unstable();
+    objects.dedup();
+    for id in space.object_ids() {
+        let is_referencing = space.attributes(id)
+            .iter()
+            .any(|(_, value)| is_object_reference(value, &objects));
+        if is_referencing {
+            space.attributes_mut(id).retain(|_, value| !is_object_reference(value, &objects));
+        }
+    }
+}
+
+fn is_object_reference(value: &Value, objects: &[Id]) -> bool {
+    match value {
+        Value::Object(id) => objects.binary_search(id).is_ok(),
+        Value::Tuple(values) => values.iter().any(|value| is_object_reference(value, objects)),
+        Value::Symbol(_) |
+        Value::Int(_) |
+        Value::Float(_) => false,
+    }
+}
+
 fn search_bindings<F>(
     ops: &[Op],
     space: &dyn Access,
     bindings: &mut [Value],
-    mut control: F,
+    control: F,
 ) -> bool
 where
     F: FnMut(&mut [Value]) -> RuntimeControl,
 {
-    let mut op_index = 0;
-    let mut frames = Vec::new();
+    Search::new().run(ops, space, bindings, control)
+}
 
-    loop {
-        let flow = match &ops[op_index] {
-            Op::AssertObjectBinding { binding } => {
-                if bindings[binding.index()].object().is_some() {
-                    Flow::NextOp
-                } else {
-                    Flow::NextBranch
-                }
-            },
-            Op::RequireAttributeBinding { binding, attribute, value_binding } => {
-                if let Some(id) = bindings[binding.index()].object() {
-                    if space.attributes(id)
-                        .has(attribute.as_ref(), &bindings[value_binding.index()])
-                    {
+#[derive(Debug, Default)]
+struct Search {
+    op_index: usize,
+    frames: Vec<Frame>,
+    reads: Option<Reads>,
+    backtrack: bool,
+}
+
+impl Search {
+
+    fn new() -> Self {
+        Self::default()
+    }
+
+    // makes the next run continue after the match it stopped at
+    fn skip_match(&mut self) {
+        self.backtrack = true;
+    }
+
+    fn restart(&mut self) {
+        self.op_index = 0;
+        self.frames.clear();
+        self.backtrack = false;
+        if let Some(reads) = &mut self.reads {
+            reads.clear();
+        }
+    }
+
+    fn run<F>(
+        &mut self,
+        ops: &[Op],
+        space: &dyn Access,
+        bindings: &mut [Value],
+        mut control: F,
+    ) -> bool
+    where
+        F: FnMut(&mut [Value]) -> RuntimeControl,
+    {
+        let Self { op_index, frames, reads, backtrack } = self;
+
+        if std::mem::take(backtrack) && !next_branch(ops, frames, reads, op_index, bindings) {
+            return false;
+        }
+
+        loop {
+            let flow = match &ops[*op_index] {
+                Op::AssertObjectBinding { binding } => {
+                    if bindings[binding.index()].object().is_some() {
                         Flow::NextOp
                     } else {
                         Flow::NextBranch
                     }
-                } else {
-                    Flow::NextBranch
-                }
-            },
-            Op::RequireAttributeValue { binding, attribute, value } => {
-                if let Some(id) = bindings[binding.index()].object() {
-                    if space.attributes(id).has(attribute.as_ref(), value) {
-                        Flow::NextOp
+                },
+                Op::RequireAttributeBinding { binding, attribute, value_binding } => {
+                    if let Some(id) = bindings[binding.index()].object() {
+                        if read(space, reads, id)
+                            .has(attribute.as_ref(), &bindings[value_binding.index()])
+                        {
+                            Flow::NextOp
+                        } else {
+                            Flow::NextBranch
+                        }
                     } else {
                         Flow::NextBranch
                     }
-                } else {
-                    Flow::NextBranch
-                }
-            },
-            Op::RequireAttribute { binding, attribute } => {
-                if let Some(id) = bindings[binding.index()].object() {
-                    if space.attributes(id).has_named(attribute.as_ref()) {
-                        Flow::NextOp
+                },
+                Op::RequireAttributeValue { binding, attribute, value } => {
+                    if let Some(id) = bindings[binding.index()].object() {
+                        if read(space, reads, id).has(attribute.as_ref(), value) {
+                            Flow::NextOp
+                        } else {
+                            Flow::NextBranch
+                        }
                     } else {
                         Flow::NextBranch
                     }
-                } else {
+                },
+                Op::RequireAttribute { binding, attribute } => {
+                    if let Some(id) = bindings[binding.index()].object() {
+                        if read(space, reads, id).has_named(attribute.as_ref()) {
+                            Flow::NextOp
+                        } else {
+                            Flow::NextBranch
+                        }
+                    } else {
+                        Flow::NextBranch
+                    }
+                },
+                Op::RequireVariableAttributeBinding { binding, attribute_binding, value_binding } => {
+                    match (
+                        bindings[binding.index()].object(),
+                        bindings[attribute_binding.index()].symbol(),
+                    ) {
+                        (Some(id), Some(attribute))
+                            if read(space, reads, id)
+                                .has(attribute, &bindings[value_binding.index()])
+                            => Flow::NextOp,
+                        _ => Flow::NextBranch,
+                    }
+                },
+                Op::RequireVariableAttribute { binding, attribute_binding } => {
+                    match (
+                        bindings[binding.index()].object(),
+                        bindings[attribute_binding.index()].symbol(),
+                    ) {
+                        (Some(id), Some(attribute))
+                            if read(space, reads, id).has_named(attribute) => Flow::NextOp,
+                        _ => Flow::NextBranch,
+                    }
+                },
+                Op::SearchVariableAttributeBinding { binding, attribute_binding, value_binding } => {
+                    if let (Some(id), Some(attribute)) = (
+                        bindings[binding.index()].object(),
+                        bindings[attribute_binding.index()].symbol(),
+                    ) {
+                        if let Some(values) = read(space, reads, id).shared_named(attribute) {
+                            frames.push(Frame::choice(*op_index, Choice::Values {
+                                values,
+                                index: 0,
+                                binding: value_binding.index(),
+                            }));
+                        }
+                    }
                     Flow::NextBranch
-                }
-            },
-            Op::CompareBinding { binding, value } => {
-                if &bindings[binding.index()] == value {
-                    Flow::NextOp
-                } else {
+                },
+                Op::SearchAttributeNames { binding, attribute_binding } => {
+                    if let Some(id) = bindings[binding.index()].object() {
+                        frames.push(Frame::choice(*op_index, Choice::Names {
+                            attributes: read(space, reads, id).to_attr_data(),
+                            index: 0,
+                            value: None,
+                            binding: attribute_binding.index(),
+                        }));
+                    }
                     Flow::NextBranch
-                }
-            },
-            Op::SearchAttributeBinding { binding, attribute, value_binding } => {
-                if let Some(id) = bindings[binding.index()].object() {
-                    let iter = space.attributes(id).iter_named(attribute);
-                    frames.push(Frame::Iter {
-                        binding: value_binding.index(),
-                        continue_op_index: op_index + 1,
-                        iter,
-                    });
+                },
+                Op::SearchAttributeNamesWithValue { binding, attribute_binding, value_binding } => {
+                    if let Some(id) = bindings[binding.index()].object() {
+                        frames.push(Frame::choice(*op_index, Choice::Names {
+                            attributes: read(space, reads, id).to_attr_data(),
+                            index: 0,
+                            value: Some(bindings[value_binding.index()].clone()),
+                            binding: attribute_binding.index(),
+                        }));
+                    }
                     Flow::NextBranch
-                } else {
+                },
+                Op::SearchAttributePairs { binding, attribute_binding, value_binding } => {
+                    if let Some(id) = bindings[binding.index()].object() {
+                        frames.push(Frame::choice(*op_index, Choice::Pairs {
+                            attributes: read(space, reads, id).to_attr_data(),
+                            index: 0,
+                            value_index: 0,
+                            name_binding: attribute_binding.index(),
+                            value_binding: value_binding.index(),
+                        }));
+                    }
+                    Flow::NextBranch
+                },
+                Op::SearchAttributeClosure { closure } => {
+                    if let Some(id) = bindings[closure.binding.index()].object() {
+                        let reachable = attribute_closure(space, reads, id, closure);
+                        frames.push(Frame::choice(*op_index, Choice::Closure {
+                            reachable: reachable.into_iter(),
+                            value_binding: closure.value_binding.index(),
+                            depth: closure.depth,
+                        }));
+                    }
+                    Flow::NextBranch
+                },
+                Op::RequireAttributeClosure { closure } => {
+                    let found = bindings[closure.binding.index()].object()
+                        .and_then(|id| {
+                            attribute_closure(space, reads, id, closure)
+                                .into_iter()
+                                .find(|(value, _)| *value == bindings[closure.value_binding.index()])
+                        });
+                    match found {
+                        Some((_, depth)) if bind_closure_depth(bindings, closure.depth, depth) =>
+                            Flow::NextOp,
+                        _ => Flow::NextBranch,
+                    }
+                },
+                Op::CompareBinding { binding, value } => {
+                    if &bindings[binding.index()] == value {
+                        Flow::NextOp
+                    } else {
+                        Flow::NextBranch
+                    }
+                },
+                Op::SearchAttributeBinding { binding, attribute, value_binding } => {
+                    if let Some(id) = bindings[binding.index()].object() {
+                        if let Some(values) = read(space, reads, id).shared_named(attribute) {
+                            frames.push(Frame::choice(*op_index, Choice::Values {
+                                values,
+                                index: 0,
+                                binding: value_binding.index(),
+                            }));
+                        }
+                    }
                     Flow::NextBranch
                 }
-            }
-            Op::UnpackTupleBinding { binding, values } => {
-                if let Some(tuple) = bindings[binding.index()].tuple().cloned() {
-                    if tuple.len() == values.len() {
-                        let matched = tuple.iter().zip(values.iter())
-                            .all(|(value, expected)| {
-                                match expected {
-                                    TupleItem::Ignore => true,
-                                    TupleItem::Bind(binding) => {
-                                        bindings[binding.index()] = value.clone();
-                                        true
-                                    },
-                                    TupleItem::CompareBinding(binding) => {
-                                        bindings[binding.index()] == *value
-                                    },
-                                    TupleItem::CompareValue(expected_value) => {
-                                        expected_value == value
-                                    },
-                                }
-                            });
-                        if matched {
+                Op::UnpackTupleBinding { binding, values } => {
+                    if let Some(tuple) = bindings[binding.index()].tuple().cloned() {
+                        if unpack_tuple(bindings, &tuple, values) {
                             Flow::NextOp
                         } else {
                             Flow::NextBranch
@@ -270,155 +801,333 @@ where
                     } else {
                         Flow::NextBranch
                     }
-                } else {
-                    Flow::NextBranch
-                }
-            },
-            Op::MatchEnumBinding { binding, options } => {
-                let mut matched = false;
-                'options: for option in options {
-                    match option {
-                        EnumOption::Binding(match_binding) => {
-                            if bindings[binding.index()] == bindings[match_binding.index()] {
-                                matched = true;
-                                break 'options;
-                            }
-                        },
-                        EnumOption::Value(value) => {
-                            if bindings[binding.index()] == *value {
-                                matched = true;
-                                break 'options;
-                            }
-                        },
+                },
+                Op::MatchEnumBinding { binding, options } => {
+                    if matches_enum(bindings, binding.index(), options) {
+                        Flow::NextOp
+                    } else {
+                        Flow::NextBranch
                     }
-                }
-                if matched {
-                    Flow::NextOp
-                } else {
-                    Flow::NextBranch
-                }
-            },
-            Op::Compare { comparison } => {
-                let left_value = comparison.left.resolve(bindings);
-                let right_value = comparison.right.resolve(bindings);
-                if let Some((left_value, right_value))
-                    = unify_numeric_types(left_value.clone(), right_value.clone())
-                {
-                    let cmp = left_value.partial_cmp(&right_value);
-                    let matched = match cmp {
-                        Some(Ordering::Equal) => match comparison.operator {
-                            CompareOp::Equal | CompareOp::LessOrEqual | CompareOp::GreaterOrEqual
-                                => true,
-                            _ => false,
+                },
+                Op::Compare { comparison } => {
+                    if compare(bindings, comparison) {
+                        Flow::NextOp
+                    } else {
+                        Flow::NextBranch
+                    }
+                },
+                Op::Calculation { binding, operation } => {
+                    match perform_calculation(bindings, operation) {
+                        Some(value) => {
+                            bindings[binding.index()] = value;
+                            Flow::NextOp
                         },
-                        Some(Ordering::Less) => match comparison.operator {
-                            CompareOp::Less | CompareOp::LessOrEqual | CompareOp::NotEqual
-                                => true,
-                            _ => false,
+                        None => {
+                            Flow::NextBranch
                         },
-                        Some(Ordering::Greater) => match comparison.operator {
-                            CompareOp::Greater | CompareOp::GreaterOrEqual | CompareOp::NotEqual
-                                => true,
-                            _ => false,
+                    }
+                },
+                Op::CalculationCompare { binding, operation } => {
+                    match perform_calculation(bindings, operation) {
+                        Some(value) if bindings[binding.index()] == value => {
+                            Flow::NextOp
                         },
-                        None => match comparison.operator {
-                            CompareOp::NotEqual => true,
-                            _ => false,
+                        _ => {
+                            Flow::NextBranch
                         },
-                    };
-                    if matched {
+                    }
+                },
+                Op::SearchTupleMemberBinding { binding, value_binding } => {
+                    if let Some(tuple) = bindings[binding.index()].tuple().cloned() {
+                        frames.push(Frame::choice(*op_index, Choice::Tuple {
+                            tuple,
+                            index: 0,
+                            binding: value_binding.index(),
+                        }));
+                    }
+                    Flow::NextBranch
+                },
+                Op::RequireTupleMemberBinding { binding, value_binding } => {
+                    if is_tuple_member(&bindings[binding.index()], &bindings[value_binding.index()]) {
                         Flow::NextOp
                     } else {
                         Flow::NextBranch
                     }
-                } else {
-                    Flow::NextBranch
-                }
-            },
-            Op::Calculation { binding, operation } => {
-                match perform_calculation(bindings, operation) {
-                    Some(value) => {
-                        bindings[binding.index()] = value;
+                },
+                Op::RequireTupleMemberValue { binding, value } => {
+                    if is_tuple_member(&bindings[binding.index()], value) {
                         Flow::NextOp
-                    },
-                    None => {
+                    } else {
                         Flow::NextBranch
-                    },
-                }
+                    }
+                },
+                Op::BeginNot { index, sequence_len } => {
+                    frames.push(Frame::NotScope {
+                        index: *index,
+                        continue_ok: *op_index + *sequence_len + 1,
+                    });
+                    Flow::NextOp
+                },
+                Op::EndNot { index } => {
+                    let frame_index = frames
+                        .iter()
+                        .position(|frame| match frame {
+                            Frame::NotScope { index: fr_index, .. } => *fr_index == *index,
+                            _ => false,
+                        })
+                        .expect("corresponding not-scope frame");
+                    frames.truncate(frame_index);
+                    Flow::NextBranch
+                },
+                Op::BeginOptional { index, .. } => {
+                    frames.push(Frame::OptionalScope {
+                        index: *index,
+                        matched: false,
+                        begin_op_index: *op_index,
+                    });
+                    Flow::NextOp
+                },
+                Op::EndOptional { index } => {
+                    let frame = frames
+                        .iter_mut()
+                        .rev()
+                        .find_map(|frame| match frame {
+                            Frame::OptionalScope { index: fr_index, matched, .. }
+                                if *fr_index == *index => Some(matched),
+                            _ => None,
+                        })
+                        .expect("corresponding optional-scope frame");
+                    *frame = true;
+                    Flow::NextOp
+                },
+                Op::End => {
+                    match control(bindings) {
+                        RuntimeControl::Continue => Flow::NextBranch,
+                        RuntimeControl::Stop => {
+                            return true;
+                        },
+                    }
+                },
+            };
+            match flow {
+                Flow::NextOp => {
+                    *op_index += 1;
+                },
+                Flow::NextBranch => {
+                    if !next_branch(ops, frames, reads, op_index, bindings) {
+                        return false;
+                    }
+                },
+            }
+        }
+    }
+}
+
+// backtracks to the most recent frame with an alternative left. Returns
+// false once all frames are exhausted
+fn next_branch(
+    ops: &[Op],
+    frames: &mut Vec<Frame>,
+    reads: &mut Option<Reads>,
+    op_index: &mut usize,
+    bindings: &mut [Value],
+) -> bool {
+    loop {
+        let outermost = frames.len() == 1;
+        let frame = match frames.last_mut() {
+            Some(frame) => frame,
+            None => return false,
+        };
+        match frame {
+            Frame::NotScope { continue_ok, .. } => {
+                *op_index = *continue_ok;
+                frames.pop();
             },
-            Op::CalculationCompare { binding, operation } => {
-                match perform_calculation(bindings, operation) {
-                    Some(value) => {
-                        if bindings[binding.index()] == value {
-                            Flow::NextOp
-                        } else {
-                            Flow::NextBranch
-                        }
-                    },
-                    None => {
-                        Flow::NextBranch
-                    },
+            Frame::OptionalScope { matched, begin_op_index, .. } => {
+                let matched = *matched;
+                let begin_op_index = *begin_op_index;
+                frames.pop();
+                if matched {
+                    continue;
+                }
+                if let Op::BeginOptional { sequence_len, defaults, .. }
+                    = &ops[begin_op_index]
+                {
+                    for (binding, value) in defaults {
+                        bindings[binding.index()] = value.clone();
+                    }
+                    *op_index = begin_op_index + sequence_len + 1;
                 }
             },
-            Op::BeginNot { index, sequence_len } => {
-                frames.push(Frame::NotScope {
-                    index: *index,
-                    continue_ok: op_index + *sequence_len + 1,
-                });
-                Flow::NextOp
-            },
-            Op::EndNot { index } => {
-                let frame_index = frames
-                    .iter()
-                    .position(|frame| match frame {
-                        Frame::NotScope { index: fr_index, .. } => *fr_index == *index,
-                        _ => false,
-                    })
-                    .expect("corresponding not-scope frame");
-                frames.truncate(frame_index);
-                Flow::NextBranch
-            },
-            Op::End => {
-                match control(bindings) {
-                    RuntimeControl::Continue => Flow::NextBranch,
-                    RuntimeControl::Stop => {
-                        return true;
+            Frame::Choice { choice, continue_op_index, reads_before } => {
+                let advanced = match reads {
+                    Some(reads) if outermost => {
+                        let next = *continue_op_index;
+                        reads.advance_outermost(choice, next, bindings)
                     },
+                    _ => choice.advance(bindings),
+                };
+                if advanced {
+                    *op_index = *continue_op_index;
+                    *reads_before = reads_len(reads);
+                } else {
+                    frames.pop();
+                    continue;
                 }
             },
-        };
-        match flow {
-            Flow::NextOp => {
-                op_index += 1;
-            },
-            Flow::NextBranch => {
-                'next_branch: loop {
-                    if let Some(frame) = frames.last_mut() {
-                        match frame {
-                            Frame::NotScope { continue_ok, .. } => {
-                                op_index = *continue_ok;
-                                frames.pop();
-                            },
-                            Frame::Iter { iter, binding, continue_op_index } => {
-                                if let Some(value) = iter.next() {
-                                    bindings[*binding] = value.clone();
-                                    op_index = *continue_op_index;
-                                } else {
-                                    frames.pop();
-                                    continue 'next_branch;
-                                }
-                            },
-                        }
-                    } else {
-                        return false;
+        }
+        return true;
+    }
+}
+
+fn read<'s>(space: &'s dyn Access, reads: &mut Option<Reads>, id: Id) -> Attributes<'s> {
+    if let Some(reads) = reads {
+        reads.record(id);
+    }
+    space.attributes(id)
+}
+
+fn reads_len(reads: &Option<Reads>) -> usize {
+    reads.as_ref().map(Reads::len).unwrap_or(0)
+}
+
+fn attribute_closure(
+    space: &dyn Access,
+    reads: &mut Option<Reads>,
+    start: Id,
+    closure: &AttributeClosure,
+) -> Vec<(Value, usize)> {
+    let mut reachable = Vec::new();
+    let mut seen = BTreeSet::new();
+    if let ClosureMode::ZeroOrMore = closure.mode {
+        seen.insert(Value::Object(start));
+        reachable.push((Value::Object(start), 0));
+    }
+    let mut current = vec![start];
+    let mut depth = 0;
+    while !current.is_empty() && closure.max_depth.map(|max| depth < max).unwrap_or(true) {
+        depth += 1;
+        let mut next = Vec::new();
+        for id in current {
+            for value in read(space, reads, id).iter_named(&closure.attribute) {
+                if seen.insert(value.clone()) {
+                    reachable.push((value.clone(), depth));
+                    if let Some(value_id) = value.object() {
+                        next.push(value_id);
                     }
-                    break 'next_branch;
                 }
-            },
+            }
         }
+        current = next;
+    }
+    reachable
+}
+
+fn bind_closure_depth(bindings: &mut [Value], depth: ClosureDepth, found: usize) -> bool {
+    let found = match found.to_i64() {
+        Some(found) => Value::Int(found),
+        None => return false,
+    };
+    match depth {
+        ClosureDepth::Ignore => true,
+        ClosureDepth::Bind(binding) => {
+            bindings[binding.index()] = found;
+            true
+        },
+        ClosureDepth::Compare(binding) => bindings[binding.index()] == found,
+    }
+}
+
+fn matches_enum(bindings: &[Value], binding: usize, options: &[EnumOption]) -> bool {
+    options.iter().any(|option| match option {
+        EnumOption::Binding(match_binding) => bindings[binding] == bindings[match_binding.index()],
+        EnumOption::Value(value) => bindings[binding] == *value,
+    })
+}
+
+fn compare(bindings: &[Value], comparison: &Comparison) -> bool {
+    let left_value = comparison.left.resolve(bindings);
+    let right_value = comparison.right.resolve(bindings);
+    let (left_value, right_value) =
+        match unify_numeric_types(left_value.clone(), right_value.clone()) {
+            Some(values) => values,
+            None => return false,
+        };
+    match left_value.partial_cmp(&right_value) {
+        Some(Ordering::Equal) => matches!(
+            comparison.operator,
+            CompareOp::Equal | CompareOp::LessOrEqual | CompareOp::GreaterOrEqual
+        ),
+        Some(Ordering::Less) => matches!(
+            comparison.operator,
+            CompareOp::Less | CompareOp::LessOrEqual | CompareOp::NotEqual
+        ),
+        Some(Ordering::Greater) => matches!(
+            comparison.operator,
+            CompareOp::Greater | CompareOp::GreaterOrEqual | CompareOp::NotEqual
+        ),
+        None => matches!(comparison.operator, CompareOp::NotEqual),
+    }
+}
+
+fn is_tuple_member(tuple: &Value, value: &Value) -> bool {
+    tuple.tuple()
+        .map(|tuple| tuple.iter().any(|item| item == value))
+        .unwrap_or(false)
+}
+
+fn unpack_tuple(bindings: &mut [Value], tuple: &[Value], items: &[TupleItem]) -> bool {
+    match items.iter().position(TupleItem::is_rest) {
+        None => {
+            tuple.len() == items.len() && unpack_tuple_items(bindings, tuple, items)
+        },
+        Some(rest_index) => {
+            if tuple.len() < items.len() - 1 {
+                return false;
+            }
+            let rest_end = tuple.len() - (items.len() - rest_index - 1);
+            unpack_tuple_items(bindings, &tuple[..rest_index], &items[..rest_index])
+            && match &items[rest_index] {
+                TupleItem::BindRest(binding) => {
+                    bindings[binding.index()] = Value::from(tuple[rest_index..rest_end].to_vec());
+                    true
+                },
+                TupleItem::CompareRest(binding) => {
+                    bindings[binding.index()].tuple()
+                        .map(|expected| expected.as_ref() == &tuple[rest_index..rest_end])
+                        .unwrap_or(false)
+                },
+                _ => true,
+            }
+            && unpack_tuple_items(bindings, &tuple[rest_end..], &items[(rest_index + 1)..])
+        },
     }
 }
 
+fn unpack_tuple_items(bindings: &mut [Value], tuple: &[Value], items: &[TupleItem]) -> bool {
+    tuple.iter().zip(items.iter())
+        .all(|(value, expected)| {
+            match expected {
+                TupleItem::Ignore => true,
+                TupleItem::Bind(binding) => {
+                    bindings[binding.index()] = value.clone();
+                    true
+                },
+                TupleItem::CompareBinding(binding) => {
+                    bindings[binding.index()] == *value
+                },
+                TupleItem::CompareValue(expected_value) => {
+                    expected_value == value
+                },
+                TupleItem::IgnoreRest |
+                TupleItem::BindRest(_) |
+                TupleItem::CompareRest(_) => {
+                    unreachable!("rest items are unpacked separately")
+                },
+            }
+        })
+}
+
 fn perform_calculation(bindings: &[Value], calc: &Calculation) -> Option<Value> {
     match calc {
         Calculation::Value(value) => Some(value.clone()),
@@ -463,6 +1172,81 @@ fn perform_calculation(bindings: &[Value], calc: &Calculation) -> Option<Value>
                 },
             }
         },
+        Calculation::Tuple(items) => {
+            let values = items.iter()
+                .map(|item| perform_calculation(bindings, item))
+                .collect::<Option<Vec<_>>>()?;
+            Some(Value::from(values))
+        },
+        Calculation::Concat(left, right) => {
+            let left = perform_calculation(bindings, left)?;
+            let right = perform_calculation(bindings, right)?;
+            let values: Vec<Value> = left.tuple()?.iter()
+                .chain(right.tuple()?.iter())
+                .cloned()
+                .collect();
+            Some(Value::from(values))
+        },
+        Calculation::Member(item, tuple) => {
+            let item = perform_calculation(bindings, item)?;
+            let tuple = perform_calculation(bindings, tuple)?;
+            let is_member = tuple.tuple()?.contains(&item);
+            Some(Value::Int(is_member.into()))
+        },
+        Calculation::Len(tuple) => {
+            let tuple = perform_calculation(bindings, tuple)?;
+            Some(Value::Int(tuple.tuple()?.len().to_i64()?))
+        },
+        Calculation::Index(tuple, index) => {
+            let tuple = perform_calculation(bindings, tuple)?;
+            let tuple = tuple.tuple()?;
+            let index = perform_calculation(bindings, index)?.int()?;
+            let index = resolve_tuple_position(tuple.len(), index)?;
+            tuple.get(index).cloned()
+        },
+        Calculation::Slice(tuple, start, end) => {
+            let tuple = perform_calculation(bindings, tuple)?;
+            let tuple = tuple.tuple()?;
+            let start = match start {
+                Some(start) => {
+                    let start = perform_calculation(bindings, start)?.int()?;
+                    resolve_tuple_position(tuple.len(), start)?
+                },
+                None => 0,
+            };
+            let end = match end {
+                Some(end) => {
+                    let end = perform_calculation(bindings, end)?.int()?;
+                    resolve_tuple_position(tuple.len(), end)?
+                },
+                None => tuple.len(),
+            };
+            if start <= end && end <= tuple.len() {
+                Some(Value::from(tuple[start..end].to_vec()))
+            } else {
+                None
+            }
+        },
+        Calculation::Symbol(parts) => {
+            let mut symbol = String::new();
+            for part in parts {
+                match perform_calculation(bindings, part)? {
+                    Value::Symbol(value) => symbol.push_str(value.as_ref()),
+                    Value::Int(value) => symbol.push_str(&value.to_string()),
+                    Value::Float(value) => symbol.push_str(&value.to_string()),
+                    Value::Object(_) | Value::Tuple(_) => return None,
+                }
+            }
+            Some(Value::from(symbol.as_str()))
+        },
+    }
+}
+
+fn resolve_tuple_position(len: usize, position: i64) -> Option<usize> {
+    if position < 0 {
+        len.checked_sub(position.checked_neg()?.to_usize()?)
+    } else {
+        position.to_usize()
     }
 }
 
@@ -476,16 +1260,144 @@ fn unify_numeric_types(left: Value, right: Value) -> Option<(Value, Value)> {
     }
 }
 
-enum Frame<'a> {
-    Iter {
-        iter: ValuesIter<'a>,
-        binding: usize,
+#[derive(Debug)]
+enum Frame {
+    Choice {
+        choice: Choice,
         continue_op_index: usize,
+        reads_before: usize,
     },
     NotScope {
         index: usize,
         continue_ok: usize,
     },
+    OptionalScope {
+        index: usize,
+        matched: bool,
+        begin_op_index: usize,
+    },
+}
+
+impl Frame {
+
+    fn choice(op_index: usize, choice: Choice) -> Self {
+        Frame::Choice {
+            choice,
+            continue_op_index: op_index + 1,
+            reads_before: 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Choice {
+    Values {
+        values: Arc<Vec<Value>>,
+        index: usize,
+        binding: usize,
+    },
+    Names {
+        attributes: Vec<(Symbol, Arc<Vec<Value>>)>,
+        index: usize,
+        value: Option<Value>,
+        binding: usize,
+    },
+    Pairs {
+        attributes: Vec<(Symbol, Arc<Vec<Value>>)>,
+        index: usize,
+        value_index: usize,
+        name_binding: usize,
+        value_binding: usize,
+    },
+    Closure {
+        reachable: std::vec::IntoIter<(Value, usize)>,
+        value_binding: usize,
+        depth: ClosureDepth,
+    },
+    Tuple {
+        tuple: Tuple,
+        index: usize,
+        binding: usize,
+    },
+}
+
+impl Choice {
+
+    fn checkpoint(&self) -> Option<Choice> {
+        match self {
+            Choice::Values { values, index, binding } => Some(Choice::Values {
+                values: values.clone(),
+                index: *index,
+                binding: *binding,
+            }),
+            Choice::Tuple { tuple, index, binding } => Some(Choice::Tuple {
+                tuple: tuple.clone(),
+                index: *index,
+                binding: *binding,
+            }),
+            _ => None,
+        }
+    }
+
+    fn advance(&mut self, bindings: &mut [Value]) -> bool {
+        match self {
+            Choice::Values { values, index, binding } => {
+                if let Some(value) = values.get(*index) {
+                    bindings[*binding] = value.clone();
+                    *index += 1;
+                    true
+                } else {
+                    false
+                }
+            },
+            Choice::Names { attributes, index, value, binding } => {
+                while let Some((name, values)) = attributes.get(*index) {
+                    *index += 1;
+                    let is_match = match value {
+                        Some(value) => values.iter().any(|ex_value| value.match_value(ex_value)),
+                        None => !values.is_empty(),
+                    };
+                    if is_match {
+                        bindings[*binding] = Value::Symbol(name.clone());
+                        return true;
+                    }
+                }
+                false
+            },
+            Choice::Pairs { attributes, index, value_index, name_binding, value_binding } => {
+                while let Some((name, values)) = attributes.get(*index) {
+                    if let Some(value) = values.get(*value_index) {
+                        *value_index += 1;
+                        bindings[*name_binding] = Value::Symbol(name.clone());
+                        bindings[*value_binding] = value.clone();
+                        return true;
+                    }
+                    *index += 1;
+                    *value_index = 0;
+                }
+                false
+            },
+            Choice::Closure { reachable, value_binding, depth } => {
+                let next = reachable
+                    .find(|(_, found)| bind_closure_depth(bindings, *depth, *found));
+                if let Some((value, _)) = next {
+                    bindings[*value_binding] = value;
+                    true
+                } else {
+                    false
+                }
+            },
+            Choice::Tuple { tuple, index, binding } => {
+                if let Some(value) = tuple.get(*index) {
+                    bindings[*binding] = value.clone();
+                    *index += 1;
+                    true
+                } else {
+                    false
+                }
+            },
+        }
+    }
 }
 
 enum Flow {
@@ -0,0 +1,347 @@
+use std::fmt;
+use crate::{Value, Access, RuntimeError};
+use crate::compiler::{CompiledRule, Op};
+use super::{
+    Choice,
+    ApplyEffects,
+    RunContext,
+    RuntimeControl,
+    apply_rule,
+    attribute_closure,
+    bind_closure_depth,
+    compare,
+    is_tuple_member,
+    matches_enum,
+    perform_calculation,
+    unpack_tuple,
+};
+
+type Step = Box<
+    dyn Fn(&dyn Access, &mut [Value], &mut dyn FnMut(&mut [Value]) -> bool) -> bool + Send + Sync
+>;
+
+// the select ops of a rule turned into nested closures. Each op is a
+// closure calling the closure of the op after it, with its operands taken
+// out of the op when compiling, so a search no longer dispatches on every
+// step. Backtracking happens by returning, which replaces the frame stack.
+pub struct CompiledSearch {
+    ops_len: usize,
+    step: Step,
+}
+
+impl fmt::Debug for CompiledSearch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompiledSearch").field("ops_len", &self.ops_len).finish_non_exhaustive()
+    }
+}
+
+impl CompiledSearch {
+
+    pub fn new(ops: &[Op]) -> Self {
+        Self {
+            ops_len: ops.len(),
+            step: compile(ops),
+        }
+    }
+
+    pub fn run<F>(&self, space: &dyn Access, bindings: &mut [Value], mut control: F) -> bool
+    where
+        F: FnMut(&mut [Value]) -> RuntimeControl,
+    {
+        (self.step)(space, bindings, &mut |bindings| control(bindings) == RuntimeControl::Stop)
+    }
+
+    pub fn find_first(&self, space: &dyn Access, bindings: &mut [Value]) -> bool {
+        self.run(space, bindings, |_| RuntimeControl::Stop)
+    }
+}
+
+#[derive(Debug)]
+pub struct CompiledMatcher<'a> {
+    searches: &'a [CompiledSearch],
+}
+
+impl<'a> CompiledMatcher<'a> {
+
+    pub fn new(searches: &'a [CompiledSearch]) -> Self {
+        Self { searches }
+    }
+
+    pub fn attempt_rule_firing(
+        &self,
+        index: usize,
+        rule: &CompiledRule,
+        space: &mut dyn Access,
+        bindings: &mut [Value],
+        effects: &mut ApplyEffects,
+        context: RunContext<'_>,
+    ) -> Result<bool, RuntimeError> {
+        effects.clear();
+        if !self.searches[index].find_first(space, bindings) {
+
+            #[cfg(feature = "tracing")]
+            tracing::trace!(rule = rule.name().as_ref(), outcome = "failed match");
+
+            return Ok(false);
+        }
+        apply_rule(rule, space, bindings, effects, context)
+    }
+}
+
+fn compile(ops: &[Op]) -> Step {
+    let (op, rest) = match ops.split_first() {
+        Some(split) => split,
+        None => return Box::new(|_, bindings, next| next(bindings)),
+    };
+    match op {
+        Op::AssertObjectBinding { binding } => {
+            let binding = binding.index();
+            require(rest, move |_, bindings| bindings[binding].object().is_some())
+        },
+        Op::RequireAttributeBinding { binding, attribute, value_binding } => {
+            let (binding, attribute, value_binding) =
+                (binding.index(), attribute.clone(), value_binding.index());
+            require(rest, move |space, bindings| {
+                bindings[binding].object().is_some_and(|id| {
+                    space.attributes(id).has(attribute.as_ref(), &bindings[value_binding])
+                })
+            })
+        },
+        Op::RequireAttributeValue { binding, attribute, value } => {
+            let (binding, attribute, value) = (binding.index(), attribute.clone(), value.clone());
+            require(rest, move |space, bindings| {
+                bindings[binding].object().is_some_and(|id| {
+                    space.attributes(id).has(attribute.as_ref(), &value)
+                })
+            })
+        },
+        Op::RequireAttribute { binding, attribute } => {
+            let (binding, attribute) = (binding.index(), attribute.clone());
+            require(rest, move |space, bindings| {
+                bindings[binding].object().is_some_and(|id| {
+                    space.attributes(id).has_named(attribute.as_ref())
+                })
+            })
+        },
+        Op::RequireVariableAttributeBinding { binding, attribute_binding, value_binding } => {
+            let (binding, attribute_binding, value_binding) =
+                (binding.index(), attribute_binding.index(), value_binding.index());
+            require(rest, move |space, bindings| {
+                match (bindings[binding].object(), bindings[attribute_binding].symbol()) {
+                    (Some(id), Some(attribute)) =>
+                        space.attributes(id).has(attribute, &bindings[value_binding]),
+                    _ => false,
+                }
+            })
+        },
+        Op::RequireVariableAttribute { binding, attribute_binding } => {
+            let (binding, attribute_binding) = (binding.index(), attribute_binding.index());
+            require(rest, move |space, bindings| {
+                match (bindings[binding].object(), bindings[attribute_binding].symbol()) {
+                    (Some(id), Some(attribute)) => space.attributes(id).has_named(attribute),
+                    _ => false,
+                }
+            })
+        },
+        Op::SearchVariableAttributeBinding { binding, attribute_binding, value_binding } => {
+            let (binding, attribute_binding, value_binding) =
+                (binding.index(), attribute_binding.index(), value_binding.index());
+            choose(rest, move |space, bindings| {
+                match (bindings[binding].object(), bindings[attribute_binding].symbol()) {
+                    (Some(id), Some(attribute)) => space.attributes(id)
+                        .shared_named(attribute)
+                        .map(|values| Choice::Values { values, index: 0, binding: value_binding }),
+                    _ => None,
+                }
+            })
+        },
+        Op::SearchAttributeNames { binding, attribute_binding } => {
+            let (binding, attribute_binding) = (binding.index(), attribute_binding.index());
+            choose(rest, move |space, bindings| {
+                bindings[binding].object().map(|id| Choice::Names {
+                    attributes: space.attributes(id).to_attr_data(),
+                    index: 0,
+                    value: None,
+                    binding: attribute_binding,
+                })
+            })
+        },
+        Op::SearchAttributeNamesWithValue { binding, attribute_binding, value_binding } => {
+            let (binding, attribute_binding, value_binding) =
+                (binding.index(), attribute_binding.index(), value_binding.index());
+            choose(rest, move |space, bindings| {
+                bindings[binding].object().map(|id| Choice::Names {
+                    attributes: space.attributes(id).to_attr_data(),
+                    index: 0,
+                    value: Some(bindings[value_binding].clone()),
+                    binding: attribute_binding,
+                })
+            })
+        },
+        Op::SearchAttributePairs { binding, attribute_binding, value_binding } => {
+            let (binding, attribute_binding, value_binding) =
+                (binding.index(), attribute_binding.index(), value_binding.index());
+            choose(rest, move |space, bindings| {
+                bindings[binding].object().map(|id| Choice::Pairs {
+                    attributes: space.attributes(id).to_attr_data(),
+                    index: 0,
+                    value_index: 0,
+                    name_binding: attribute_binding,
+                    value_binding,
+                })
+            })
+        },
+        Op::SearchAttributeClosure { closure } => {
+            let closure = closure.clone();
+            choose(rest, move |space, bindings| {
+                bindings[closure.binding.index()].object().map(|id| Choice::Closure {
+                    reachable: attribute_closure(space, &mut None, id, &closure).into_iter(),
+                    value_binding: closure.value_binding.index(),
+                    depth: closure.depth,
+                })
+            })
+        },
+        Op::RequireAttributeClosure { closure } => {
+            let closure = closure.clone();
+            require(rest, move |space, bindings| {
+                let found = bindings[closure.binding.index()].object().and_then(|id| {
+                    attribute_closure(space, &mut None, id, &closure)
+                        .into_iter()
+                        .find(|(value, _)| *value == bindings[closure.value_binding.index()])
+                });
+                matches!(
+                    found,
+                    Some((_, depth)) if bind_closure_depth(bindings, closure.depth, depth)
+                )
+            })
+        },
+        Op::CompareBinding { binding, value } => {
+            let (binding, value) = (binding.index(), value.clone());
+            require(rest, move |_, bindings| bindings[binding] == value)
+        },
+        Op::SearchAttributeBinding { binding, attribute, value_binding } => {
+            let (binding, attribute, value_binding) =
+                (binding.index(), attribute.clone(), value_binding.index());
+            choose(rest, move |space, bindings| {
+                bindings[binding].object()
+                    .and_then(|id| space.attributes(id).shared_named(&attribute))
+                    .map(|values| Choice::Values { values, index: 0, binding: value_binding })
+            })
+        },
+        Op::UnpackTupleBinding { binding, values } => {
+            let (binding, values) = (binding.index(), values.clone());
+            require(rest, move |_, bindings| match bindings[binding].tuple().cloned() {
+                Some(tuple) => unpack_tuple(bindings, &tuple, &values),
+                None => false,
+            })
+        },
+        Op::MatchEnumBinding { binding, options } => {
+            let (binding, options) = (binding.index(), options.clone());
+            require(rest, move |_, bindings| matches_enum(bindings, binding, &options))
+        },
+        Op::Compare { comparison } => {
+            let comparison = comparison.clone();
+            require(rest, move |_, bindings| compare(bindings, &comparison))
+        },
+        Op::Calculation { binding, operation } => {
+            let (binding, operation) = (binding.index(), operation.clone());
+            require(rest, move |_, bindings| match perform_calculation(bindings, &operation) {
+                Some(value) => {
+                    bindings[binding] = value;
+                    true
+                },
+                None => false,
+            })
+        },
+        Op::CalculationCompare { binding, operation } => {
+            let (binding, operation) = (binding.index(), operation.clone());
+            require(rest, move |_, bindings| {
+                perform_calculation(bindings, &operation).is_some_and(|value| {
+                    bindings[binding] == value
+                })
+            })
+        },
+        Op::SearchTupleMemberBinding { binding, value_binding } => {
+            let (binding, value_binding) = (binding.index(), value_binding.index());
+            choose(rest, move |_, bindings| {
+                bindings[binding].tuple().cloned().map(|tuple| Choice::Tuple {
+                    tuple,
+                    index: 0,
+                    binding: value_binding,
+                })
+            })
+        },
+        Op::RequireTupleMemberBinding { binding, value_binding } => {
+            let (binding, value_binding) = (binding.index(), value_binding.index());
+            require(rest, move |_, bindings| {
+                is_tuple_member(&bindings[binding], &bindings[value_binding])
+            })
+        },
+        Op::RequireTupleMemberValue { binding, value } => {
+            let (binding, value) = (binding.index(), value.clone());
+            require(rest, move |_, bindings| is_tuple_member(&bindings[binding], &value))
+        },
+        Op::BeginNot { sequence_len, .. } => {
+            let body = compile(&rest[..(sequence_len - 1)]);
+            let continuation = compile(&rest[*sequence_len..]);
+            Box::new(move |space, bindings, next| {
+                !body(space, bindings, &mut |_| true) && continuation(space, bindings, next)
+            })
+        },
+        Op::BeginOptional { sequence_len, defaults, .. } => {
+            let body = compile(&rest[..(sequence_len - 1)]);
+            let continuation = compile(&rest[*sequence_len..]);
+            let defaults = defaults
+                .iter()
+                .map(|(binding, value)| (binding.index(), value.clone()))
+                .collect::<Vec<_>>();
+            Box::new(move |space, bindings, next| {
+                let mut matched = false;
+                let stopped = body(space, bindings, &mut |bindings| {
+                    matched = true;
+                    continuation(space, bindings, next)
+                });
+                if stopped || matched {
+                    return stopped;
+                }
+                for (binding, value) in &defaults {
+                    bindings[*binding] = value.clone();
+                }
+                continuation(space, bindings, next)
+            })
+        },
+        Op::EndNot { .. } | Op::EndOptional { .. } => {
+            unreachable!("scope ends are compiled with their scope")
+        },
+        Op::End => Box::new(|_, bindings, next| next(bindings)),
+    }
+}
+
+fn require<F>(rest: &[Op], check: F) -> Step
+where
+    F: Fn(&dyn Access, &mut [Value]) -> bool + Send + Sync + 'static,
+{
+    let next_step = compile(rest);
+    Box::new(move |space, bindings, next| {
+        check(space, bindings) && next_step(space, bindings, next)
+    })
+}
+
+fn choose<F>(rest: &[Op], choice: F) -> Step
+where
+    F: Fn(&dyn Access, &[Value]) -> Option<Choice> + Send + Sync + 'static,
+{
+    let next_step = compile(rest);
+    Box::new(move |space, bindings, next| {
+        let mut choice = match choice(space, bindings) {
+            Some(choice) => choice,
+            None => return false,
+        };
+        while choice.advance(bindings) {
+            if next_step(space, bindings, next) {
+                return true;
+            }
+        }
+        false
+    })
+}
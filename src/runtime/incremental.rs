@@ -0,0 +1,443 @@
+use std::collections::{BTreeSet};
+use fnv::{FnvHashMap, FnvHashSet};
+use crate::{Value, Symbol, Id, Access, RuntimeError};
+use crate::compiler::{CompiledRule, Op};
+use super::{Search, Frame, Choice, ApplyEffects, RunContext, RuntimeControl, apply_changes};
+
+#[derive(Debug)]
+pub struct IncrementalMatcher {
+    memories: Vec<RuleMemory>,
+}
+
+#[derive(Debug)]
+struct RuleMemory {
+    search: Search,
+    bindings: Vec<Value>,
+    attributes: Option<FnvHashSet<Symbol>>,
+    state: MatchState,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum MatchState {
+    Unsearched,
+    Exhausted {
+        invalidated: bool,
+    },
+    Matched,
+}
+
+impl IncrementalMatcher {
+
+    pub fn new(rules: &[CompiledRule], bindings: &[Value]) -> Self {
+        let memories = rules
+            .iter()
+            .map(|rule| RuleMemory {
+                search: Search::tracking(),
+                bindings: bindings.to_vec(),
+                attributes: read_attributes(rule.ops()),
+                state: MatchState::Unsearched,
+            })
+            .collect();
+        Self { memories }
+    }
+
+    pub fn attempt_rule_firing(
+        &mut self,
+        index: usize,
+        rule: &CompiledRule,
+        space: &mut dyn Access,
+        effects: &mut ApplyEffects,
+        context: RunContext<'_>,
+    ) -> Result<bool, RuntimeError> {
+        effects.clear();
+        let memory = &mut self.memories[index];
+        if !memory.find(rule.ops(), space) {
+
+            #[cfg(feature = "tracing")]
+            tracing::trace!(rule = rule.name().as_ref(), outcome = "failed match");
+
+            return Ok(false);
+        }
+
+        let mut error = None;
+        let mut changes = Vec::new();
+        let fired = space.transaction(&mut |mut tx| {
+            let mut bindings = memory.bindings.clone();
+            let before = tx.outer();
+            let apply_ops = rule.apply_ops();
+            match apply_changes(apply_ops, &mut tx, before, &mut bindings, effects, context) {
+                Ok(true) => {
+
+                    #[cfg(feature = "tracing")]
+                    tracing::trace!(rule = rule.name().as_ref(), outcome = "applied");
+
+                    changes = tx.changed_attributes();
+                    Some(tx)
+                },
+                Ok(false) => {
+
+                    #[cfg(feature = "tracing")]
+                    tracing::trace!(rule = rule.name().as_ref(), outcome = "failed application");
+
+                    None
+                },
+                Err(apply_error) => {
+                    error = Some(apply_error);
+                    None
+                },
+            }
+        });
+        if !fired {
+            effects.clear();
+        }
+        if let Some(error) = error {
+            return Err(error);
+        }
+        if fired {
+            self.invalidate(&changes);
+        }
+        Ok(fired)
+    }
+
+    fn invalidate(&mut self, changes: &[(Id, Symbol)]) {
+        for memory in &mut self.memories {
+            let attributes = &memory.attributes;
+            let changed = changes
+                .iter()
+                .filter(|(_, name)| {
+                    attributes.as_ref().map_or(true, |names| names.contains(name))
+                })
+                .map(|(id, _)| *id);
+            if memory.search.invalidate(changed) {
+                if let MatchState::Exhausted { .. } = memory.state {
+                    memory.state = MatchState::Exhausted { invalidated: true };
+                }
+            }
+        }
+    }
+}
+
+impl RuleMemory {
+
+    fn find(&mut self, ops: &[Op], space: &dyn Access) -> bool {
+        match self.state {
+            MatchState::Exhausted { invalidated: false } => {
+                return false;
+            },
+            MatchState::Unsearched => {
+                self.search.restart();
+            },
+            // a match made without any choices has nothing to resume from,
+            // but it can still hold after firing
+            MatchState::Matched if self.search.frames.is_empty() => {
+                self.search.restart();
+            },
+            MatchState::Exhausted { invalidated: true } |
+            MatchState::Matched => {
+                if !self.search.resume(&mut self.bindings) {
+                    self.state = MatchState::Exhausted { invalidated: false };
+                    return false;
+                }
+            },
+        }
+        let found = self.search.run(ops, space, &mut self.bindings, |_| RuntimeControl::Stop);
+        self.state = if found {
+            MatchState::Matched
+        } else {
+            MatchState::Exhausted { invalidated: false }
+        };
+        found
+    }
+}
+
+// a tracked search records which objects it has read. Each value of the
+// outermost choice gets its own segment with its own reads, so a change
+// only requires searching the segments that have seen the changed objects
+// again. Everything read before the outermost choice is in the prefix.
+#[derive(Debug, Default)]
+pub(super) struct Reads {
+    prefix: ReadLog,
+    segments: Vec<Segment>,
+    readers: FnvHashMap<Id, FnvHashSet<usize>>,
+    current: usize,
+    in_segment: bool,
+    dirty: BTreeSet<usize>,
+    invalid_from: Option<usize>,
+    restart: bool,
+}
+
+#[derive(Debug)]
+struct Segment {
+    choice: Choice,
+    continue_op_index: usize,
+    reads: ReadLog,
+}
+
+impl Reads {
+
+    pub(super) fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.active().order.len()
+    }
+
+    pub(super) fn record(&mut self, id: Id) {
+        if self.in_segment {
+            let current = self.current;
+            if self.segments[current].reads.record(id) {
+                self.readers.entry(id).or_default().insert(current);
+            }
+        } else {
+            self.prefix.record(id);
+        }
+    }
+
+    // called in place of advancing the outermost choice. While segments
+    // before the last one are searched again this moves on to the next
+    // dirty segment, and past the last one once none are left
+    pub(super) fn advance_outermost(
+        &mut self,
+        choice: &mut Choice,
+        continue_op_index: usize,
+        bindings: &mut [Value],
+    ) -> bool {
+        if self.in_segment && self.current + 1 < self.segments.len() {
+            let next = self.dirty.range((self.current + 1)..).next().copied();
+            match next {
+                Some(index) => {
+                    *choice = self.enter(index, bindings, true);
+                    return true;
+                },
+                None => {
+                    *choice = self.enter(self.segments.len() - 1, bindings, false);
+                },
+            }
+        }
+        let checkpoint = choice.checkpoint();
+        if !choice.advance(bindings) {
+            self.in_segment = false;
+            return false;
+        }
+        if let Some(choice) = checkpoint {
+            self.segments.push(Segment {
+                choice,
+                continue_op_index,
+                reads: ReadLog::default(),
+            });
+            self.current = self.segments.len() - 1;
+            self.in_segment = true;
+        }
+        true
+    }
+
+    fn enter(&mut self, index: usize, bindings: &mut [Value], search: bool) -> Choice {
+        let segment = &mut self.segments[index];
+        let mut choice = segment.choice.checkpoint().expect("segment choice");
+        let advanced = choice.advance(bindings);
+        debug_assert!(advanced);
+        if search {
+            segment.reads = ReadLog::default();
+            self.dirty.remove(&index);
+        }
+        self.current = index;
+        self.in_segment = true;
+        choice
+    }
+
+    fn active(&self) -> &ReadLog {
+        if self.in_segment {
+            &self.segments[self.current].reads
+        } else {
+            &self.prefix
+        }
+    }
+
+    fn active_mut(&mut self) -> &mut ReadLog {
+        if self.in_segment {
+            &mut self.segments[self.current].reads
+        } else {
+            &mut self.prefix
+        }
+    }
+}
+
+impl Search {
+
+    fn tracking() -> Self {
+        Self {
+            reads: Some(Reads::default()),
+            ..Self::default()
+        }
+    }
+
+    fn invalidate<I>(&mut self, changed: I) -> bool
+    where
+        I: Iterator<Item = Id>,
+    {
+        let in_progress = !self.frames.is_empty();
+        let reads = match &mut self.reads {
+            Some(reads) => reads,
+            None => return false,
+        };
+        let mut invalidated = false;
+        for id in changed {
+            if let Some(position) = reads.prefix.position(id) {
+                invalidated = true;
+                if reads.segments.is_empty() {
+                    reads.invalid_from = earliest(reads.invalid_from, position);
+                } else {
+                    reads.restart = true;
+                }
+            }
+            for &index in reads.readers.get(&id).into_iter().flatten() {
+                let position = match reads.segments[index].reads.position(id) {
+                    Some(position) => position,
+                    None => continue,
+                };
+                invalidated = true;
+                if in_progress && reads.in_segment && index == reads.current {
+                    reads.invalid_from = earliest(reads.invalid_from, position);
+                } else {
+                    reads.dirty.insert(index);
+                }
+            }
+        }
+        invalidated
+    }
+
+    // prepares the search to continue after changes. Returns false when
+    // there is nothing left that could produce a match.
+    fn resume(&mut self, bindings: &mut [Value]) -> bool {
+        let reads = self.reads.as_mut().expect("tracked search");
+        if reads.restart {
+            self.restart();
+            return true;
+        }
+        let invalid_from = reads.invalid_from.take();
+        let first_dirty = reads.dirty.iter().next().copied();
+        if self.frames.is_empty() {
+            return match first_dirty {
+                Some(index) => {
+                    self.enter_segment(index, bindings);
+                    true
+                },
+                None if invalid_from.is_some() => {
+                    self.restart();
+                    true
+                },
+                None => false,
+            };
+        }
+        if let Some(index) = first_dirty.filter(|index| *index < reads.current) {
+            // the unfinished segment is searched again once it comes up
+            reads.dirty.insert(reads.current);
+            self.enter_segment(index, bindings);
+            return true;
+        }
+
+        // continue from the deepest choice made before any invalid read.
+        // Choices inside optional scopes track whether they matched, so
+        // they can't be resumed
+        let limit = invalid_from.unwrap_or(usize::MAX);
+        let optional_start = self.frames
+            .iter()
+            .position(|frame| matches!(frame, Frame::OptionalScope { .. }))
+            .unwrap_or(self.frames.len());
+        let resume_index = self.frames[..optional_start]
+            .iter()
+            .rposition(|frame| matches!(
+                frame,
+                Frame::Choice { reads_before, .. } if *reads_before <= limit
+            ));
+        let resume_index = match resume_index {
+            Some(index) => index,
+            None => {
+                self.restart();
+                return true;
+            },
+        };
+        self.frames.truncate(resume_index + 1);
+        if let Some(Frame::Choice { continue_op_index, reads_before, .. }) = self.frames.last() {
+            self.op_index = *continue_op_index;
+            if let Some(reads) = &mut self.reads {
+                reads.active_mut().truncate(*reads_before);
+            }
+        }
+        true
+    }
+
+    fn enter_segment(&mut self, index: usize, bindings: &mut [Value]) {
+        let reads = self.reads.as_mut().expect("tracked search");
+        let choice = reads.enter(index, bindings, true);
+        let continue_op_index = reads.segments[index].continue_op_index;
+        self.frames.clear();
+        self.frames.push(Frame::Choice {
+            choice,
+            continue_op_index,
+            reads_before: 0,
+        });
+        self.op_index = continue_op_index;
+    }
+}
+
+#[derive(Debug, Default)]
+struct ReadLog {
+    order: Vec<Id>,
+    positions: FnvHashMap<Id, usize>,
+}
+
+impl ReadLog {
+
+    fn record(&mut self, id: Id) -> bool {
+        if self.positions.contains_key(&id) {
+            return false;
+        }
+        self.positions.insert(id, self.order.len());
+        self.order.push(id);
+        true
+    }
+
+    fn position(&self, id: Id) -> Option<usize> {
+        self.positions.get(&id).copied()
+    }
+
+    fn truncate(&mut self, len: usize) {
+        for id in self.order.drain(len..) {
+            self.positions.remove(&id);
+        }
+    }
+}
+
+fn earliest(invalid_from: Option<usize>, position: usize) -> Option<usize> {
+    Some(invalid_from.map_or(position, |invalid_from| invalid_from.min(position)))
+}
+
+fn read_attributes(ops: &[Op]) -> Option<FnvHashSet<Symbol>> {
+    let mut attributes = FnvHashSet::default();
+    for op in ops {
+        match op {
+            Op::SearchAttributeBinding { attribute, .. } |
+            Op::RequireAttributeBinding { attribute, .. } |
+            Op::RequireAttributeValue { attribute, .. } |
+            Op::RequireAttribute { attribute, .. } => {
+                attributes.insert(attribute.clone());
+            },
+            Op::SearchAttributeClosure { closure } |
+            Op::RequireAttributeClosure { closure } => {
+                attributes.insert(closure.attribute.clone());
+            },
+            Op::SearchAttributeNames { .. } |
+            Op::SearchAttributeNamesWithValue { .. } |
+            Op::SearchAttributePairs { .. } |
+            Op::SearchVariableAttributeBinding { .. } |
+            Op::RequireVariableAttributeBinding { .. } |
+            Op::RequireVariableAttribute { .. } => {
+                return None;
+            },
+            _ => (),
+        }
+    }
+    Some(attributes)
+}
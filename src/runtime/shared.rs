@@ -0,0 +1,134 @@
+use crate::{Value, Access, RuntimeError};
+use crate::compiler::{CompiledRule, SharedPrefixes};
+use super::{
+    Search,
+    ApplyEffects,
+    RunContext,
+    RuntimeControl,
+    attempt_rule_firing,
+    apply_rule,
+    find_first_bindings,
+};
+
+// matches of shared prefixes are collected on demand and kept until a rule
+// fires, so every rule starting with the same selects can continue from
+// them instead of searching them again
+#[derive(Debug)]
+pub struct SharedMatcher<'a> {
+    prefixes: &'a SharedPrefixes,
+    inputs: Vec<Value>,
+    nodes: Vec<NodeMatches>,
+}
+
+#[derive(Debug, Default)]
+struct NodeMatches {
+    matches: Vec<Vec<Value>>,
+    parent_index: usize,
+    search: Option<(Search, Vec<Value>)>,
+    exhausted: bool,
+}
+
+impl<'a> SharedMatcher<'a> {
+
+    pub fn new(prefixes: &'a SharedPrefixes, bindings: &[Value]) -> Self {
+        Self {
+            prefixes,
+            inputs: bindings.to_vec(),
+            nodes: prefixes.nodes().iter().map(|_| NodeMatches::default()).collect(),
+        }
+    }
+
+    pub fn attempt_rule_firing(
+        &mut self,
+        index: usize,
+        rule: &CompiledRule,
+        space: &mut dyn Access,
+        bindings: &mut [Value],
+        effects: &mut ApplyEffects,
+        context: RunContext<'_>,
+    ) -> Result<bool, RuntimeError> {
+        let prefix = match self.prefixes.rule(index) {
+            Some(prefix) => prefix,
+            None => {
+                let fired = attempt_rule_firing(rule, space, bindings, effects, context)?;
+                if fired {
+                    self.reset();
+                }
+                return Ok(fired);
+            },
+        };
+        effects.clear();
+        let ops = &rule.ops()[prefix.ops_len..];
+        let mut match_index = 0;
+        let found = loop {
+            if !self.fill(prefix.node, match_index, space) {
+                break false;
+            }
+            bindings.clone_from_slice(&self.nodes[prefix.node].matches[match_index]);
+            if find_first_bindings(ops, space, bindings) {
+                break true;
+            }
+            match_index += 1;
+        };
+        if !found {
+
+            #[cfg(feature = "tracing")]
+            tracing::trace!(rule = rule.name().as_ref(), outcome = "failed match");
+
+            return Ok(false);
+        }
+        let fired = apply_rule(rule, space, bindings, effects, context)?;
+        if fired {
+            self.reset();
+        }
+        Ok(fired)
+    }
+
+    fn reset(&mut self) {
+        for node in &mut self.nodes {
+            *node = NodeMatches::default();
+        }
+    }
+
+    // makes sure the match at `index` of a prefix node is available,
+    // returning false when the node has fewer matches
+    fn fill(&mut self, node: usize, index: usize, space: &dyn Access) -> bool {
+        let prefixes = self.prefixes;
+        loop {
+            let state = &mut self.nodes[node];
+            if index < state.matches.len() {
+                return true;
+            }
+            if state.exhausted {
+                return false;
+            }
+            if let Some((search, bindings)) = &mut state.search {
+                let ops = &prefixes.node(node).ops;
+                if search.run(ops, space, bindings, |_| RuntimeControl::Stop) {
+                    state.matches.push(bindings.clone());
+                    search.skip_match();
+                } else {
+                    state.search = None;
+                    state.parent_index += 1;
+                }
+                continue;
+            }
+            let parent_index = state.parent_index;
+            let bindings = match prefixes.node(node).parent {
+                Some(parent) => {
+                    if !self.fill(parent, parent_index, space) {
+                        self.nodes[node].exhausted = true;
+                        return false;
+                    }
+                    self.nodes[parent].matches[parent_index].clone()
+                },
+                None if parent_index == 0 => self.inputs.clone(),
+                None => {
+                    state.exhausted = true;
+                    return false;
+                },
+            };
+            self.nodes[node].search = Some((Search::new(), bindings));
+        }
+    }
+}
@@ -61,12 +61,19 @@ impl ObjectSet {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Space {
     root_objects: ObjectSet,
     objects: ObjectData,
 }
 
+impl Default for Space {
+
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Space {
 
     pub fn new() -> Self {
@@ -94,7 +101,7 @@ impl Space {
             .objects()
             .iter()
             .copied()
-            .map(|id| Value::from(id))
+            .map(Value::from)
             .collect::<Vec<_>>();
         let mut trace = roots
             .iter()
@@ -133,6 +140,87 @@ impl Space {
 
         orig_len - self.objects.len()
     }
+
+    pub fn statistics(&self) -> Statistics {
+        let mut statistics = Statistics::default();
+        for attributes in self.objects.values() {
+            if attributes.iter().all(|(_, values)| values.is_empty()) {
+                continue;
+            }
+            statistics.objects += 1;
+            for (name, values) in attributes {
+                if values.is_empty() {
+                    continue;
+                }
+                let attribute = statistics.attributes.entry(name.clone()).or_default();
+                attribute.objects += 1;
+                attribute.values += values.len();
+            }
+        }
+        statistics
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Statistics {
+    objects: usize,
+    attributes: FnvHashMap<Symbol, AttributeStatistics>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AttributeStatistics {
+    pub objects: usize,
+    pub values: usize,
+}
+
+const MIN_FAN_OUT: f64 = 0.1;
+
+impl Statistics {
+
+    pub fn objects(&self) -> usize {
+        self.objects
+    }
+
+    pub fn attribute(&self, name: &str) -> AttributeStatistics {
+        self.attributes.get(&Symbol::from(name)).copied().unwrap_or_default()
+    }
+
+    pub fn fan_out(&self, name: &str) -> f64 {
+        let attribute = self.attribute(name);
+        ratio(attribute.values, attribute.objects)
+    }
+
+    // values per attribute, over all attributes
+    pub fn mean_fan_out(&self) -> f64 {
+        let (objects, values) = self.totals();
+        ratio(values, objects)
+    }
+
+    pub fn attributes_per_object(&self) -> f64 {
+        let (attributes, _) = self.totals();
+        ratio(attributes, self.objects)
+    }
+
+    pub fn values_per_object(&self) -> f64 {
+        let (_, values) = self.totals();
+        ratio(values, self.objects)
+    }
+
+    fn totals(&self) -> (usize, usize) {
+        self.attributes
+            .values()
+            .fold((0, 0), |(objects, values), attribute| {
+                (objects + attribute.objects, values + attribute.values)
+            })
+    }
+}
+
+fn ratio(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        MIN_FAN_OUT
+    } else {
+        (count as f64 / total as f64).max(MIN_FAN_OUT)
+    }
 }
 
 impl Access for Space {
@@ -155,6 +243,10 @@ impl Access for Space {
         self.root_objects.add(object)
     }
 
+    fn object_ids(&self) -> Vec<Id> {
+        self.objects.keys().copied().collect()
+    }
+
     fn unregister_root(&mut self, object: Id) -> bool {
         self.root_objects.remove(object)
     }
@@ -170,7 +262,7 @@ impl Access for Space {
     fn attributes_mut(&mut self, object: Id) -> AttributesMut<'_> {
         let attributes = self.objects
             .entry(object)
-            .or_insert_with(Vec::new);
+            .or_default();
         AttributesMut::new(object, attributes)
     }
 
@@ -225,6 +317,30 @@ pub trait Access: Debug {
 
     fn roots(&self) -> &[Id];
 
+    // implementations that can't list their objects fall back to the
+    // objects reachable from the roots
+    fn object_ids(&self) -> Vec<Id> {
+        let mut ids = Vec::new();
+        let mut trace = self.roots().iter().copied().map(Value::from).collect::<Vec<_>>();
+        while let Some(value) = trace.pop() {
+            match value {
+                Value::Object(id) => {
+                    if let Err(index) = ids.binary_search(&id) {
+                        ids.insert(index, id);
+                        trace.extend(self.attributes(id).iter().map(|(_, value)| value.clone()));
+                    }
+                },
+                Value::Tuple(values) => {
+                    trace.extend(values.iter().cloned());
+                },
+                Value::Symbol(_) |
+                Value::Int(_) |
+                Value::Float(_) => (),
+            }
+        }
+        ids
+    }
+
     fn attributes(&self, object: Id) -> Attributes<'_>;
 
     fn attributes_mut(&mut self, object: Id) -> AttributesMut<'_>;
@@ -258,6 +374,22 @@ impl<'a> Iterator for ValuesIter<'a> {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct NamesIter<'a> {
+    inner: std::slice::Iter<'a, (Symbol, Arc<Vec<Value>>)>,
+}
+
+impl<'a> Iterator for NamesIter<'a> {
+
+    type Item = &'a Symbol;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.by_ref()
+            .find(|(_, values)| !values.is_empty())
+            .map(|(name, _)| name)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct AttributesIter<'a> {
     attributes: &'a [(Symbol, Arc<Vec<Value>>)],
@@ -271,8 +403,8 @@ impl<'a> AttributesIter<'a> {
             Self {
                 attributes: rest,
                 state: AttributesIterState::Current {
-                    name: name,
-                    values: values,
+                    name,
+                    values,
                 },
             }
         } else {
@@ -344,7 +476,7 @@ impl<'a> Attributes<'a> {
         self.len() == 0
     }
 
-    fn to_attr_data(&self) -> AttrData {
+    pub(crate) fn to_attr_data(self) -> AttrData {
         self.attributes.to_vec()
     }
 
@@ -370,7 +502,13 @@ impl<'a> Attributes<'a> {
     }
 
     pub fn iter(&self) -> AttributesIter<'a> {
-        AttributesIter::new(&self.attributes)
+        AttributesIter::new(self.attributes)
+    }
+
+    pub fn iter_names(&self) -> NamesIter<'a> {
+        NamesIter {
+            inner: self.attributes.iter(),
+        }
     }
 
     pub fn iter_named(&self, name: &str) -> ValuesIter<'a> {
@@ -382,6 +520,13 @@ impl<'a> Attributes<'a> {
         ValuesIter::new(&[])
     }
 
+    pub(crate) fn shared_named(&self, name: &str) -> Option<Arc<Vec<Value>>> {
+        self.attributes
+            .iter()
+            .find(|(ex_name, _)| ex_name.as_ref() == name)
+            .map(|(_, values)| values.clone())
+    }
+
     pub fn single_named(&self, name: &str) -> Option<&'a Value> {
         self.iter_named(name).next()
     }
@@ -411,7 +556,7 @@ impl<'a> AttributesMut<'a> {
     }
 
     pub fn inspect(&'a self) -> Attributes<'a> {
-        Attributes::new(self.object, &self.attributes)
+        Attributes::new(self.object, self.attributes)
     }
 
     pub fn add<S, V>(&mut self, name: S, value: V)
@@ -459,7 +604,7 @@ impl<'a> AttributesMut<'a> {
     pub fn remove_all_named(&mut self, name: &str) -> Vec<Value> {
         for (ex_name, ex_values) in self.attributes.iter_mut() {
             if ex_name.as_ref() == name {
-                return std::mem::replace(Arc::make_mut(ex_values), Vec::new());
+                return std::mem::take(Arc::make_mut(ex_values));
             }
         }
         Vec::new()
@@ -522,6 +667,28 @@ impl<'a> Transaction<'a> {
     fn unpack(self) -> (ObjectSet, ObjectData) {
         (self.local_root_objects, self.local_objects)
     }
+
+    pub(crate) fn outer(&self) -> &'a dyn Access {
+        self.outer
+    }
+
+    pub(crate) fn changed_attributes(&self) -> Vec<(Id, Symbol)> {
+        let mut changed = Vec::new();
+        for (&id, attributes) in &self.local_objects {
+            let previous = self.outer.attributes(id);
+            for (name, values) in attributes {
+                if previous.iter_named(name).ne(values.iter()) {
+                    changed.push((id, name.clone()));
+                }
+            }
+            for name in previous.iter_names() {
+                if !attributes.iter().any(|(ex_name, _)| ex_name == name) {
+                    changed.push((id, name.clone()));
+                }
+            }
+        }
+        changed
+    }
 }
 
 impl<'a> Access for Transaction<'a> {
@@ -543,6 +710,14 @@ impl<'a> Access for Transaction<'a> {
         self.local_root_objects.add(object)
     }
 
+    fn object_ids(&self) -> Vec<Id> {
+        let mut ids = self.outer.object_ids();
+        ids.extend(self.local_objects.keys().copied());
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+
     fn unregister_root(&mut self, object: Id) -> bool {
         self.local_root_objects.remove(object)
     }
@@ -2,7 +2,9 @@
 use std::sync::{Arc};
 use std::path::{Path};
 use std::io::{Error as IoError};
-use crate::{parser, compiler, runtime, Id, Value, Access, Transaction, RuntimeControl};
+use crate::{parser, ast, compiler, runtime, Id, Value, Access, Transaction, RuntimeControl, SystemRunMode};
+use crate::{Statistics};
+use crate::runtime::{RunContext, Matcher};
 
 #[derive(Debug)]
 pub struct System {
@@ -10,10 +12,20 @@ pub struct System {
     input_variables: Vec<Arc<str>>,
     max_binding_len: usize,
     rules: Vec<compiler::CompiledRule>,
+    prefixes: compiler::SharedPrefixes,
+    matcher: Matcher,
+    compiled: Vec<runtime::CompiledSearch>,
+    statistics: Option<Statistics>,
     #[cfg(feature = "tracing")]
     tracing_span: tracing::Span,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedPrefix {
+    pub rules: Vec<Arc<str>>,
+    pub ops_len: usize,
+}
+
 #[derive(Debug, Clone, thiserror::Error)]
 pub enum SystemError {
     #[error("invalid system name `{0}`")]
@@ -22,6 +34,8 @@ pub enum SystemError {
     InvalidInputVariable(Arc<str>),
     #[error("duplicate input variable name `${0}`")]
     DuplicateInputVariable(Arc<str>),
+    #[error("duplicate system name `{0}`")]
+    DuplicateSystem(Arc<str>),
 }
 
 #[derive(Debug, Clone, thiserror::Error)]
@@ -30,6 +44,21 @@ pub enum RuntimeError {
     Stopped {
         count: u64,
     },
+    #[error("halted by rule `{rule}` after {count} rule firings")]
+    Halted {
+        count: u64,
+        rule: Arc<str>,
+        reason: Option<Value>,
+    },
+    #[error("unknown system `{name}`")]
+    UnknownSystem {
+        name: Arc<str>,
+    },
+    #[error("running system `{name}` exceeded the maximum depth of {max_depth}")]
+    DepthLimitExceeded {
+        name: Arc<str>,
+        max_depth: usize,
+    },
     #[error("expected {expected} input arguments but received {received}")]
     InvalidInputArgumentLen {
         expected: usize,
@@ -58,6 +87,10 @@ impl System {
             input_variables: input_variables.iter().map(|&var| var.into()).collect(),
             max_binding_len: input_variables.len(),
             rules: Vec::new(),
+            prefixes: compiler::SharedPrefixes::default(),
+            matcher: Matcher::default(),
+            compiled: Vec::new(),
+            statistics: None,
             #[cfg(feature = "tracing")]
             tracing_span: tracing::debug_span!("system", system_name = name),
         })
@@ -71,6 +104,15 @@ impl System {
         &self.input_variables
     }
 
+    pub fn matcher(&self) -> Matcher {
+        self.matcher
+    }
+
+    pub fn set_matcher(&mut self, matcher: Matcher) {
+        self.matcher = matcher;
+        self.compile_searches();
+    }
+
     pub fn count(&self) -> usize {
         self.rules.len()
     }
@@ -90,17 +132,83 @@ impl System {
         self.load(compiled_rule)
     }
 
-    fn load(&mut self, rule: compiler::CompiledRule) -> Result<(), LoadError> {
+    pub fn rule_source(&self, name: &str) -> Result<String, compiler::DecompileError> {
+        let rule = self.rules
+            .iter()
+            .find(|rule| rule.name().as_ref() == name)
+            .ok_or_else(|| compiler::DecompileError::UnknownRule(name.into()))?;
+        let source = rule.to_source(&self.name, &self.input_variables)?;
+        format_decompiled(&source)
+    }
+
+    pub fn source(&self) -> Result<String, compiler::DecompileError> {
+        let inputs = self.input_variables
+            .iter()
+            .map(|input| format!("${}", input))
+            .collect::<Vec<_>>();
+        let mut source = format!("system {}({});\n", self.name, inputs.join(", "));
+        for rule in &self.rules {
+            source.push_str(&rule.to_source(&self.name, &self.input_variables)?);
+        }
+        format_decompiled(&source)
+    }
+
+    // the statistics are kept to plan rules loaded later on
+    pub fn replan(&mut self, statistics: Option<&Statistics>) {
+        self.statistics = statistics.cloned();
+        let input_variables_len = self.input_variables.len();
+        for rule in &mut self.rules {
+            rule.replan(input_variables_len, statistics);
+        }
+        self.prefixes = compiler::SharedPrefixes::new(&self.rules);
+        self.compile_searches();
+    }
+
+    pub fn shared_prefixes(&self) -> Vec<SharedPrefix> {
+        self.prefixes
+            .nodes()
+            .iter()
+            .map(|node| SharedPrefix {
+                rules: node.rules.iter().map(|&index| self.rules[index].name().clone()).collect(),
+                ops_len: node.ops_len,
+            })
+            .collect()
+    }
+
+    pub fn explain(&self, name: &str) -> Option<String> {
+        self.rules
+            .iter()
+            .find(|rule| rule.name().as_ref() == name)
+            .map(|rule| rule.explain(&self.input_variables))
+    }
+
+    fn load(&mut self, mut rule: compiler::CompiledRule) -> Result<(), LoadError> {
         if self.rules.iter().any(|ex| ex.name() == rule.name()) {
             return Err(LoadError::DuplicateRuleName(self.name.clone(), rule.name().clone()));
         }
+        if let Some(statistics) = &self.statistics {
+            rule.replan(self.input_variables.len(), Some(statistics));
+        }
         if rule.bindings_len() > self.max_binding_len {
             self.max_binding_len = rule.bindings_len();
         }
         self.rules.push(rule);
+        self.prefixes = compiler::SharedPrefixes::new(&self.rules);
+        self.compile_searches();
         Ok(())
     }
 
+    // compiled searches are only kept around while they are in use
+    fn compile_searches(&mut self) {
+        self.compiled = match self.matcher {
+            Matcher::Compiled => self.rules
+                .iter()
+                .map(|rule| runtime::CompiledSearch::new(rule.ops()))
+                .collect(),
+            Matcher::Search | Matcher::Incremental => Vec::new(),
+        };
+    }
+
     fn make_bindings_storage(&self, inputs: &[Id]) -> Result<Vec<Value>, RuntimeError> {
         self.verify_inputs(inputs)?;
         let rest_bindings_len = self.max_binding_len
@@ -114,6 +222,13 @@ impl System {
         Ok(bindings)
     }
 
+    fn incremental_matcher(&self, bindings: &[Value]) -> Option<runtime::IncrementalMatcher> {
+        match self.matcher {
+            Matcher::Incremental => Some(runtime::IncrementalMatcher::new(&self.rules, bindings)),
+            Matcher::Search | Matcher::Compiled => None,
+        }
+    }
+
     fn verify_inputs(&self, inputs: &[Id]) -> Result<(), RuntimeError> {
         if inputs.len() == self.input_variables.len() {
             Ok(())
@@ -130,6 +245,25 @@ impl System {
         space: &mut dyn Access,
         inputs: &[Id],
     ) -> Result<Option<Arc<str>>, RuntimeError> {
+        self.run_to_first_with_output(space, inputs, &mut Vec::new())
+    }
+
+    pub fn run_to_first_with_output(
+        &self,
+        space: &mut dyn Access,
+        inputs: &[Id],
+        output: &mut Vec<(Arc<str>, Value)>,
+    ) -> Result<Option<Arc<str>>, RuntimeError> {
+        self.run_to_first_in(RunContext::new(None), space, inputs, output)
+    }
+
+    fn run_to_first_in(
+        &self,
+        context: RunContext<'_>,
+        space: &mut dyn Access,
+        inputs: &[Id],
+        output: &mut Vec<(Arc<str>, Value)>,
+    ) -> Result<Option<Arc<str>>, RuntimeError> {
 
         #[cfg(feature = "tracing")]
         let _enter = self.tracing_span.enter();
@@ -138,9 +272,23 @@ impl System {
         tracing::trace!(system_run_mode = "to-first");
 
         let mut bindings = self.make_bindings_storage(inputs)?;
-        for rule in &self.rules {
-            let rule_fired = runtime::attempt_rule_firing(rule, space, &mut bindings);
+        let mut effects = runtime::ApplyEffects::default();
+        let mut shared = runtime::SharedMatcher::new(&self.prefixes, &bindings);
+        let compiled = runtime::CompiledMatcher::new(&self.compiled);
+        let mut incremental = self.incremental_matcher(&bindings);
+        for (index, rule) in self.rules.iter().enumerate() {
+            let bindings = &mut bindings;
+            let rule_fired = match &mut incremental {
+                Some(matcher) =>
+                    matcher.attempt_rule_firing(index, rule, space, &mut effects, context)?,
+                None if self.matcher == Matcher::Compiled => compiled
+                    .attempt_rule_firing(index, rule, space, bindings, &mut effects, context)?,
+                None => shared
+                    .attempt_rule_firing(index, rule, space, bindings, &mut effects, context)?,
+            };
             if rule_fired {
+                deliver_output(rule.name(), &mut effects.emitted, output);
+                check_halted(rule.name(), &mut effects, 1)?;
                 return Ok(Some(rule.name().clone()));
             }
         }
@@ -163,7 +311,34 @@ impl System {
         &self,
         space: &mut dyn Access,
         inputs: &[Id],
+        control: F,
+    ) -> Result<u64, RuntimeError>
+    where
+        F: FnMut(&Arc<str>, &dyn Access, u64) -> RuntimeControl,
+    {
+        self.run_rule_saturation_with_control_and_output(space, inputs, control, &mut Vec::new())
+    }
+
+    pub fn run_rule_saturation_with_control_and_output<F>(
+        &self,
+        space: &mut dyn Access,
+        inputs: &[Id],
+        control: F,
+        output: &mut Vec<(Arc<str>, Value)>,
+    ) -> Result<u64, RuntimeError>
+    where
+        F: FnMut(&Arc<str>, &dyn Access, u64) -> RuntimeControl,
+    {
+        self.run_rule_saturation_in(RunContext::new(None), space, inputs, control, output)
+    }
+
+    fn run_rule_saturation_in<F>(
+        &self,
+        context: RunContext<'_>,
+        space: &mut dyn Access,
+        inputs: &[Id],
         mut control: F,
+        output: &mut Vec<(Arc<str>, Value)>,
     ) -> Result<u64, RuntimeError>
     where
         F: FnMut(&Arc<str>, &dyn Access, u64) -> RuntimeControl,
@@ -176,11 +351,24 @@ impl System {
 
         let mut run_count = 0;
         let mut bindings = self.make_bindings_storage(inputs)?;
-        for rule in &self.rules {
+        let mut effects = runtime::ApplyEffects::default();
+        let compiled = runtime::CompiledMatcher::new(&self.compiled);
+        let mut incremental = self.incremental_matcher(&bindings);
+        for (index, rule) in self.rules.iter().enumerate() {
             'current_rule: loop {
-                let rule_fired = runtime::attempt_rule_firing(rule, space, &mut bindings);
+                let bindings = &mut bindings;
+                let rule_fired = match &mut incremental {
+                    Some(matcher) =>
+                        matcher.attempt_rule_firing(index, rule, space, &mut effects, context)?,
+                    None if self.matcher == Matcher::Compiled => compiled
+                        .attempt_rule_firing(index, rule, space, bindings, &mut effects, context)?,
+                    None =>
+                        runtime::attempt_rule_firing(rule, space, bindings, &mut effects, context)?,
+                };
                 if rule_fired {
                     run_count += 1;
+                    deliver_output(rule.name(), &mut effects.emitted, output);
+                    check_halted(rule.name(), &mut effects, run_count)?;
                     match control(rule.name(), space, run_count) {
                         RuntimeControl::Continue => {
                             continue 'current_rule;
@@ -216,7 +404,34 @@ impl System {
         &self,
         space: &mut dyn Access,
         inputs: &[Id],
+        control: F,
+    ) -> Result<u64, RuntimeError>
+    where
+        F: FnMut(&Arc<str>, &dyn Access, u64) -> RuntimeControl,
+    {
+        self.run_saturation_with_control_and_output(space, inputs, control, &mut Vec::new())
+    }
+
+    pub fn run_saturation_with_control_and_output<F>(
+        &self,
+        space: &mut dyn Access,
+        inputs: &[Id],
+        control: F,
+        output: &mut Vec<(Arc<str>, Value)>,
+    ) -> Result<u64, RuntimeError>
+    where
+        F: FnMut(&Arc<str>, &dyn Access, u64) -> RuntimeControl,
+    {
+        self.run_saturation_in(RunContext::new(None), space, inputs, control, output)
+    }
+
+    fn run_saturation_in<F>(
+        &self,
+        context: RunContext<'_>,
+        space: &mut dyn Access,
+        inputs: &[Id],
         mut control: F,
+        output: &mut Vec<(Arc<str>, Value)>,
     ) -> Result<u64, RuntimeError>
     where
         F: FnMut(&Arc<str>, &dyn Access, u64) -> RuntimeControl,
@@ -229,11 +444,25 @@ impl System {
 
         let mut run_count = 0;
         let mut bindings = self.make_bindings_storage(inputs)?;
+        let mut effects = runtime::ApplyEffects::default();
+        let mut shared = runtime::SharedMatcher::new(&self.prefixes, &bindings);
+        let compiled = runtime::CompiledMatcher::new(&self.compiled);
+        let mut incremental = self.incremental_matcher(&bindings);
         'firing: loop {
-            for rule in &self.rules {
-                let rule_fired = runtime::attempt_rule_firing(rule, space, &mut bindings);
+            for (index, rule) in self.rules.iter().enumerate() {
+                let bindings = &mut bindings;
+                let rule_fired = match &mut incremental {
+                    Some(matcher) =>
+                        matcher.attempt_rule_firing(index, rule, space, &mut effects, context)?,
+                    None if self.matcher == Matcher::Compiled => compiled
+                        .attempt_rule_firing(index, rule, space, bindings, &mut effects, context)?,
+                    None => shared
+                        .attempt_rule_firing(index, rule, space, bindings, &mut effects, context)?,
+                };
                 if rule_fired {
                     run_count += 1;
+                    deliver_output(rule.name(), &mut effects.emitted, output);
+                    check_halted(rule.name(), &mut effects, run_count)?;
                     match control(rule.name(), space, run_count) {
                         RuntimeControl::Continue => {
                             continue 'firing;
@@ -261,6 +490,31 @@ impl System {
     ) -> Result<usize, RuntimeError>
     where
         F: FnMut(Transaction<'space>, &Arc<str>) -> RuntimeControl,
+    {
+        self.run_splinter_with_output(tx, inputs, |new_tx, rule_name, _| collect(new_tx, rule_name))
+    }
+
+    pub fn run_splinter_with_output<'space, F>(
+        &self,
+        tx: &Transaction<'space>,
+        inputs: &[Id],
+        collect: F,
+    ) -> Result<usize, RuntimeError>
+    where
+        F: FnMut(Transaction<'space>, &Arc<str>, Vec<Value>) -> RuntimeControl,
+    {
+        self.run_splinter_in(RunContext::new(None), tx, inputs, collect)
+    }
+
+    fn run_splinter_in<'space, F>(
+        &self,
+        context: RunContext<'_>,
+        tx: &Transaction<'space>,
+        inputs: &[Id],
+        mut collect: F,
+    ) -> Result<usize, RuntimeError>
+    where
+        F: FnMut(Transaction<'space>, &Arc<str>, Vec<Value>) -> RuntimeControl,
     {
         #[cfg(feature = "tracing")]
         let _enter = self.tracing_span.enter();
@@ -271,14 +525,31 @@ impl System {
         let mut bindings = self.make_bindings_storage(inputs)?;
         let mut total_count = 0;
         let mut stopped = false;
+        let mut halt_reason = None;
         'firing: for rule in &self.rules {
-            total_count += runtime::splinter_rule(rule, tx, &mut bindings, |new_tx| {
-                let result = collect(new_tx, rule.name());
+            total_count += runtime::splinter_rule(rule, tx, &mut bindings, context, |new_tx, effects| {
+                let result = collect(new_tx, rule.name(), effects.emitted);
+                if effects.halted {
+                    halt_reason = Some(effects.halt_reason);
+                    stopped = true;
+                    return RuntimeControl::Stop;
+                }
                 if let RuntimeControl::Stop = result {
                     stopped = true;
                 }
                 result
-            });
+            })?;
+            if let Some(reason) = halt_reason {
+
+                #[cfg(feature = "tracing")]
+                tracing::debug!("system halted");
+
+                return Err(RuntimeError::Halted {
+                    count: total_count as u64,
+                    rule: rule.name().clone(),
+                    reason,
+                });
+            }
             if stopped {
 
                 #[cfg(feature = "tracing")]
@@ -291,6 +562,218 @@ impl System {
     }
 }
 
+const DEFAULT_MAX_DEPTH: usize = 16;
+
+#[derive(Debug)]
+pub struct SystemRegistry {
+    systems: Vec<System>,
+    max_depth: usize,
+    statistics: Option<Statistics>,
+}
+
+impl Default for SystemRegistry {
+
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SystemRegistry {
+
+    pub fn new() -> Self {
+        Self {
+            systems: Vec::new(),
+            max_depth: DEFAULT_MAX_DEPTH,
+            statistics: None,
+        }
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    pub fn register(&mut self, mut system: System) -> Result<(), SystemError> {
+        if self.get(system.name()).is_some() {
+            return Err(SystemError::DuplicateSystem(system.name().clone()));
+        }
+        if let Some(statistics) = &self.statistics {
+            system.replan(Some(statistics));
+        }
+        self.systems.push(system);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&System> {
+        self.systems.iter().find(|system| system.name().as_ref() == name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut System> {
+        self.systems.iter_mut().find(|system| system.name().as_ref() == name)
+    }
+
+    pub fn replan(&mut self, statistics: Option<&Statistics>) {
+        self.statistics = statistics.cloned();
+        for system in &mut self.systems {
+            system.replan(statistics);
+        }
+    }
+
+    pub fn loader(&mut self) -> SystemLoader<'_> {
+        SystemLoader::new(self.systems.iter_mut().collect())
+    }
+
+    fn get_required(&self, name: &str) -> Result<&System, RuntimeError> {
+        self.get(name).ok_or_else(|| RuntimeError::UnknownSystem { name: name.into() })
+    }
+
+    pub fn run_to_first(
+        &self,
+        name: &str,
+        space: &mut dyn Access,
+        inputs: &[Id],
+    ) -> Result<Option<Arc<str>>, RuntimeError> {
+        self.run_to_first_with_output(name, space, inputs, &mut Vec::new())
+    }
+
+    pub fn run_to_first_with_output(
+        &self,
+        name: &str,
+        space: &mut dyn Access,
+        inputs: &[Id],
+        output: &mut Vec<(Arc<str>, Value)>,
+    ) -> Result<Option<Arc<str>>, RuntimeError> {
+        self.get_required(name)?
+            .run_to_first_in(RunContext::new(Some(self)), space, inputs, output)
+    }
+
+    pub fn run_saturation_with_control<F>(
+        &self,
+        name: &str,
+        space: &mut dyn Access,
+        inputs: &[Id],
+        control: F,
+    ) -> Result<u64, RuntimeError>
+    where
+        F: FnMut(&Arc<str>, &dyn Access, u64) -> RuntimeControl,
+    {
+        self.run_saturation_with_control_and_output(name, space, inputs, control, &mut Vec::new())
+    }
+
+    pub fn run_saturation_with_control_and_output<F>(
+        &self,
+        name: &str,
+        space: &mut dyn Access,
+        inputs: &[Id],
+        control: F,
+        output: &mut Vec<(Arc<str>, Value)>,
+    ) -> Result<u64, RuntimeError>
+    where
+        F: FnMut(&Arc<str>, &dyn Access, u64) -> RuntimeControl,
+    {
+        self.get_required(name)?
+            .run_saturation_in(RunContext::new(Some(self)), space, inputs, control, output)
+    }
+
+    pub fn run_rule_saturation_with_control_and_output<F>(
+        &self,
+        name: &str,
+        space: &mut dyn Access,
+        inputs: &[Id],
+        control: F,
+        output: &mut Vec<(Arc<str>, Value)>,
+    ) -> Result<u64, RuntimeError>
+    where
+        F: FnMut(&Arc<str>, &dyn Access, u64) -> RuntimeControl,
+    {
+        self.get_required(name)?
+            .run_rule_saturation_in(RunContext::new(Some(self)), space, inputs, control, output)
+    }
+
+    pub fn run_splinter_with_output<'space, F>(
+        &self,
+        name: &str,
+        tx: &Transaction<'space>,
+        inputs: &[Id],
+        collect: F,
+    ) -> Result<usize, RuntimeError>
+    where
+        F: FnMut(Transaction<'space>, &Arc<str>, Vec<Value>) -> RuntimeControl,
+    {
+        self.get_required(name)?
+            .run_splinter_in(RunContext::new(Some(self)), tx, inputs, collect)
+    }
+
+    pub(crate) fn run_nested(
+        &self,
+        name: &str,
+        mode: SystemRunMode,
+        space: &mut dyn Access,
+        inputs: &[Id],
+        depth: usize,
+        emitted: &mut Vec<Value>,
+    ) -> Result<bool, RuntimeError> {
+        let system = self.get_required(name)?;
+        if depth > self.max_depth {
+            return Err(RuntimeError::DepthLimitExceeded {
+                name: system.name().clone(),
+                max_depth: self.max_depth,
+            });
+        }
+        let context = RunContext::nested(self, depth);
+        let mut output = Vec::new();
+        let result = match mode {
+            SystemRunMode::ToFirst => system
+                .run_to_first_in(context, space, inputs, &mut output)
+                .map(|fired| fired.is_some()),
+            SystemRunMode::Saturation => system
+                .run_saturation_in(context, space, inputs, |_, _, _| RuntimeControl::Continue, &mut output)
+                .map(|_| true),
+        };
+        emitted.extend(output.into_iter().map(|(_, value)| value));
+        match result {
+            Err(RuntimeError::Halted { .. }) => Ok(true),
+            other => other,
+        }
+    }
+}
+
+fn format_decompiled(source: &str) -> Result<String, compiler::DecompileError> {
+    crate::format_source(source)
+        .map_err(|error| compiler::DecompileError::InvalidSource(error.to_string().into()))
+}
+
+fn check_halted(
+    rule_name: &Arc<str>,
+    effects: &mut runtime::ApplyEffects,
+    count: u64,
+) -> Result<(), RuntimeError> {
+    if effects.halted {
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!("system halted");
+
+        Err(RuntimeError::Halted {
+            count,
+            rule: rule_name.clone(),
+            reason: effects.halt_reason.take(),
+        })
+    } else {
+        Ok(())
+    }
+}
+
+fn deliver_output(
+    rule_name: &Arc<str>,
+    emitted: &mut Vec<Value>,
+    output: &mut Vec<(Arc<str>, Value)>,
+) {
+    output.extend(emitted.drain(..).map(|value| (rule_name.clone(), value)));
+}
+
 #[derive(Debug, Clone, thiserror::Error)]
 pub enum LoadError {
     #[error("unable to parse source code:\n{0}")]
@@ -301,6 +784,12 @@ pub enum LoadError {
     DuplicateRuleName(Arc<str>, Arc<str>),
     #[error("unknown system `{0}`")]
     NoSuchSystem(Arc<str>),
+    #[error("rule for undeclared system `{0}`")]
+    UndeclaredSystem(Arc<str>),
+    #[error("conflicting input variables in declaration of system `{0}`")]
+    ConflictingSystemDeclaration(Arc<str>),
+    #[error("invalid system declaration")]
+    System(#[source] SystemError),
 }
 
 #[derive(Debug, Clone, thiserror::Error)]
@@ -321,12 +810,24 @@ pub enum FileLoadErrorKind {
 
 pub struct SystemLoader<'a> {
     systems: Vec<&'a mut System>,
+    declared: Option<Vec<System>>,
+}
+
+impl SystemLoader<'static> {
+
+    pub fn declaring() -> Self {
+        Self { systems: Vec::new(), declared: Some(Vec::new()) }
+    }
 }
 
 impl<'a> SystemLoader<'a> {
 
     pub fn new(systems: Vec<&'a mut System>) -> Self {
-        Self { systems }
+        Self { systems, declared: None }
+    }
+
+    pub fn into_declared(self) -> Vec<System> {
+        self.declared.unwrap_or_default()
     }
 
     pub fn load_file<P>(&mut self, path: P) -> Result<usize, FileLoadError>
@@ -347,22 +848,57 @@ impl<'a> SystemLoader<'a> {
     }
 
     pub fn load_str(&mut self, contents: &str) -> Result<usize, LoadError> {
-        let parsed_rules = parser::parse(contents)
+        let document = parser::parse(contents)
             .map_err(LoadError::Parse)?;
-        let rule_count = parsed_rules.len();
-        'rules: for rule in parsed_rules {
-            for system in &mut self.systems {
-                if system.name().as_ref() == rule.system_name.as_str() {
-                    let compiled = compiler::compile(&rule, system.input_variables())
-                        .map_err(LoadError::Compile)?;
-                    system.load(compiled)?;
-                    continue 'rules;
-                }
-            }
-            return Err(LoadError::NoSuchSystem(rule.system_name.as_str().into()));
+        for declaration in document.systems() {
+            self.declare(declaration)?;
+        }
+        let rule_count = document.rules().count();
+        for rule in document.rules() {
+            let system_name = rule.system_name.as_str();
+            let system = match self.find_system(system_name) {
+                Some(system) => system,
+                None => return Err(if self.declared.is_some() {
+                    LoadError::UndeclaredSystem(system_name.into())
+                } else {
+                    LoadError::NoSuchSystem(system_name.into())
+                }),
+            };
+            let compiled = compiler::compile(rule, system.input_variables())
+                .map_err(LoadError::Compile)?;
+            system.load(compiled)?;
         }
         Ok(rule_count)
     }
+
+    fn declare(&mut self, declaration: &ast::SystemDeclaration<'_>) -> Result<(), LoadError> {
+        let name = declaration.name.as_str();
+        let inputs = declaration.inputs.iter().map(|input| input.as_str()).collect::<Vec<_>>();
+        if let Some(system) = self.find_system(name) {
+            let is_matching = system.input_variables().len() == inputs.len()
+                && system.input_variables().iter().zip(&inputs).all(|(a, b)| a.as_ref() == *b);
+            return if is_matching {
+                Ok(())
+            } else {
+                Err(LoadError::ConflictingSystemDeclaration(name.into()))
+            };
+        }
+        match &mut self.declared {
+            Some(declared) => {
+                declared.push(System::new(name, &inputs).map_err(LoadError::System)?);
+                Ok(())
+            },
+            None => Err(LoadError::NoSuchSystem(name.into())),
+        }
+    }
+
+    fn find_system(&mut self, name: &str) -> Option<&mut System> {
+        self.systems
+            .iter_mut()
+            .map(|system| &mut **system)
+            .chain(self.declared.iter_mut().flatten())
+            .find(|system| system.name().as_ref() == name)
+    }
 }
 
 pub fn control_limit_total(total_limit: u64)
@@ -1,5 +1,6 @@
 
 use sym_engine::*;
+use assert_matches::{assert_matches};
 
 #[test]
 fn object_bindings() {
@@ -114,6 +115,41 @@ fn tuples() {
     assert!(!space.attributes(root).has_named("done"));
 }
 
+#[test]
+fn tuple_rests() {
+
+    let mut space = Space::new();
+    let root = space.create_id();
+    space.attributes_mut(root).add("value", vec![
+        Value::from("in"),
+        Value::from(23),
+        Value::from(42),
+        Value::from(99),
+    ]);
+
+    let mut sys = System::new("test", &["ROOT"]).unwrap();
+    sys.build_rule("test", |mut builder, input| {
+        let binding = builder.add_attribute_binding(input[0], "value");
+        let (rest_binding, last_binding) = builder.add_tuple_unpacking(binding, |builder| {
+            builder.add_value_item("in");
+            let rest_binding = builder.add_new_rest_binding();
+            let last_binding = builder.add_new_binding_item();
+            (rest_binding, last_binding)
+        });
+        builder.add_not_clause(|builder| {
+            builder.add_attribute_requirement(input[0], "done");
+        });
+        let mut builder = builder.into_apply_builder();
+        builder.add_binding_attribute_addition(input[0], "done", rest_binding);
+        builder.add_binding_attribute_addition(input[0], "last", last_binding);
+        builder
+    }).unwrap();
+
+    sys.run_saturation_with_control(&mut space, &[root], control_limit_total(10)).unwrap();
+    assert!(space.attributes(root).has("done", &Value::from(vec![23, 42])));
+    assert!(space.attributes(root).has("last", &99));
+}
+
 #[test]
 fn enums() {
 
@@ -217,6 +253,146 @@ fn calculations() {
     assert!(space.attributes(root).has("result", &130));
 }
 
+#[test]
+fn tuple_calculations() {
+
+    let mut space = Space::new();
+    let root = space.create_id();
+
+    space.attributes_mut(root).apply(|attrs| {
+        attrs.add("list", vec![1, 2, 3]);
+        attrs.add("name", "item");
+    });
+
+    let mut sys = System::new("test", &["ROOT"]).unwrap();
+    sys.build_rule("test", |mut builder, input| {
+        let binding_list = builder.add_attribute_binding(input[0], "list");
+        let binding_name = builder.add_attribute_binding(input[0], "name");
+        builder.add_tuple_member_value_requirement(binding_list, 2);
+        let binding_last = builder.add_calculation(|calc| {
+            calc.index(calc.binding(binding_list), calc.value(-1))
+        });
+        builder.add_tuple_member_binding_requirement(binding_list, binding_last);
+        let binding_result = builder.add_calculation(|calc| {
+            calc.concat(
+                calc.slice(calc.binding(binding_list), Some(calc.value(1)), None),
+                calc.tuple(vec![
+                    calc.len(calc.binding(binding_list)),
+                    calc.symbol(vec![calc.binding(binding_name), calc.binding(binding_last)]),
+                    calc.member(calc.value(4), calc.binding(binding_list)),
+                ]),
+            )
+        });
+        builder.add_not_clause(|builder| {
+            builder.add_attribute_requirement(input[0], "result");
+        });
+        let mut builder = builder.into_apply_builder();
+        builder.add_binding_attribute_addition(input[0], "result", binding_result);
+        builder
+    }).unwrap();
+
+    sys.run_saturation_with_control(&mut space, &[root], control_limit_total(10)).unwrap();
+    assert!(space.attributes(root).has("result", &Value::from(vec![
+        Value::from(2),
+        Value::from(3),
+        Value::from(3),
+        Value::from("item3"),
+        Value::from(0),
+    ])));
+}
+
+#[test]
+fn tuple_member_search() {
+
+    let mut space = Space::new();
+    let root = space.create_id();
+    space.attributes_mut(root).add("values", vec![5, 23, 42]);
+
+    let mut sys = System::new("test", &["ROOT"]).unwrap();
+    sys.build_rule("test", |mut builder, input| {
+        let values_binding = builder.add_attribute_binding(input[0], "values");
+        let value_binding = builder.add_tuple_member_binding(values_binding);
+        builder.add_not_clause(|builder| {
+            builder.add_attribute_binding_requirement(input[0], "seen", value_binding);
+        });
+        let mut builder = builder.into_apply_builder();
+        builder.add_binding_attribute_addition(input[0], "seen", value_binding);
+        builder
+    }).unwrap();
+
+    sys.run_saturation_with_control(&mut space, &[root], control_limit_total(10)).unwrap();
+    assert!(space.attributes(root).has("seen", &5));
+    assert!(space.attributes(root).has("seen", &23));
+    assert!(space.attributes(root).has("seen", &42));
+}
+
+#[test]
+fn variable_attributes() {
+
+    let mut space = Space::new();
+    let source = space.create_object().apply(|attrs| {
+        attrs.add("a", 23);
+        attrs.add("b", 42);
+        attrs.object()
+    });
+    let root = space.create_object().apply(|attrs| {
+        attrs.add("source", source);
+        attrs.object()
+    });
+
+    let mut sys = System::new("test", &["ROOT"]).unwrap();
+    sys.build_rule("test", |mut builder, input| {
+        let source_binding = builder.add_attribute_binding(input[0], "source");
+        let (name_binding, value_binding) = builder.add_attribute_pair_binding(source_binding);
+        builder.add_not_clause(|builder| {
+            builder.add_variable_attribute_binding_requirement(
+                input[0],
+                name_binding,
+                value_binding,
+            );
+        });
+        let mut builder = builder.into_apply_builder();
+        builder.add_variable_binding_attribute_addition(input[0], name_binding, value_binding);
+        builder
+    }).unwrap();
+
+    sys.run_saturation_with_control(&mut space, &[root], control_limit_total(10)).unwrap();
+    assert!(space.attributes(root).has("a", &23));
+    assert!(space.attributes(root).has("b", &42));
+}
+
+#[test]
+fn attribute_closures() {
+
+    let mut space = Space::new();
+    let top = space.create_id();
+    let middle = space.create_id();
+    let root = space.create_id();
+    space.attributes_mut(middle).add("parent", top);
+    space.attributes_mut(root).add("parent", middle);
+
+    let mut sys = System::new("test", &["ROOT"]).unwrap();
+    sys.build_rule("test", |mut builder, input| {
+        let (ancestor_binding, depth_binding) = builder.add_attribute_closure(
+            input[0],
+            "parent",
+            ClosureMode::OneOrMore,
+            None,
+        );
+        builder.add_not_clause(|builder| {
+            builder.add_attribute_binding_requirement(ancestor_binding, "depth", depth_binding);
+        });
+        let mut builder = builder.into_apply_builder();
+        builder.add_binding_attribute_addition(ancestor_binding, "depth", depth_binding);
+        builder
+    }).unwrap();
+
+    sys.run_saturation_with_control(&mut space, &[root], control_limit_total(10)).unwrap();
+    assert!(space.attributes(middle).has("depth", &1));
+    assert!(space.attributes(top).has("depth", &2));
+    assert!(!space.attributes(root).has_named("depth"));
+}
+
 #[test]
 fn object_creation() {
 
@@ -239,4 +415,393 @@ fn object_creation() {
     let value = space.attributes(root).single_named("value").unwrap();
     let object = value.object().unwrap();
     assert!(space.attributes(object).has("done", &23));
-}
\ No newline at end of file
+}
+
+#[test]
+fn attribute_replacements() {
+
+    let mut space = Space::new();
+    let root = space.create_object().apply(|attrs| {
+        attrs.add("count", 1);
+        attrs.add("count", 2);
+        attrs.add("source", 23);
+        attrs.object()
+    });
+
+    let mut sys = System::new("test", &["ROOT"]).unwrap();
+    sys.build_rule("test", |mut builder, input| {
+        let source_binding = builder.add_attribute_binding(input[0], "source");
+        builder.add_not_clause(|builder| {
+            builder.add_attribute_requirement(input[0], "done");
+        });
+        let mut builder = builder.into_apply_builder();
+        builder.add_binding_attribute_replacement(input[0], "count", source_binding);
+        builder.add_value_attribute_replacement(input[0], "done", 1);
+        builder
+    }).unwrap();
+
+    assert!(sys.run_to_first(&mut space, &[root]).unwrap().is_some());
+    assert_eq!(space.attributes(root).iter_named("count").count(), 1);
+    assert!(space.attributes(root).has("count", &23));
+    assert!(space.attributes(root).has("done", &1));
+    assert!(sys.run_to_first(&mut space, &[root]).unwrap().is_none());
+}
+
+#[test]
+fn apply_calculations() {
+
+    let mut space = Space::new();
+    let root = space.create_object().apply(|attrs| {
+        attrs.add("count", 2);
+        attrs.add("zero", 0);
+        attrs.object()
+    });
+
+    let mut sys = System::new("test", &["ROOT"]).unwrap();
+    sys.build_rule("test", |mut builder, input| {
+        let count_binding = builder.add_attribute_binding(input[0], "count");
+        builder.add_binding_value_comparison(count_binding, 2);
+        let mut builder = builder.into_apply_builder();
+        let next_binding = builder.add_calculation(|calc| {
+            calc.add(calc.binding(count_binding), calc.value(1))
+        });
+        builder.add_binding_attribute_replacement(input[0], "count", next_binding);
+        builder
+    }).unwrap();
+
+    assert!(sys.run_to_first(&mut space, &[root]).unwrap().is_some());
+    assert_eq!(space.attributes(root).iter_named("count").count(), 1);
+    assert!(space.attributes(root).has("count", &3));
+
+    let mut sys = System::new("test", &["ROOT"]).unwrap();
+    sys.build_rule("test", |mut builder, input| {
+        let zero_binding = builder.add_attribute_binding(input[0], "zero");
+        let mut builder = builder.into_apply_builder();
+        let result_binding = builder.add_calculation(|calc| {
+            calc.divide(calc.value(1), calc.binding(zero_binding))
+        });
+        builder.add_binding_attribute_addition(input[0], "result", result_binding);
+        builder
+    }).unwrap();
+
+    assert!(sys.run_to_first(&mut space, &[root]).unwrap().is_none());
+    assert!(!space.attributes(root).has_named("result"));
+}
+
+#[test]
+fn object_deletion() {
+
+    let mut space = Space::new();
+    let target = space.create_root_object().apply(|attrs| {
+        attrs.add("value", 1);
+        attrs.add("other", 2);
+        attrs.object()
+    });
+    let root = space.create_object().apply(|attrs| {
+        attrs.add("target", target);
+        attrs.add("list", Value::Tuple(vec![Value::from(target)].into()));
+        attrs.object()
+    });
+
+    let mut sys = System::new("test", &["ROOT"]).unwrap();
+    sys.build_rule("test", |mut builder, input| {
+        let target_binding = builder.add_attribute_binding(input[0], "target");
+        builder.add_attribute_requirement(target_binding, "value");
+        let mut builder = builder.into_apply_builder();
+        builder.add_attribute_clearing(target_binding, "value");
+        builder
+    }).unwrap();
+
+    assert!(sys.run_to_first(&mut space, &[root]).unwrap().is_some());
+    assert!(!space.attributes(target).has_named("value"));
+    assert!(space.attributes(target).has("other", &2));
+
+    let mut sys = System::new("test", &["ROOT"]).unwrap();
+    sys.build_rule("test", |mut builder, input| {
+        let target_binding = builder.add_attribute_binding(input[0], "target");
+        let mut builder = builder.into_apply_builder();
+        builder.add_object_deletion(target_binding, DeletionMode::WithReferences);
+        builder
+    }).unwrap();
+
+    assert!(sys.run_to_first(&mut space, &[root]).unwrap().is_some());
+    assert!(space.attributes(target).is_empty());
+    assert!(!space.roots().contains(&target));
+    assert!(space.attributes(root).is_empty());
+}
+
+#[test]
+fn emissions() {
+
+    let mut space = Space::new();
+    let root = space.create_object().apply(|attrs| {
+        attrs.add("value", 23);
+        attrs.object()
+    });
+
+    let mut sys = System::new("test", &["ROOT"]).unwrap();
+    sys.build_rule("test", |mut builder, input| {
+        let value_binding = builder.add_attribute_binding(input[0], "value");
+        let mut builder = builder.into_apply_builder();
+        builder.add_value_emission("found");
+        builder.add_binding_emission(value_binding);
+        builder
+    }).unwrap();
+
+    let mut output = Vec::new();
+    assert!(sys.run_to_first_with_output(&mut space, &[root], &mut output).unwrap().is_some());
+    assert_eq!(output.len(), 2);
+    assert_eq!(output[0].0.as_ref(), "test");
+    assert_eq!(output[0].1, Value::from("found"));
+    assert_eq!(output[1].1, Value::Int(23));
+}
+
+#[test]
+fn halting() {
+
+    let mut space = Space::new();
+    let root = space.create_object().apply(|attrs| {
+        attrs.add("value", 23);
+        attrs.object()
+    });
+
+    let mut sys = System::new("test", &["ROOT"]).unwrap();
+    sys.build_rule("test", |mut builder, input| {
+        let value_binding = builder.add_attribute_binding(input[0], "value");
+        let mut builder = builder.into_apply_builder();
+        builder.add_halt_with_binding(value_binding);
+        builder
+    }).unwrap();
+
+    match sys.run_saturation(&mut space, &[root]) {
+        Err(RuntimeError::Halted { count, rule, reason }) => {
+            assert_eq!(count, 1);
+            assert_eq!(rule.as_ref(), "test");
+            assert_eq!(reason, Some(Value::Int(23)));
+        },
+        other => panic!("unexpected run result {:?}", other),
+    }
+}
+
+#[test]
+fn system_runs() {
+
+    let mut space = Space::new();
+    let root = space.create_id();
+
+    let mut registry = SystemRegistry::new();
+    let mut main = System::new("main", &["ROOT"]).unwrap();
+    main.build_rule("start", |mut builder, input| {
+        builder.add_not_clause(|builder| {
+            builder.add_attribute_requirement(input[0], "done");
+        });
+        let mut builder = builder.into_apply_builder();
+        builder.add_system_run("sub", SystemRunMode::ToFirst, &[input[0]]);
+        builder.add_value_attribute_addition(input[0], "done", 1);
+        builder
+    }).unwrap();
+    let mut sub = System::new("sub", &["ROOT"]).unwrap();
+    sub.build_rule("mark", |builder, input| {
+        let mut builder = builder.into_apply_builder();
+        builder.add_value_attribute_addition(input[0], "marked", 1);
+        builder
+    }).unwrap();
+    registry.register(main).unwrap();
+    registry.register(sub).unwrap();
+
+    assert!(registry.run_to_first("main", &mut space, &[root]).unwrap().is_some());
+    assert!(space.attributes(root).has("marked", &1));
+    assert!(space.attributes(root).has("done", &1));
+}
+
+#[test]
+fn forall_clauses() {
+
+    let mut space = Space::new();
+
+    let packed = space.create_id();
+    space.attributes_mut(packed).add("packed", 1);
+    let unpacked = space.create_id();
+
+    let id_ok = space.create_id();
+    space.attributes_mut(id_ok).add("item", packed);
+
+    let id_err = space.create_id();
+    space.attributes_mut(id_err).apply(|attrs| {
+        attrs.add("item", packed);
+        attrs.add("item", unpacked);
+    });
+
+    let mut sys = System::new("test", &["ROOT"]).unwrap();
+    sys.build_rule("test", |mut builder, input| {
+        builder.add_forall_clause(
+            |builder| builder.add_attribute_binding(input[0], "item"),
+            |builder, item| builder.add_attribute_value_requirement(item, "packed", 1),
+        );
+        let mut builder = builder.into_apply_builder();
+        builder.add_value_attribute_addition(input[0], "ready", 1);
+        builder
+    }).unwrap();
+
+    assert!(sys.run_to_first(&mut space, &[id_ok]).unwrap().is_some());
+    assert!(sys.run_to_first(&mut space, &[id_err]).unwrap().is_none());
+}
+
+#[test]
+fn rule_sources() {
+
+    let mut sys = System::new("test", &["ROOT"]).unwrap();
+    sys.build_rule("count", |mut builder, input| {
+        let count_binding = builder.add_attribute_binding(input[0], "count");
+        builder.add_binding_value_comparison(count_binding, 2);
+        let mut builder = builder.into_apply_builder();
+        let next_binding = builder.add_calculation(|calc| {
+            calc.multiply(calc.add(calc.binding(count_binding), calc.value(1)), calc.value(2))
+        });
+        builder.add_binding_attribute_replacement(input[0], "count", next_binding);
+        builder
+    }).unwrap();
+    sys.build_rule("create", |mut builder, input| {
+        let (parent, _) = builder.add_attribute_closure(
+            input[0],
+            "parent",
+            ClosureMode::OneOrMore,
+            Some(3),
+        );
+        let items = builder.add_attribute_binding(parent, "items");
+        builder.add_tuple_unpacking(items, |tuple| {
+            tuple.add_value_item("list");
+            tuple.add_ignored_rest();
+        });
+        builder.add_not_clause(|builder| {
+            builder.add_attribute_requirement(input[0], "created");
+        });
+        let mut builder = builder.into_apply_builder();
+        let object = builder.add_object_creation();
+        builder.add_value_attribute_addition(object, "kind", "created");
+        let tuple = builder.add_tuple_creation(|tuple| {
+            tuple.add_binding_item(parent);
+            tuple.add_value_item(23);
+        });
+        builder.add_binding_attribute_addition(object, "pair", tuple);
+        builder.add_binding_attribute_addition(input[0], "created", object);
+        builder.add_value_attribute_replacement(object, "kind", "replaced");
+        builder.add_binding_emission(object);
+        builder
+    }).unwrap();
+
+    let source = sys.source().unwrap();
+    assert_eq!(source, "\
+system test($ROOT);
+
+rule test:count {
+    $ROOT.count: $v1,
+    $v1: 2,
+} do {
+    = $ROOT.count: ($v1 + 1) * 2,
+}
+
+rule test:create {
+    $ROOT.parent+(3): $v1,
+    $v1.items: $v2,
+    $v2: [list, ..],
+    not { $ROOT.created: $ },
+} do {
+    + $ROOT.created: $v3 @ { kind: created, pair: [$v1, 23] },
+    = $v3.kind: replaced,
+    emit $v3,
+}
+");
+    assert_eq!(sys.rule_source("count").unwrap(), "\
+rule test:count {
+    $ROOT.count: $v1,
+    $v1: 2,
+} do {
+    = $ROOT.count: ($v1 + 1) * 2,
+}
+");
+
+    let mut loader = SystemLoader::declaring();
+    loader.load_str(&source).unwrap();
+    let loaded = loader.into_declared().remove(0);
+    assert_eq!(loaded.source().unwrap(), source);
+
+    for system in &[&sys, &loaded] {
+        let mut space = Space::new();
+        let parent = space.create_object().apply(|attrs| {
+            attrs.add("items", vec![Value::from("list"), Value::from(1)]);
+            attrs.object()
+        });
+        let root = space.create_object().apply(|attrs| {
+            attrs.add("count", 2);
+            attrs.add("parent", parent);
+            attrs.object()
+        });
+        let mut output = Vec::new();
+        system.run_saturation_with_control_and_output(
+            &mut space,
+            &[root],
+            control_limit_total(10),
+            &mut output,
+        ).unwrap();
+        assert!(space.attributes(root).has("count", &6));
+        let created = space.attributes(root).single_named("created").unwrap().object().unwrap();
+        assert!(space.attributes(created).has("kind", &Value::from("replaced")));
+        assert!(space.attributes(created).has(
+            "pair",
+            &Value::from(vec![Value::from(parent), Value::from(23)]),
+        ));
+        assert_eq!(output.len(), 1);
+        assert_eq!(output[0].1, Value::from(created));
+    }
+}
+
+#[test]
+fn rule_source_errors() {
+
+    let mut sys = System::new("test", &["ROOT"]).unwrap();
+    sys.build_rule("detached", |builder, input| {
+        let mut builder = builder.into_apply_builder();
+        let object = builder.add_object_creation();
+        builder.add_value_attribute_addition(object, "kind", "lost");
+        builder.add_value_attribute_addition(input[0], "done", 1);
+        builder
+    }).unwrap();
+    sys.build_rule("symbol", |builder, input| {
+        let mut builder = builder.into_apply_builder();
+        builder.add_value_attribute_addition(input[0], "name", "not an identifier");
+        builder
+    }).unwrap();
+    sys.build_rule("end_attribute", |builder, input| {
+        let mut builder = builder.into_apply_builder();
+        builder.add_value_attribute_addition(input[0], "__END__", 1);
+        builder
+    }).unwrap();
+    sys.build_rule("end_symbol", |builder, input| {
+        let mut builder = builder.into_apply_builder();
+        builder.add_value_attribute_addition(input[0], "name", "__END__");
+        builder
+    }).unwrap();
+
+    assert_matches!(
+        sys.rule_source("detached"),
+        Err(DecompileError::DetachedObject { rule }) if rule.as_ref() == "detached"
+    );
+    assert_matches!(
+        sys.rule_source("symbol"),
+        Err(DecompileError::UnrepresentableValue { rule, .. }) if rule.as_ref() == "symbol"
+    );
+    assert_matches!(
+        sys.rule_source("end_attribute"),
+        Err(DecompileError::InvalidName { name, .. }) if name.as_ref() == "__END__"
+    );
+    assert_matches!(
+        sys.rule_source("end_symbol"),
+        Err(DecompileError::InvalidName { name, .. }) if name.as_ref() == "__END__"
+    );
+    assert_matches!(
+        sys.rule_source("unknown"),
+        Err(DecompileError::UnknownRule(name)) if name.as_ref() == "unknown"
+    );
+    assert_matches!(sys.source(), Err(DecompileError::DetachedObject { .. }));
+}
@@ -0,0 +1,54 @@
+use sym_engine::*;
+
+pub const MATCHERS: &[Matcher] = &[Matcher::Search, Matcher::Incremental, Matcher::Compiled];
+
+fn describe(
+    value: &Value,
+    space: &Space,
+    names: &mut Vec<Id>,
+    pending: &mut Vec<Id>,
+) -> String {
+    match value {
+        Value::Object(id) => {
+            let index = match names.iter().position(|known| known == id) {
+                Some(index) => index,
+                None => {
+                    names.push(*id);
+                    pending.push(*id);
+                    names.len() - 1
+                },
+            };
+            if space.attributes(*id).is_empty() {
+                format!("#{}", index)
+            } else {
+                format!("@{}", index)
+            }
+        },
+        Value::Tuple(values) => {
+            let values = values
+                .iter()
+                .map(|value| describe(value, space, names, pending))
+                .collect::<Vec<_>>();
+            format!("[{}]", values.join(", "))
+        },
+        other => other.to_string(),
+    }
+}
+
+// objects are numbered in order of discovery so ids don't matter
+pub fn dump(space: &Space, roots: &[Id]) -> Vec<String> {
+    let mut names = roots.to_vec();
+    let mut pending = roots.to_vec();
+    let mut lines = Vec::new();
+    let mut index = 0;
+    while index < pending.len() {
+        let id = pending[index];
+        index += 1;
+        let object = names.iter().position(|known| *known == id).unwrap();
+        for (name, value) in space.attributes(id).iter() {
+            let value = describe(value, space, &mut names, &mut pending);
+            lines.push(format!("{}.{}: {}", object, name, value));
+        }
+    }
+    lines
+}
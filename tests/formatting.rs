@@ -0,0 +1,211 @@
+use sym_engine::*;
+use assert_matches::{assert_matches};
+
+fn assert_canonical(source: &str) {
+    let formatted = format_source(source).expect("formatted successfully");
+    assert_eq!(formatted, source);
+}
+
+#[test]
+fn canonical_layout() {
+
+    let formatted = format_source("
+        system test ( $ROOT ) ;
+        rule test:x { $ROOT.value : $v , not{$ROOT.done:true} }
+        do { + $ROOT.done : true, + $ROOT.copy: $v }
+        rule test:y {} do {}
+    ").unwrap();
+    assert_eq!(formatted, "\
+system test($ROOT);
+
+rule test:x {
+    $ROOT.value: $v,
+    not { $ROOT.done: true },
+} do {
+    + $ROOT.done: true,
+    + $ROOT.copy: $v,
+}
+
+rule test:y {} do {}
+");
+}
+
+#[test]
+fn round_trips() {
+
+    assert_canonical("\
+system test($ROOT);
+system other($A, $B);
+
+rule test:select {
+    $ROOT.object: $o @ { value: $v, list: [1, ..$rest], kind: a | b | $k },
+    $ROOT.tuple: $t @ [$, 2.5, ..],
+    $o: { other: -3 },
+    $o.parent+(3, $d): $p,
+    $o.child*: $c,
+    $o.path.to.$attr: 5,
+    $v >= 2.5,
+    $x is ($v + 1) * 2 - $t[0] ++ $t[1..] ++ $rest[..$v],
+    $y is len($t) / (2 - $v),
+    $z is ($v in $t) + 1 in $t ++ $rest,
+    3 in $rest,
+    not { $ROOT.done: true },
+    forall { $ROOT.item: $i } => { $i.packed: true },
+    optional { $o.label: $label } else { $label: unnamed },
+} do {
+    + $ROOT.result: $v,
+    = $ROOT.count: $x + 1,
+    = $ROOT.member: $z in [$y],
+    ! $ROOT.flag: $,
+    - $o.a.b: 1.0,
+    clear $o.list,
+    clear $o.$attr,
+    clear $o,
+    delete $p,
+    purge $c,
+    emit [$v, $x],
+    halt,
+    halt $label,
+    run first other($ROOT, $o),
+    run other($o, $o),
+    if { $o.active: true } then { + $ROOT.active: $o },
+    match { { $o.color: $col } => { + $ROOT.color: $col }, _ => {} },
+    for { $ROOT.item: $it } do { + $it.seen: true },
+}
+");
+
+    assert_canonical("\
+rule test:long {} do {
+    if { $ROOT.some_long_attribute_name: $value } then {
+        + $ROOT.another_long_attribute_name: $value,
+    } else {
+        + $ROOT.missing: true,
+    },
+    match {
+        { $ROOT.first_attribute_to_check: $first } => { + $ROOT.found: $first },
+        { $ROOT.second_attribute_to_check: $second } => {
+            + $ROOT.found: $second,
+            + $ROOT.found_second: true,
+        },
+        _ => { halt nothing_found },
+    },
+}
+");
+}
+
+#[test]
+fn comments() {
+
+    assert_canonical("\
+// rules for testing
+system test($ROOT);
+
+/* the main rule */
+rule test:x {
+    // must have a value
+    $ROOT.value: $v,
+    not {
+        // only once
+        $ROOT.done: true,
+    },
+} do {
+    + $ROOT.done: true,
+    // nothing else to do
+}
+
+__END__
+notes that are not parsed
+");
+
+    let formatted = format_source("
+        rule test:x { $ROOT.value: $v, // inline
+            $v > 2 } do {}
+    ").unwrap();
+    assert_eq!(formatted, "\
+rule test:x {
+    $ROOT.value: $v, // inline
+    $v > 2,
+} do {}
+");
+}
+
+#[test]
+fn comment_placement() {
+
+    assert_canonical("\
+rule test:x {
+    $ROOT.value: $v,
+    // last select
+} do {
+    + $ROOT.done: $v,
+}
+");
+
+    assert_canonical("\
+rule test:y {
+    /* empty */
+} do {}
+
+rule test:z {} do {}
+");
+
+    assert_canonical("\
+rule test:x {} do {
+    if { $ROOT.value: 1 } then {
+        + $ROOT.done: 2,
+        // end of then
+    },
+    + $ROOT.after: 3,
+}
+");
+
+    assert_canonical("\
+rule test:x {
+    $ROOT.value: $v, // trailing
+    $v > 2, /* also trailing */
+} do {
+    + $ROOT.done: $v,
+    // before halt
+    halt,
+}
+");
+
+    let formatted = format_source("
+        rule test:x {
+            $y.a: 2, /* ml */ $y.b: 3,
+        } do {}
+    ").unwrap();
+    assert_eq!(formatted, "\
+rule test:x {
+    $y.a: 2,
+    /* ml */
+    $y.b: 3,
+} do {}
+");
+}
+
+#[test]
+fn formatted_rules_load() {
+
+    let source = format_source("
+        rule test:x { $ROOT.value: $v, $w is ($v + 1) * 2 } do { + $ROOT.result: $w }
+    ").unwrap();
+
+    let mut space = Space::new();
+    let root = space.create_id();
+    space.attributes_mut(root).add("value", 2);
+
+    let mut system = System::new("test", &["ROOT"]).unwrap();
+    let mut loader = SystemLoader::new(vec![&mut system]);
+    loader.load_str(&source).unwrap();
+    system.run_to_first(&mut space, &[root]).unwrap();
+    assert_matches!(
+        space.attributes_mut(root).remove_single_named("result"),
+        Some(Value::Int(6))
+    );
+}
+
+#[test]
+fn format_errors() {
+    assert_matches!(format_source("rule test:x { $ROOT.x } do {}"), Err(LoadError::Parse(_)));
+}
@@ -2,11 +2,39 @@
 use sym_engine::*;
 use assert_matches::{assert_matches};
 
+mod common;
+use common::{MATCHERS, dump};
+
 fn test_run(space: &mut Space, root: Id, rules: &str) -> Option<Value> {
     eprintln!("RULES:\n{}", rules);
     let mut system = System::new("test", &["ROOT"]).unwrap();
     let mut loader = SystemLoader::new(vec![&mut system]);
     loader.load_str(rules).expect("loaded successfully");
+
+    // every matcher has to end up with the same space, in both run modes
+    let mut runs = Vec::new();
+    for &matcher in MATCHERS {
+        system.set_matcher(matcher);
+        let mut saturated = space.clone();
+        let result = system.run_saturation_with_control(
+            &mut saturated,
+            &[root],
+            control_limit_total(20),
+        );
+        let mut first = space.clone();
+        let fired = system.run_to_first(&mut first, &[root]).expect("run successfully");
+        runs.push((
+            format!("{:?}", result),
+            dump(&saturated, &[root]),
+            fired,
+            dump(&first, &[root]),
+        ));
+    }
+    for (matcher, run) in MATCHERS.iter().zip(&runs).skip(1) {
+        assert_eq!(*run, runs[0], "{:?} matcher differs", matcher);
+    }
+
+    system.set_matcher(Matcher::Search);
     system.run_to_first(space, &[root]).expect("run successfully");
     space.attributes_mut(root).remove_single_named("result")
 }
@@ -306,274 +334,1116 @@ fn apply_add_attributes_errors() {
 }
 
 #[test]
-fn select_bindings() {
+fn apply_set_attributes() {
 
     let mut space = Space::new();
     let root = space.create_object().apply(|attrs| {
-        attrs.add("value", "foo");
+        attrs.add("count", 1);
+        attrs.add("count", 2);
+        attrs.add("key", "count");
+        attrs.add("other", 3);
         attrs.object()
     });
 
-    // literal
+    // literals replace all previous values
     assert_matches!(test_run(&mut space, root, "
-        rule test:err {
-            $ROOT.value: $value,
-            $value: bar,
-        } do {
-            + $ROOT.result: wrong,
+        rule test:ok {} do {
+            = $ROOT.count: 23,
+            + $ROOT.result: 99,
         }
-        rule test:ok {
-            $ROOT.value: $value,
-            $value: foo,
-        } do {
-            + $ROOT.result: found,
+    "), Some(Value::Int(99)));
+    assert_eq!(space.attributes(root).iter_named("count").count(), 1);
+    assert!(space.attributes(root).has("count", &23));
+    assert!(space.attributes(root).has("other", &3));
+
+    // bound values
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok { $ROOT.other: $other } do {
+            = $ROOT.count: $other,
+            + $ROOT.result: $other,
         }
-    "), Some(Value::Symbol(symbol)) if symbol.as_ref() == "found");
+    "), Some(Value::Int(3)));
+    assert_eq!(space.attributes(root).iter_named("count").count(), 1);
+    assert!(space.attributes(root).has("count", &3));
+
+    // missing attributes are created
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {} do {
+            = $ROOT.fresh: 7,
+            + $ROOT.result: 99,
+        }
+    "), Some(Value::Int(99)));
+    assert!(space.attributes(root).has("fresh", &7));
+
+    // variable attribute names
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok { $ROOT.key: $key } do {
+            = $ROOT.$key: 42,
+            + $ROOT.result: 99,
+        }
+    "), Some(Value::Int(99)));
+    assert_eq!(space.attributes(root).iter_named("count").count(), 1);
+    assert!(space.attributes(root).has("count", &42));
+
+    // nested objects replace previous values
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {} do {
+            = $ROOT.count: { x: 2 },
+            + $ROOT.result: 99,
+        }
+    "), Some(Value::Int(99)));
+    let value = space.attributes_mut(root).remove_single_named("count").unwrap();
+    let nested = value.object().unwrap();
+    assert!(space.attributes(nested).has("x", &2));
+
+    // paths
+    let inner = space.create_object().apply(|attrs| {
+        attrs.add("value", 1);
+        attrs.add("value", 2);
+        attrs.object()
+    });
+    space.attributes_mut(root).add("inner", inner);
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {} do {
+            = $ROOT.inner.value: 5,
+            + $ROOT.result: 99,
+        }
+    "), Some(Value::Int(99)));
+    assert_eq!(space.attributes(inner).iter_named("value").count(), 1);
+    assert!(space.attributes(inner).has("value", &5));
 }
 
 #[test]
-fn select_bindings_errors() {
+fn apply_set_attributes_errors() {
 
     assert_matches!(
-        load_error("rule test:x { $: 23 } do {}"),
+        load_error("rule test:x {} do { = $.value: 23 }"),
         Some(LoadError::Compile(CompileError::IllegalWildcard { .. }))
     );
     assert_matches!(
-        load_error("rule test:x { $unknown: 23 } do {}"),
+        load_error("rule test:x {} do { = $unknown.value: 23 }"),
         Some(LoadError::Compile(CompileError::ExistingBindingRequired { .. }))
     );
+    assert_matches!(
+        load_error("rule test:x {} do { = $ROOT.value+: 23 }"),
+        Some(LoadError::Compile(CompileError::IllegalClosure { .. }))
+    );
 }
 
 #[test]
-fn enums() {
+fn apply_calculations() {
 
     let mut space = Space::new();
-    let deep = space.create_object().apply(|attrs| {
-        attrs.add("deep_value", 42);
-        attrs.object()
-    });
     let root = space.create_object().apply(|attrs| {
-        attrs.add("value", 23);
-        attrs.add("spec", 23);
-        attrs.add("deep", deep);
+        attrs.add("count", 2);
+        attrs.add("zero", 0);
+        attrs.add("list", Value::Tuple(vec![Value::from(1), Value::from(2)].into()));
         attrs.object()
     });
 
-    // capture
+    // arithmetic
     assert_matches!(test_run(&mut space, root, "
-        rule test:ok {
-            $ROOT.value: $value @ x | 42 | 23 | 99,
-        } do {
-            + $ROOT.result: $value,
+        rule test:ok { $ROOT.count: $count } do {
+            + $ROOT.result: $count * 10 + 3,
         }
     "), Some(Value::Int(23)));
 
-    // capture toplevel
+    // replacing with a calculated value
     assert_matches!(test_run(&mut space, root, "
-        rule test:ok {
-            $ROOT.value: $value,
-            $value: x | 23 | y,
-        } do {
-            + $ROOT.result: $value,
+        rule test:ok { $ROOT.count: $count } do {
+            = $ROOT.count: $count + 1,
+            + $ROOT.result: $count,
         }
-    "), Some(Value::Int(23)));
+    "), Some(Value::Int(2)));
+    assert_eq!(space.attributes(root).iter_named("count").count(), 1);
+    assert!(space.attributes(root).has("count", &3));
 
-    // match
+    // tuple operations
     assert_matches!(test_run(&mut space, root, "
-        rule test:ok {
-            $ROOT.spec: $spec,
-            $ROOT.value: $value @ x | 42 | $spec | 99,
-        } do {
-            + $ROOT.result: $value,
+        rule test:ok { $ROOT.list: $list } do {
+            + $ROOT.result: [0] ++ $list[1..],
         }
-    "), Some(Value::Int(23)));
+    "), Some(Value::Tuple(items)) if items.len() == 2);
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok { $ROOT.list: $list } do {
+            + $ROOT.result: len($list),
+        }
+    "), Some(Value::Int(2)));
 
-    // attributes
+    // inside tuples and objects
     assert_matches!(test_run(&mut space, root, "
-        rule test:ok {
-            $ROOT.deep: {
-                deep_value: $value @ x | 42 | 23 | y,
-            },
-        } do {
-            + $ROOT.result: $value,
+        rule test:ok { $ROOT.count: $count } do {
+            + $ROOT.result: [$count - 1, $count, { next: $count + 1 }],
         }
-    "), Some(Value::Int(42)));
+    "), Some(Value::Tuple(items)) if items.len() == 3 && items[0] == Value::Int(2));
 
-    // no capture
+    // removal of a calculated value
     assert_matches!(test_run(&mut space, root, "
-        rule test:ok {
-            $ROOT.value: x | 23 | y,
-        } do {
-            + $ROOT.result: 99,
+        rule test:ok { $ROOT.count: $count } do {
+            - $ROOT.count: $count * 1,
+            + $ROOT.result: 23,
         }
-    "), Some(Value::Int(99)));
+    "), Some(Value::Int(23)));
+    assert!(!space.attributes(root).has_named("count"));
 
-    // no match
+    // only evaluated on the branch that uses it
     assert_matches!(test_run(&mut space, root, "
-        rule test:ok {
-            $ROOT.value: x | 123 | y,
-        } do {
-            + $ROOT.result: 99,
+        rule test:ok { $ROOT.zero: $zero } do {
+            if { $zero == 0 } then {
+                + $ROOT.result: $zero,
+            } else {
+                + $ROOT.result: 10 / $zero,
+            }
+        }
+    "), Some(Value::Int(0)));
+
+    // failed arithmetic fails the application
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok { $ROOT.zero: $zero } do {
+            + $ROOT.before: 1,
+            + $ROOT.result: 10 / $zero,
         }
     "), None);
+    assert!(!space.attributes(root).has_named("before"));
 }
 
 #[test]
-fn enum_errors() {
+fn apply_calculation_errors() {
 
     assert_matches!(
-        load_error("rule test:x { $ROOT.x: 23 | $unknown } do {}"),
-        Some(LoadError::Compile(CompileError::ExistingBindingRequired { .. }))
+        load_error("rule test:x { $ROOT.x: $x + 1 } do {}"),
+        Some(LoadError::Compile(CompileError::IllegalCalculation { .. }))
     );
     assert_matches!(
-        load_error("rule test:x { $ROOT.x: 23 | $ } do {}"),
-        Some(LoadError::Compile(CompileError::IllegalWildcard { .. }))
+        load_error("rule test:x { $ROOT.x: [$x, 2 * 3] } do {}"),
+        Some(LoadError::Compile(CompileError::IllegalCalculation { .. }))
     );
     assert_matches!(
-        load_error("rule test:x { } do { + $ROOT.x: x | y }"),
-        Some(LoadError::Compile(CompileError::IllegalEnumSpecification { .. }))
+        load_error("rule test:x {} do { + $ROOT.value: $unknown + 1 }"),
+        Some(LoadError::Compile(CompileError::ExistingBindingRequired { .. }))
     );
     assert_matches!(
-        load_error("rule test:x { } do { - $ROOT.x: x | y }"),
-        Some(LoadError::Compile(CompileError::IllegalEnumSpecification { .. }))
+        load_error("rule test:x {} do { + $ROOT.value: foo(2) }"),
+        Some(LoadError::Compile(CompileError::UnknownFunction { .. }))
     );
 }
 
 #[test]
-fn wildcards() {
+fn apply_clear_and_delete() {
 
     let mut space = Space::new();
-    let attr_ok = space.create_object().apply(|attrs| {
-        attrs.add("value", 23);
-        attrs.add("mark", 99);
-        attrs.object()
-    });
-    let attr_err = space.create_object().apply(|attrs| {
-        attrs.add("value", 42);
+    let inner = space.create_object().apply(|attrs| {
+        attrs.add("value", 1);
+        attrs.add("value", 2);
+        attrs.add("other", 3);
         attrs.object()
     });
     let root = space.create_object().apply(|attrs| {
-        attrs.add("attr", attr_err);
-        attrs.add("attr", attr_ok);
+        attrs.add("inner", inner);
+        attrs.add("count", 1);
+        attrs.add("count", 2);
+        attrs.add("key", "other");
         attrs.object()
     });
 
-    // attributes
+    // single attribute
     assert_matches!(test_run(&mut space, root, "
-        rule test:ok {
-            $ROOT.attr: {
-                value: $value,
-                mark: $,
-            },
-        } do {
-            + $ROOT.result: $value,
+        rule test:ok {} do {
+            clear $ROOT.count,
+            + $ROOT.result: 23,
         }
     "), Some(Value::Int(23)));
-}
-
-#[test]
-fn select_tuples() {
+    assert!(!space.attributes(root).has_named("count"));
 
-    let mut space = Space::new();
-    let tuple_a = Value::from(vec![Value::from("foo"), Value::from(13)]);
-    let tuple_b = Value::from(vec![Value::from("foo"), Value::from(23), Value::from(42)]);
-    let tuple_c = Value::from(vec![Value::from("bar"), Value::from(42)]);
-    let nest = Value::from(vec![tuple_a.clone(), tuple_c.clone()]);
-    let inner = space.create_object().apply(|attrs| {
-        attrs.add("inner", 23);
-        attrs.object()
-    });
-    let inner_tuple = Value::from(vec![inner]);
-    let root = space.create_object().apply(|attrs| {
-        attrs.add("tuple", tuple_a);
-        attrs.add("tuple", tuple_b);
-        attrs.add("tuple", tuple_c);
-        attrs.add("nested", nest);
-        attrs.add("with_inner", inner_tuple);
-        attrs.object()
-    });
+    // attribute paths
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {} do {
+            clear $ROOT.inner.value,
+            + $ROOT.result: 23,
+        }
+    "), Some(Value::Int(23)));
+    assert!(!space.attributes(inner).has_named("value"));
+    assert!(space.attributes(inner).has("other", &3));
 
-    // matches tuple/3
+    // variable attribute names
     assert_matches!(test_run(&mut space, root, "
-        rule test:ok {
-            $ROOT.tuple: [foo, $value, 42],
-        } do {
-            + $ROOT.result: $value,
+        rule test:ok { $ROOT.key: $key, $ROOT.inner: $inner } do {
+            clear $inner.$key,
+            + $ROOT.result: 23,
         }
     "), Some(Value::Int(23)));
+    assert!(!space.attributes(inner).has_named("other"));
 
-    // skip to correct tuple
+    // all attributes
+    space.attributes_mut(inner).add("value", 1);
     assert_matches!(test_run(&mut space, root, "
-        rule test:ok {
-            $ROOT.tuple: [bar, $value],
-        } do {
-            + $ROOT.result: $value,
+        rule test:ok { $ROOT.inner: $inner } do {
+            clear $inner,
+            + $ROOT.result: 23,
         }
-    "), Some(Value::Int(42)));
+    "), Some(Value::Int(23)));
+    assert!(space.attributes(inner).is_empty());
+    assert!(space.attributes(root).has("inner", &Value::from(inner)));
 
-    // wildcard
+    // clearing a non-object fails the application
     assert_matches!(test_run(&mut space, root, "
-        rule test:ok {
-            $ROOT.tuple: [$, $value],
-        } do {
-            + $ROOT.result: $value,
+        rule test:ok { $ROOT.key: $key } do {
+            clear $key,
+            + $ROOT.result: 23,
         }
-    "), Some(Value::Int(13)));
+    "), None);
 
-    // nesting
+    // deletion keeps references
+    let deleted = space.create_root_object().apply(|attrs| {
+        attrs.add("value", 1);
+        attrs.object()
+    });
+    space.attributes_mut(root).add("deleted", deleted);
     assert_matches!(test_run(&mut space, root, "
-        rule test:ok {
-            $ROOT.nested: [[foo, $], [$, $value]],
-        } do {
-            + $ROOT.result: $value,
+        rule test:ok { $ROOT.deleted: $deleted } do {
+            delete $deleted,
+            + $ROOT.result: 23,
         }
-    "), Some(Value::Int(42)));
+    "), Some(Value::Int(23)));
+    assert!(space.attributes(deleted).is_empty());
+    assert!(!space.roots().contains(&deleted));
+    assert!(space.attributes(root).has("deleted", &Value::from(deleted)));
 
-    // inner object
+    // purging removes references
+    let purged = space.create_object().apply(|attrs| {
+        attrs.add("value", 1);
+        attrs.object()
+    });
+    let holder = space.create_object().apply(|attrs| {
+        attrs.add("direct", purged);
+        attrs.add("nested", Value::Tuple(vec![Value::from(1), Value::from(purged)].into()));
+        attrs.add("kept", 23);
+        attrs.object()
+    });
+    space.attributes_mut(root).apply(|attrs| {
+        attrs.add("purged", purged);
+        attrs.add("holder", holder);
+    });
     assert_matches!(test_run(&mut space, root, "
-        rule test:ok {
-            $ROOT.with_inner: [{ inner: $value }],
-        } do {
-            + $ROOT.result: $value,
+        rule test:ok { $ROOT.purged: $purged } do {
+            purge $purged,
+            + $ROOT.result: 23,
+        }
+    "), Some(Value::Int(23)));
+    assert!(space.attributes(purged).is_empty());
+    assert!(!space.attributes(root).has_named("purged"));
+    assert!(space.attributes(root).has("holder", &Value::from(holder)));
+    assert!(!space.attributes(holder).has_named("direct"));
+    assert!(!space.attributes(holder).has_named("nested"));
+    assert!(space.attributes(holder).has("kept", &23));
+
+    // purges across a whole block remove references added later in the block
+    let items = (0..3).map(|_| space.create_id()).collect::<Vec<_>>();
+    space.attributes_mut(root).apply(|attrs| {
+        for &item in &items {
+            attrs.add("item", item);
+        }
+    });
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok { $ROOT.holder: $holder, $ROOT.item: $ } do {
+            for { $ROOT.item: $item } do {
+                purge $item,
+                + $holder.item: $item,
+            },
+            + $ROOT.result: 23,
         }
     "), Some(Value::Int(23)));
+    assert!(!space.attributes(root).has_named("item"));
+    assert!(!space.attributes(holder).has_named("item"));
 }
 
 #[test]
-fn tuple_errors() {
+fn apply_clear_and_delete_errors() {
 
     assert_matches!(
-        load_error("rule test:x {} do { + $ROOT.x: [$] }"),
+        load_error("rule test:x {} do { clear $unknown }"),
+        Some(LoadError::Compile(CompileError::ExistingBindingRequired { .. }))
+    );
+    assert_matches!(
+        load_error("rule test:x {} do { clear $.value }"),
         Some(LoadError::Compile(CompileError::IllegalWildcard { .. }))
     );
     assert_matches!(
-        load_error("rule test:x {} do { + $ROOT.x: [$unknown] }"),
+        load_error("rule test:x {} do { delete $unknown }"),
         Some(LoadError::Compile(CompileError::ExistingBindingRequired { .. }))
     );
+    assert_matches!(
+        load_error("rule test:x {} do { purge $ }"),
+        Some(LoadError::Compile(CompileError::IllegalWildcard { .. }))
+    );
 }
 
 #[test]
-fn apply_remove_tuple() {
+fn emit_values() {
 
     let mut space = Space::new();
-    let tuple = Value::from(vec![Value::from("foo"), Value::from(23)]);
-    let root = space.create_id();
-
-    // remove by structure
-    space.attributes_mut(root).add("tuple", tuple.clone());
-    assert_matches!(test_run(&mut space, root, "
-        rule test:ok {
-            $ROOT.tuple: [foo, $value],
-        } do {
-            + $ROOT.result: $value,
-            - $ROOT.tuple: [foo, $value],
+    let root = space.create_object().apply(|attrs| {
+        attrs.add("value", 23);
+        attrs.object()
+    });
+    let mut system = System::new("test", &["ROOT"]).unwrap();
+    let mut loader = SystemLoader::new(vec![&mut system]);
+    loader.load_str("
+        rule test:emit { $ROOT.value: $value } do {
+            emit done,
+            emit $value,
+            emit [say, $value * 2],
+            emit { value: $value },
+            if { $value > 100 } then {
+                emit large,
+            } else {
+                emit small,
+            }
         }
-    "), Some(Value::Int(23)));
-    assert!(!space.attributes(root).has_named("tuple"));
+    ").expect("loaded successfully");
+
+    let mut output = Vec::new();
+    system.run_to_first_with_output(&mut space, &[root], &mut output).expect("run successfully");
+    let values = output.into_iter()
+        .map(|(name, value)| {
+            assert_eq!(name.as_ref(), "emit");
+            value
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(values.len(), 5);
+    assert_eq!(values[0], Value::from("done"));
+    assert_eq!(values[1], Value::Int(23));
+    assert_eq!(values[2], Value::Tuple(vec![Value::from("say"), Value::from(46)].into()));
+    let object = values[3].object().unwrap();
+    assert!(space.attributes(object).has("value", &23));
+    assert_eq!(values[4], Value::from("small"));
+}
 
-    // remove by value
-    space.attributes_mut(root).add("tuple", tuple.clone());
-    assert_matches!(test_run(&mut space, root, "
+#[test]
+fn emit_errors() {
+
+    assert_matches!(
+        load_error("rule test:x {} do { emit $unknown }"),
+        Some(LoadError::Compile(CompileError::ExistingBindingRequired { .. }))
+    );
+    assert_matches!(
+        load_error("rule test:x {} do { emit a | b }"),
+        Some(LoadError::Compile(CompileError::IllegalEnumSpecification { .. }))
+    );
+    assert_matches!(
+        load_error("rule test:x {} do { halt $unknown }"),
+        Some(LoadError::Compile(CompileError::ExistingBindingRequired { .. }))
+    );
+    assert_matches!(
+        load_error("rule test:x {} do { run other($unknown) }"),
+        Some(LoadError::Compile(CompileError::ExistingBindingRequired { .. }))
+    );
+    assert_matches!(
+        load_error("rule test:x {} do { run first($) }"),
+        Some(LoadError::Compile(CompileError::IllegalWildcard { .. }))
+    );
+}
+
+#[test]
+fn attribute_paths() {
+
+    let mut space = Space::new();
+    let leaf = space.create_object().apply(|attrs| {
+        attrs.add("value", 42);
+        attrs.object()
+    });
+    let leaf_other = space.create_object().apply(|attrs| {
+        attrs.add("value", 23);
+        attrs.object()
+    });
+    let middle = space.create_object().apply(|attrs| {
+        attrs.add("leaf", leaf_other);
+        attrs.add("leaf", leaf);
+        attrs.object()
+    });
+    let root = space.create_object().apply(|attrs| {
+        attrs.add("middle", middle);
+        attrs.add("number", 5);
+        attrs.object()
+    });
+
+    // select through path with backtracking
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.middle.leaf.value: $value,
+            $value > 30,
+        } do {
+            + $ROOT.result: $value,
+        }
+    "), Some(Value::Int(42)));
+
+    // paths inside struct specifications
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.middle: { leaf.value: 23 },
+        } do {
+            + $ROOT.result: 23,
+        }
+    "), Some(Value::Int(23)));
+
+    // non-object steps do not match
+    assert_matches!(test_run(&mut space, root, "
+        rule test:err {
+            $ROOT.number.value: $value,
+        } do {
+            + $ROOT.result: $value,
+        }
+    "), None);
+
+    // add and remove through resolved paths
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.middle.leaf: $leaf,
+            $leaf.value: 42,
+        } do {
+            + $ROOT.middle.marked: true,
+            - $leaf.value: 42,
+            + $ROOT.result: 23,
+        }
+    "), Some(Value::Int(23)));
+    assert!(space.attributes(middle).has("marked", &Value::from("true")));
+
+    // unresolvable paths fail the application
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {} do {
+            + $ROOT.result: 23,
+            + $ROOT.missing.value: 23,
+        }
+    "), None);
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {} do {
+            + $ROOT.result: 23,
+            + $ROOT.number.value: 23,
+        }
+    "), None);
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {} do {
+            + $ROOT.result: 23,
+            + $ROOT.middle.leaf.value: 23,
+        }
+    "), None);
+}
+
+#[test]
+fn attribute_path_errors() {
+
+    assert_matches!(
+        load_error("rule test:x {} do { + $ROOT.new: { a.b: 23 } }"),
+        Some(LoadError::Compile(CompileError::IllegalAttributePath { .. }))
+    );
+}
+
+#[test]
+fn variable_attributes() {
+
+    let mut space = Space::new();
+    let source = space.create_object().apply(|attrs| {
+        attrs.add("a", 1);
+        attrs.add("b", 2);
+        attrs.add("b", 3);
+        attrs.object()
+    });
+    let target = space.create_id();
+    let root = space.create_object().apply(|attrs| {
+        attrs.add("source", source);
+        attrs.add("target", target);
+        attrs.add("wanted", "b");
+        attrs.object()
+    });
+
+    // attribute name by literal value
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.source.$name: 3,
+        } do {
+            + $ROOT.result: $name,
+        }
+    "), Some(Value::Symbol(name)) if name.as_ref() == "b");
+
+    // values of a bound attribute name
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.wanted: $name,
+            $ROOT.source.$name: $value,
+            $value > 2,
+        } do {
+            + $ROOT.result: $value,
+        }
+    "), Some(Value::Int(3)));
+
+    let mut system = System::new("test", &["ROOT"]).unwrap();
+    let mut loader = SystemLoader::new(vec![&mut system]);
+    loader.load_str("
+        rule test:names {
+            $ROOT.source: $source,
+            $source.$name: $,
+            not { $ROOT.seen: $name },
+        } do {
+            + $ROOT.seen: $name,
+        }
+        rule test:copy {
+            $ROOT.source: $source,
+            $ROOT.target: $target,
+            $source.$name: $value,
+            not { $target.$name: $value },
+        } do {
+            + $target.$name: $value,
+        }
+    ").expect("loaded successfully");
+    for &matcher in &[Matcher::Search, Matcher::Incremental, Matcher::Compiled] {
+        space.attributes_mut(root).clear_named("seen");
+        space.attributes_mut(target).clear_all();
+        system.set_matcher(matcher);
+        system.run_saturation_with_control(&mut space, &[root], control_limit_total(20))
+            .expect("run successfully");
+
+        assert_eq!(space.attributes(root).iter_named("seen").count(), 2);
+        assert!(space.attributes(root).has("seen", &Value::from("a")));
+        assert!(space.attributes(root).has("seen", &Value::from("b")));
+        assert_eq!(space.attributes(target).len(), 3);
+        assert!(space.attributes(target).has("a", &1));
+        assert!(space.attributes(target).has("b", &2));
+        assert!(space.attributes(target).has("b", &3));
+    }
+
+    // removal through variable attribute names
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.target: $target,
+            $ROOT.wanted: $name,
+        } do {
+            - $target.$name: 2,
+            ! $target.$name: 99,
+            + $ROOT.result: 23,
+        }
+    "), Some(Value::Int(23)));
+    assert!(!space.attributes(target).has("b", &2));
+    assert!(space.attributes(target).has("b", &3));
+
+    // non-symbol attribute names fail the application
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.target: $target,
+        } do {
+            + $ROOT.result: 23,
+            + $ROOT.$target: 42,
+        }
+    "), None);
+}
+
+#[test]
+fn variable_attributes_errors() {
+
+    assert_matches!(
+        load_error("rule test:x {} do { + $ROOT.$unknown: 23 }"),
+        Some(LoadError::Compile(CompileError::ExistingBindingRequired { .. }))
+    );
+    assert_matches!(
+        load_error("rule test:x {} do { + $ROOT.$: 23 }"),
+        Some(LoadError::Compile(CompileError::IllegalWildcard { .. }))
+    );
+}
+
+#[test]
+fn attribute_closures() {
+
+    let mut space = Space::new();
+    let a = space.create_id();
+    let b = space.create_id();
+    let c = space.create_id();
+    let other = space.create_id();
+    space.attributes_mut(a).apply(|attrs| {
+        attrs.add("name", "a");
+        attrs.add("parent", b);
+    });
+    space.attributes_mut(b).apply(|attrs| {
+        attrs.add("name", "b");
+        attrs.add("parent", c);
+    });
+    space.attributes_mut(c).apply(|attrs| {
+        attrs.add("name", "c");
+        attrs.add("parent", a);
+    });
+    let root = space.create_object().apply(|attrs| {
+        attrs.add("start", a);
+        attrs.add("target", c);
+        attrs.add("other", other);
+        attrs.object()
+    });
+
+    // depth of reachable values
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.start: $start,
+            $start.parent+($depth): { name: c },
+        } do {
+            + $ROOT.result: $depth,
+        }
+    "), Some(Value::Int(2)));
+
+    // cycles reach the start object once
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.start: $start,
+            $start.parent+($depth): $start,
+        } do {
+            + $ROOT.result: $depth,
+        }
+    "), Some(Value::Int(3)));
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.start: $start,
+            $start.parent*($depth): $start,
+        } do {
+            + $ROOT.result: $depth,
+        }
+    "), Some(Value::Int(0)));
+
+    // depth limits
+    assert_matches!(test_run(&mut space, root, "
+        rule test:err {
+            $ROOT.start: $start,
+            $start.parent+(1): { name: c },
+        } do {
+            + $ROOT.result: wrong,
+        }
+        rule test:ok {
+            $ROOT.start: $start,
+            $start.parent+(2, $depth): { name: c },
+        } do {
+            + $ROOT.result: $depth,
+        }
+    "), Some(Value::Int(2)));
+
+    // reachability of bound values
+    assert_matches!(test_run(&mut space, root, "
+        rule test:err {
+            $ROOT.start: $start,
+            $ROOT.other: $other,
+            $start.parent*: $other,
+        } do {
+            + $ROOT.result: wrong,
+        }
+        rule test:ok {
+            $ROOT.start: $start,
+            $ROOT.target: $target,
+            $start.parent+: $target,
+        } do {
+            + $ROOT.result: 23,
+        }
+    "), Some(Value::Int(23)));
+
+    // enumerate all reachable values
+    let mut system = System::new("test", &["ROOT"]).unwrap();
+    let mut loader = SystemLoader::new(vec![&mut system]);
+    loader.load_str("
+        rule test:collect {
+            $ROOT.start.parent*: $node,
+            $node.name: $name,
+            not { $ROOT.seen: $name },
+        } do {
+            + $ROOT.seen: $name,
+        }
+    ").expect("loaded successfully");
+    for &matcher in &[Matcher::Search, Matcher::Incremental, Matcher::Compiled] {
+        space.attributes_mut(root).clear_named("seen");
+        system.set_matcher(matcher);
+        system.run_saturation_with_control(&mut space, &[root], control_limit_total(20))
+            .expect("run successfully");
+        assert_eq!(space.attributes(root).iter_named("seen").count(), 3);
+        assert!(space.attributes(root).has("seen", &Value::from("a")));
+        assert!(space.attributes(root).has("seen", &Value::from("b")));
+        assert!(space.attributes(root).has("seen", &Value::from("c")));
+    }
+}
+
+#[test]
+fn attribute_closure_errors() {
+
+    assert_matches!(
+        load_error("rule test:x {} do { + $ROOT.parent+: 23 }"),
+        Some(LoadError::Compile(CompileError::IllegalClosure { .. }))
+    );
+    assert_matches!(
+        load_error("rule test:x { $ROOT.$name+: $v } do { + $ROOT.x: [$name, $v] }"),
+        Some(LoadError::Compile(CompileError::IllegalClosure { .. }))
+    );
+    assert_matches!(
+        load_error("rule test:x { $ROOT.parent+(-1): $v } do { + $ROOT.x: $v }"),
+        Some(LoadError::Compile(CompileError::IllegalClosure { .. }))
+    );
+}
+
+#[test]
+fn select_bindings() {
+
+    let mut space = Space::new();
+    let root = space.create_object().apply(|attrs| {
+        attrs.add("value", "foo");
+        attrs.object()
+    });
+
+    // literal
+    assert_matches!(test_run(&mut space, root, "
+        rule test:err {
+            $ROOT.value: $value,
+            $value: bar,
+        } do {
+            + $ROOT.result: wrong,
+        }
+        rule test:ok {
+            $ROOT.value: $value,
+            $value: foo,
+        } do {
+            + $ROOT.result: found,
+        }
+    "), Some(Value::Symbol(symbol)) if symbol.as_ref() == "found");
+}
+
+#[test]
+fn select_bindings_errors() {
+
+    assert_matches!(
+        load_error("rule test:x { $: 23 } do {}"),
+        Some(LoadError::Compile(CompileError::IllegalWildcard { .. }))
+    );
+    assert_matches!(
+        load_error("rule test:x { $unknown: 23 } do {}"),
+        Some(LoadError::Compile(CompileError::ExistingBindingRequired { .. }))
+    );
+}
+
+#[test]
+fn enums() {
+
+    let mut space = Space::new();
+    let deep = space.create_object().apply(|attrs| {
+        attrs.add("deep_value", 42);
+        attrs.object()
+    });
+    let root = space.create_object().apply(|attrs| {
+        attrs.add("value", 23);
+        attrs.add("spec", 23);
+        attrs.add("deep", deep);
+        attrs.object()
+    });
+
+    // capture
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.value: $value @ x | 42 | 23 | 99,
+        } do {
+            + $ROOT.result: $value,
+        }
+    "), Some(Value::Int(23)));
+
+    // capture toplevel
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.value: $value,
+            $value: x | 23 | y,
+        } do {
+            + $ROOT.result: $value,
+        }
+    "), Some(Value::Int(23)));
+
+    // match
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.spec: $spec,
+            $ROOT.value: $value @ x | 42 | $spec | 99,
+        } do {
+            + $ROOT.result: $value,
+        }
+    "), Some(Value::Int(23)));
+
+    // attributes
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.deep: {
+                deep_value: $value @ x | 42 | 23 | y,
+            },
+        } do {
+            + $ROOT.result: $value,
+        }
+    "), Some(Value::Int(42)));
+
+    // no capture
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.value: x | 23 | y,
+        } do {
+            + $ROOT.result: 99,
+        }
+    "), Some(Value::Int(99)));
+
+    // no match
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.value: x | 123 | y,
+        } do {
+            + $ROOT.result: 99,
+        }
+    "), None);
+}
+
+#[test]
+fn enum_errors() {
+
+    assert_matches!(
+        load_error("rule test:x { $ROOT.x: 23 | $unknown } do {}"),
+        Some(LoadError::Compile(CompileError::ExistingBindingRequired { .. }))
+    );
+    assert_matches!(
+        load_error("rule test:x { $ROOT.x: 23 | $ } do {}"),
+        Some(LoadError::Compile(CompileError::IllegalWildcard { .. }))
+    );
+    assert_matches!(
+        load_error("rule test:x { } do { + $ROOT.x: x | y }"),
+        Some(LoadError::Compile(CompileError::IllegalEnumSpecification { .. }))
+    );
+    assert_matches!(
+        load_error("rule test:x { } do { - $ROOT.x: x | y }"),
+        Some(LoadError::Compile(CompileError::IllegalEnumSpecification { .. }))
+    );
+}
+
+#[test]
+fn wildcards() {
+
+    let mut space = Space::new();
+    let attr_ok = space.create_object().apply(|attrs| {
+        attrs.add("value", 23);
+        attrs.add("mark", 99);
+        attrs.object()
+    });
+    let attr_err = space.create_object().apply(|attrs| {
+        attrs.add("value", 42);
+        attrs.object()
+    });
+    let root = space.create_object().apply(|attrs| {
+        attrs.add("attr", attr_err);
+        attrs.add("attr", attr_ok);
+        attrs.object()
+    });
+
+    // attributes
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.attr: {
+                value: $value,
+                mark: $,
+            },
+        } do {
+            + $ROOT.result: $value,
+        }
+    "), Some(Value::Int(23)));
+}
+
+#[test]
+fn select_tuples() {
+
+    let mut space = Space::new();
+    let tuple_a = Value::from(vec![Value::from("foo"), Value::from(13)]);
+    let tuple_b = Value::from(vec![Value::from("foo"), Value::from(23), Value::from(42)]);
+    let tuple_c = Value::from(vec![Value::from("bar"), Value::from(42)]);
+    let nest = Value::from(vec![tuple_a.clone(), tuple_c.clone()]);
+    let inner = space.create_object().apply(|attrs| {
+        attrs.add("inner", 23);
+        attrs.object()
+    });
+    let inner_tuple = Value::from(vec![inner]);
+    let root = space.create_object().apply(|attrs| {
+        attrs.add("tuple", tuple_a);
+        attrs.add("tuple", tuple_b);
+        attrs.add("tuple", tuple_c);
+        attrs.add("nested", nest);
+        attrs.add("with_inner", inner_tuple);
+        attrs.object()
+    });
+
+    // matches tuple/3
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.tuple: [foo, $value, 42],
+        } do {
+            + $ROOT.result: $value,
+        }
+    "), Some(Value::Int(23)));
+
+    // skip to correct tuple
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.tuple: [bar, $value],
+        } do {
+            + $ROOT.result: $value,
+        }
+    "), Some(Value::Int(42)));
+
+    // wildcard
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.tuple: [$, $value],
+        } do {
+            + $ROOT.result: $value,
+        }
+    "), Some(Value::Int(13)));
+
+    // nesting
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.nested: [[foo, $], [$, $value]],
+        } do {
+            + $ROOT.result: $value,
+        }
+    "), Some(Value::Int(42)));
+
+    // inner object
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.with_inner: [{ inner: $value }],
+        } do {
+            + $ROOT.result: $value,
+        }
+    "), Some(Value::Int(23)));
+}
+
+#[test]
+fn select_tuple_rests() {
+
+    let mut space = Space::new();
+    let root = space.create_object().apply(|attrs| {
+        attrs.add("list", vec![1, 2, 3, 4]);
+        attrs.add("pair", vec![5, 6]);
+        attrs.add("expected", vec![2, 3]);
+        attrs.object()
+    });
+
+    // prefix with bound rest
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.list: [$first, $, ..$rest],
+        } do {
+            + $ROOT.result: [$first, $rest],
+        }
+    "), Some(found) if found == Value::from(vec![
+        Value::from(1),
+        Value::from(vec![3, 4]),
+    ]));
+
+    // suffix only
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.list: [.., $last],
+        } do {
+            + $ROOT.result: $last,
+        }
+    "), Some(Value::Int(4)));
+
+    // rest between prefix and suffix
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.list: [1, ..$middle, 4],
+        } do {
+            + $ROOT.result: $middle,
+        }
+    "), Some(found) if found == Value::from(vec![2, 3]));
+
+    // empty rest
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.pair: [$a, ..$rest, $b],
+        } do {
+            + $ROOT.result: [$a, $b, $rest],
+        }
+    "), Some(found) if found == Value::from(vec![
+        Value::from(5),
+        Value::from(6),
+        Value::from(Vec::<Value>::new()),
+    ]));
+
+    // minimum length
+    assert_matches!(test_run(&mut space, root, "
+        rule test:err {
+            $ROOT.pair: [$, $, $, ..],
+        } do {
+            + $ROOT.result: wrong,
+        }
+        rule test:ok {
+            $ROOT.list: [$, $, $, ..],
+        } do {
+            + $ROOT.result: 23,
+        }
+    "), Some(Value::Int(23)));
+
+    // compare against existing binding
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.expected: $expected,
+            $ROOT.list: $list @ [1, ..$expected, $],
+        } do {
+            + $ROOT.result: $list,
+        }
+    "), Some(found) if found == Value::from(vec![1, 2, 3, 4]));
+}
+
+#[test]
+fn tuple_rest_errors() {
+
+    assert_matches!(
+        load_error("rule test:x { $ROOT.x: [..$a, ..$b] } do { + $ROOT.y: [$a, $b] }"),
+        Some(LoadError::Compile(CompileError::MultipleTupleRests { .. }))
+    );
+    assert_matches!(
+        load_error("rule test:x { $ROOT.x: $a } do { + $ROOT.y: [..$a] }"),
+        Some(LoadError::Compile(CompileError::IllegalTupleRest { .. }))
+    );
+    assert_matches!(
+        load_error("rule test:x { $ROOT.x: $a } do { - $ROOT.y: [..$a] }"),
+        Some(LoadError::Compile(CompileError::IllegalTupleRest { .. }))
+    );
+}
+
+#[test]
+fn tuple_errors() {
+
+    assert_matches!(
+        load_error("rule test:x {} do { + $ROOT.x: [$] }"),
+        Some(LoadError::Compile(CompileError::IllegalWildcard { .. }))
+    );
+    assert_matches!(
+        load_error("rule test:x {} do { + $ROOT.x: [$unknown] }"),
+        Some(LoadError::Compile(CompileError::ExistingBindingRequired { .. }))
+    );
+}
+
+#[test]
+fn apply_remove_tuple() {
+
+    let mut space = Space::new();
+    let tuple = Value::from(vec![Value::from("foo"), Value::from(23)]);
+    let root = space.create_id();
+
+    // remove by structure
+    space.attributes_mut(root).add("tuple", tuple.clone());
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.tuple: [foo, $value],
+        } do {
+            + $ROOT.result: $value,
+            - $ROOT.tuple: [foo, $value],
+        }
+    "), Some(Value::Int(23)));
+    assert!(!space.attributes(root).has_named("tuple"));
+
+    // remove by value
+    space.attributes_mut(root).add("tuple", tuple.clone());
+    assert_matches!(test_run(&mut space, root, "
         rule test:ok {
             $ROOT.tuple: $found @ [foo, $value],
         } do {
@@ -694,98 +1564,354 @@ fn not_clauses_errors() {
 }
 
 #[test]
-fn math() {
+fn math() {
+
+    let mut space = Space::new();
+    let root = space.create_id();
+    space.attributes_mut(root).add("value", 23);
+
+    // add
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.value: $value,
+            $out is $value + 10,
+        } do {
+            + $ROOT.result: $out,
+        }
+    "), Some(Value::Int(33)));
+
+    // subtract
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.value: $value,
+            $out is $value - 10,
+        } do {
+            + $ROOT.result: $out,
+        }
+    "), Some(Value::Int(13)));
+
+    // multiply
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.value: $value,
+            $out is $value * 10,
+        } do {
+            + $ROOT.result: $out,
+        }
+    "), Some(Value::Int(230)));
+
+    // division
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.value: $value,
+            $out is 46 / $value,
+        } do {
+            + $ROOT.result: $out,
+        }
+    "), Some(Value::Int(2)));
+
+    // multiply before add
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $out is 2*3+4*5,
+        } do {
+            + $ROOT.result: $out,
+        }
+    "), Some(Value::Int(26)));
+
+    // grouping
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $out is 2*(3+4)*5,
+        } do {
+            + $ROOT.result: $out,
+        }
+    "), Some(Value::Int(70)));
+
+    // int to float
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $out
+            is (10.0 + 5) + (10 + 5.0),
+        } do {
+            + $ROOT.result: $out,
+        }
+    "), Some(Value::Float(value)) if value > 29.0 && value < 31.0);
+}
+
+#[test]
+fn math_errors() {
+
+    assert_matches!(
+        load_error("rule test:x { $ is 2+3 } do {}"),
+        Some(LoadError::Compile(CompileError::IllegalWildcard { .. }))
+    );
+    assert_matches!(
+        load_error("rule test:x { $ROOT.x: $x, $x is 2+3 } do {}"),
+        Some(LoadError::Compile(CompileError::IllegalReuse { .. }))
+    );
+    assert_matches!(
+        load_error("rule test:x { $new is 2+$ } do {}"),
+        Some(LoadError::Compile(CompileError::IllegalWildcard { .. }))
+    );
+    assert_matches!(
+        load_error("rule test:x { $new is 2+$unknown } do {}"),
+        Some(LoadError::Compile(CompileError::ExistingBindingRequired { .. }))
+    );
+}
+
+#[test]
+fn tuple_math() {
+
+    let mut space = Space::new();
+    let root = space.create_object().apply(|attrs| {
+        attrs.add("list", vec![1, 2, 3, 4]);
+        attrs.add("name", "item");
+        attrs.add("number", 23);
+        attrs.object()
+    });
+
+    // length
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.list: $list,
+            $out is len($list),
+        } do {
+            + $ROOT.result: $out,
+        }
+    "), Some(Value::Int(4)));
+
+    // indexing from start and end
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.list: $list,
+            $out is [$list[0], $list[-1], $list[len($list) - 2]],
+        } do {
+            + $ROOT.result: $out,
+        }
+    "), Some(found) if found == Value::from(vec![1, 4, 3]));
+
+    // slicing
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.list: $list,
+            $out is [$list[1..3], $list[..1], $list[-2..], $list[..]],
+        } do {
+            + $ROOT.result: $out,
+        }
+    "), Some(found) if found == Value::from(vec![
+        Value::from(vec![2, 3]),
+        Value::from(vec![1]),
+        Value::from(vec![3, 4]),
+        Value::from(vec![1, 2, 3, 4]),
+    ]));
+
+    // concatenation
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.list: $list,
+            $out is [0] ++ $list[..2] ++ [2 + 3, done],
+        } do {
+            + $ROOT.result: $out,
+        }
+    "), Some(found) if found == Value::from(vec![
+        Value::from(0),
+        Value::from(1),
+        Value::from(2),
+        Value::from(5),
+        Value::from("done"),
+    ]));
+
+    // symbol creation
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok {
+            $ROOT.name: $name,
+            $ROOT.number: $number,
+            $out is sym($name, _, $number + 1),
+        } do {
+            + $ROOT.result: $out,
+        }
+    "), Some(Value::Symbol(found)) if found.as_ref() == "item_24");
+
+    // out of range index fails
+    assert_matches!(test_run(&mut space, root, "
+        rule test:err {
+            $ROOT.list: $list,
+            $out is $list[4],
+        } do {
+            + $ROOT.result: $out,
+        }
+        rule test:ok {
+            $ROOT.list: $list,
+            $out is $list[-4],
+        } do {
+            + $ROOT.result: $out,
+        }
+    "), Some(Value::Int(1)));
+
+    // type errors fail
+    assert_matches!(test_run(&mut space, root, "
+        rule test:err_len {
+            $ROOT.number: $number,
+            $out is len($number),
+        } do {
+            + $ROOT.result: $out,
+        }
+        rule test:err_concat {
+            $ROOT.list: $list,
+            $out is $list ++ 3,
+        } do {
+            + $ROOT.result: $out,
+        }
+        rule test:err_sym {
+            $ROOT.list: $list,
+            $out is sym(x, $list),
+        } do {
+            + $ROOT.result: $out,
+        }
+        rule test:ok {
+            $out is 23,
+        } do {
+            + $ROOT.result: $out,
+        }
+    "), Some(Value::Int(23)));
+}
+
+#[test]
+fn tuple_membership() {
 
     let mut space = Space::new();
-    let root = space.create_id();
-    space.attributes_mut(root).add("value", 23);
+    let root = space.create_object().apply(|attrs| {
+        attrs.add("list", vec![1, 2, 3]);
+        attrs.add("tags", vec!["red", "green"]);
+        attrs.add("value", 2);
+        attrs.object()
+    });
 
-    // add
     assert_matches!(test_run(&mut space, root, "
-        rule test:ok {
-            $ROOT.value: $value,
-            $out is $value + 10,
+        rule test:err {
+            $ROOT.tags: $tags,
+            blue in $tags,
         } do {
-            + $ROOT.result: $out,
+            + $ROOT.result: wrong,
         }
-    "), Some(Value::Int(33)));
-
-    // subtract
-    assert_matches!(test_run(&mut space, root, "
         rule test:ok {
+            $ROOT.list: $list,
+            $ROOT.tags: $tags,
             $ROOT.value: $value,
-            $out is $value - 10,
+            $value in $list,
+            green in $tags,
+            not { 4 in $list },
         } do {
-            + $ROOT.result: $out,
+            + $ROOT.result: 23,
         }
-    "), Some(Value::Int(13)));
+    "), Some(Value::Int(23)));
 
-    // multiply
+    // as a calculation, membership evaluates to 1 or 0
     assert_matches!(test_run(&mut space, root, "
         rule test:ok {
-            $ROOT.value: $value,
-            $out is $value * 10,
+            $ROOT.list: $list,
+            $ROOT.tags: $tags,
+            $count is (1 in $list) + (4 in $list) + (green in $tags),
         } do {
-            + $ROOT.result: $out,
+            + $ROOT.result: $count * 10 + (blue in $tags),
         }
-    "), Some(Value::Int(230)));
+    "), Some(Value::Int(20)));
+    assert_matches!(test_run(&mut space, root, "
+        rule test:ok { $ROOT.list: $list } do {
+            + $ROOT.result: [3, 4] in [$list, [3, 4]],
+        }
+    "), Some(Value::Int(1)));
 
-    // division
+    // the right side has to be a tuple
     assert_matches!(test_run(&mut space, root, "
-        rule test:ok {
+        rule test:err {
             $ROOT.value: $value,
-            $out is 46 / $value,
+            $member is 2 in $value,
         } do {
-            + $ROOT.result: $out,
+            + $ROOT.result: $member,
         }
-    "), Some(Value::Int(2)));
+    "), None);
+}
 
-    // multiply before add
+#[test]
+fn tuple_member_search() {
+
+    let mut space = Space::new();
+    let item_a = space.create_object().apply(|attrs| {
+        attrs.add("weight", 3);
+        attrs.object()
+    });
+    let item_b = space.create_object().apply(|attrs| {
+        attrs.add("weight", 7);
+        attrs.object()
+    });
+    let root = space.create_object().apply(|attrs| {
+        attrs.add("members", vec![item_a, item_b]);
+        attrs.add("numbers", vec![4, 8, 15]);
+        attrs.add("limits", vec![10, 20]);
+        attrs.object()
+    });
+
+    // backtracking over members
     assert_matches!(test_run(&mut space, root, "
         rule test:ok {
-            $out is 2*3+4*5,
+            $ROOT.members: $members,
+            $item in $members,
+            $item.weight: $weight,
+            $weight > 5,
         } do {
-            + $ROOT.result: $out,
+            + $ROOT.result: $weight,
         }
-    "), Some(Value::Int(26)));
+    "), Some(Value::Int(7)));
 
-    // grouping
+    // joining members of two tuples
     assert_matches!(test_run(&mut space, root, "
         rule test:ok {
-            $out is 2*(3+4)*5,
+            $ROOT.numbers: $numbers,
+            $ROOT.limits: $limits,
+            $number in $numbers,
+            $limit in $limits,
+            $number > $limit,
         } do {
-            + $ROOT.result: $out,
+            + $ROOT.result: [$number, $limit],
         }
-    "), Some(Value::Int(70)));
+    "), Some(found) if found == Value::from(vec![15, 10]));
 
-    // int to float
+    // no members
     assert_matches!(test_run(&mut space, root, "
+        rule test:err {
+            $e in $ROOT,
+        } do {
+            + $ROOT.result: $e,
+        }
         rule test:ok {
-            $out
-            is (10.0 + 5) + (10 + 5.0),
+            $ROOT.numbers: $numbers,
+            $e in $numbers,
+            not { $other in $numbers, $other > $e },
         } do {
-            + $ROOT.result: $out,
+            + $ROOT.result: $e,
         }
-    "), Some(Value::Float(value)) if value > 29.0 && value < 31.0);
+    "), Some(Value::Int(15)));
 }
 
 #[test]
-fn math_errors() {
+fn tuple_math_errors() {
 
     assert_matches!(
-        load_error("rule test:x { $ is 2+3 } do {}"),
-        Some(LoadError::Compile(CompileError::IllegalWildcard { .. }))
+        load_error("rule test:x { $ROOT.x: $x, $y is unknown($x) } do { + $ROOT.y: $y }"),
+        Some(LoadError::Compile(CompileError::UnknownFunction { .. }))
     );
     assert_matches!(
-        load_error("rule test:x { $ROOT.x: $x, $x is 2+3 } do {}"),
-        Some(LoadError::Compile(CompileError::IllegalReuse { .. }))
+        load_error("rule test:x { $ROOT.x: $x, $y is len($x, $x) } do { + $ROOT.y: $y }"),
+        Some(LoadError::Compile(CompileError::InvalidArgumentCount { .. }))
     );
     assert_matches!(
-        load_error("rule test:x { $new is 2+$ } do {}"),
-        Some(LoadError::Compile(CompileError::IllegalWildcard { .. }))
+        load_error("rule test:x { $y is sym() } do { + $ROOT.y: $y }"),
+        Some(LoadError::Compile(CompileError::InvalidArgumentCount { .. }))
     );
     assert_matches!(
-        load_error("rule test:x { $new is 2+$unknown } do {}"),
+        load_error("rule test:x { 2 in $unknown } do {}"),
         Some(LoadError::Compile(CompileError::ExistingBindingRequired { .. }))
     );
 }
@@ -952,4 +2078,391 @@ fn conditionals() {
             }
         }
     "), Some(Value::Int(23)));
-}
\ No newline at end of file
+}
+#[test]
+fn match_branches() {
+
+    let mut space = Space::new();
+    let root = space.create_id();
+
+    let item = space.create_id();
+    space.attributes_mut(item).add("size", 7);
+    space.attributes_mut(root).add("item", item);
+
+    // first matching branch with its bindings
+    assert_matches!(test_run(&mut space, root, "
+        rule test:test {
+            $ROOT.item: $item,
+        } do {
+            match {
+                { $item.color: $color } => { + $ROOT.result: $color },
+                { $item.size: $size } => { + $ROOT.result: $size },
+                { $item.size: $other } => { + $ROOT.result: $other, + $ROOT.result: wrong },
+                _ => { + $ROOT.result: wrong },
+            }
+        }
+    "), Some(Value::Int(7)));
+
+    // fallback branch
+    assert_matches!(test_run(&mut space, root, "
+        rule test:test {
+            $ROOT.item: $item,
+        } do {
+            match {
+                { $item.color: $color } => { + $ROOT.result: $color },
+                _ => { + $ROOT.result: fallback },
+            }
+        }
+    "), Some(Value::Symbol(sym)) if sym.as_ref() == "fallback");
+
+    // no matching branch
+    assert_matches!(test_run(&mut space, root, "
+        rule test:test {
+            $ROOT.item: $item,
+            not { $ROOT.done: true },
+        } do {
+            + $ROOT.done: true,
+            match {
+                { $item.color: $color } => { + $ROOT.result: $color },
+            }
+        }
+    "), None);
+}
+
+#[test]
+fn match_branch_errors() {
+
+    assert_matches!(
+        load_error("rule test:x { $ROOT.x: $x } do {
+            match {
+                { $x.value: $value } => { + $x.copy: $value },
+                _ => { + $x.copy: $value },
+            }
+        }"),
+        Some(LoadError::Compile(CompileError::ExistingBindingRequired { .. }))
+    );
+    assert_matches!(
+        load_error("rule test:x { $ROOT.x: $x } do {
+            match {
+                { $x.value: $value } => { + $x.copy: $value },
+            },
+            + $x.copy: $value,
+        }"),
+        Some(LoadError::Compile(CompileError::ExistingBindingRequired { .. }))
+    );
+    assert_matches!(
+        load_error("rule test:x { $ROOT.x: $x } do {
+            match {
+                { $x.value: $value } => {},
+            }
+        }"),
+        Some(LoadError::Compile(CompileError::SingleBindingUse { .. }))
+    );
+}
+
+#[test]
+fn conditional_bindings() {
+
+    let mut space = Space::new();
+    let root = space.create_id();
+
+    // existing counter is incremented
+    space.attributes_mut(root).add("counter", 2);
+    assert_matches!(test_run(&mut space, root, "
+        rule test:test {
+            not { $ROOT.done: true },
+        } do {
+            + $ROOT.done: true,
+            if { $ROOT.counter: $count } then {
+                = $ROOT.counter: $count + 1,
+            } else {
+                + $ROOT.counter: 1,
+            }
+        }
+    "), None);
+    assert_matches!(
+        space.attributes_mut(root).remove_single_named("counter"),
+        Some(Value::Int(3))
+    );
+
+    // missing counter is created
+    space.attributes_mut(root).remove_single_named("done");
+    assert_matches!(test_run(&mut space, root, "
+        rule test:test {
+            not { $ROOT.done: true },
+        } do {
+            + $ROOT.done: true,
+            if { $ROOT.counter: $count } then {
+                = $ROOT.counter: $count + 1,
+            } else {
+                + $ROOT.counter: 1,
+            }
+        }
+    "), None);
+    assert_matches!(
+        space.attributes_mut(root).remove_single_named("counter"),
+        Some(Value::Int(1))
+    );
+}
+
+#[test]
+fn conditional_binding_errors() {
+
+    assert_matches!(
+        load_error("rule test:x { $ROOT.x: $x } do {
+            if { $x.value: $value } then {
+                + $x.copy: $value,
+            } else {
+                + $x.copy: $value,
+            }
+        }"),
+        Some(LoadError::Compile(CompileError::ExistingBindingRequired { .. }))
+    );
+    assert_matches!(
+        load_error("rule test:x { $ROOT.x: $x } do {
+            if { $x.value: $value } then {
+                + $x.copy: $value,
+            },
+            + $x.other: $value,
+        }"),
+        Some(LoadError::Compile(CompileError::ExistingBindingRequired { .. }))
+    );
+}
+
+#[test]
+fn for_each_loops() {
+
+    let mut space = Space::new();
+    let root = space.create_id();
+
+    for weight in &[3, 6, 9] {
+        let item = space.create_id();
+        space.attributes_mut(item).add("weight", *weight);
+        space.attributes_mut(root).add("item", item);
+    }
+
+    assert_matches!(test_run(&mut space, root, "
+        rule test:test {
+            not { $ROOT.done: true },
+        } do {
+            + $ROOT.done: true,
+            for { $ROOT.item: $item @ { weight: $weight }, $weight > 5 } do {
+                + $item.heavy: true,
+                + $ROOT.heavy_weight: $weight,
+            }
+        }
+    "), None);
+
+    let items = space.attributes(root).iter_named("item").cloned().collect::<Vec<_>>();
+    let heavy = items.iter()
+        .filter(|item| space.attributes(item.object().unwrap()).has_named("heavy"))
+        .count();
+    assert_eq!(heavy, 2);
+    assert_eq!(space.attributes(root).iter_named("heavy_weight").count(), 2);
+
+    // matches are enumerated before the loop applies its changes
+    assert_matches!(test_run(&mut space, root, "
+        rule test:test {
+            not { $ROOT.copied: true },
+        } do {
+            + $ROOT.copied: true,
+            for { $ROOT.item: $item } do {
+                + $ROOT.item: { weight: 1, copy_of: $item },
+            }
+        }
+    "), None);
+    assert_eq!(space.attributes(root).iter_named("item").count(), 6);
+
+    // loops search the space as it was before the apply block, so earlier
+    // changes in the same block and other loops aren't enumerated
+    let mut space = Space::new();
+    let root = space.create_id();
+    space.attributes_mut(root).add("entry", 1);
+    assert_matches!(test_run(&mut space, root, "
+        rule test:test {
+            not { $ROOT.done: true },
+        } do {
+            + $ROOT.done: true,
+            + $ROOT.entry: 2,
+            for { $ROOT.entry: $entry } do {
+                + $ROOT.seen: $entry,
+            },
+            for { $ROOT.seen: $seen } do {
+                + $ROOT.seen_twice: $seen,
+            }
+        }
+    "), None);
+    let seen = space.attributes(root).iter_named("seen").cloned().collect::<Vec<_>>();
+    assert_eq!(seen, vec![Value::Int(1)]);
+    assert_eq!(space.attributes(root).iter_named("seen_twice").count(), 0);
+}
+
+#[test]
+fn for_each_loop_errors() {
+
+    assert_matches!(
+        load_error("rule test:x {} do {
+            for { $ROOT.item: $item } do {
+                + $item.seen: true,
+            },
+            + $item.last: true,
+        }"),
+        Some(LoadError::Compile(CompileError::ExistingBindingRequired { .. }))
+    );
+    assert_matches!(
+        load_error("rule test:x {} do {
+            for { $ROOT.item: $item } do {}
+        }"),
+        Some(LoadError::Compile(CompileError::SingleBindingUse { .. }))
+    );
+}
+
+#[test]
+fn forall_clauses() {
+
+    let mut space = Space::new();
+    let root = space.create_id();
+
+    let packed = space.create_id();
+    space.attributes_mut(packed).add("packed", "true");
+    let unpacked = space.create_id();
+
+    let complete = space.create_id();
+    space.attributes_mut(complete).add("item", packed);
+    let partial = space.create_id();
+    space.attributes_mut(partial).apply(|attrs| {
+        attrs.add("item", packed);
+        attrs.add("item", unpacked);
+    });
+    let empty = space.create_id();
+
+    for (box_id, expected) in &[(complete, true), (partial, false), (empty, true)] {
+        space.attributes_mut(root).remove_all_named("box");
+        space.attributes_mut(root).add("box", *box_id);
+        let result = test_run(&mut space, root, "
+            rule test:test {
+                $ROOT.box: $box,
+                forall { $box.item: $item } => { $item.packed: true },
+            } do {
+                + $ROOT.result: ready,
+            }
+        ");
+        assert_eq!(result.is_some(), *expected);
+    }
+}
+
+#[test]
+fn forall_clause_errors() {
+
+    assert_matches!(
+        load_error("rule test:x { forall { $ROOT.item: $item } => {} } do {}"),
+        Some(LoadError::Compile(CompileError::SingleBindingUse { .. }))
+    );
+    assert_matches!(
+        load_error("rule test:x {
+            forall { $ROOT.item: $item } => { $item.packed: true },
+        } do {
+            + $item.seen: true,
+        }"),
+        Some(LoadError::Compile(CompileError::ExistingBindingRequired { .. }))
+    );
+}
+
+#[test]
+fn optional_clauses() {
+
+    let mut space = Space::new();
+    let root = space.create_id();
+
+    let named = space.create_id();
+    space.attributes_mut(named).apply(|attrs| {
+        attrs.add("label", "first");
+        attrs.add("label", "second");
+    });
+    let unnamed = space.create_id();
+
+    let rules = "
+        rule test:test {
+            $ROOT.item: $item,
+            optional { $item.label: $label } else { $label: unnamed },
+            not { $ROOT.result: $label },
+        } do {
+            + $ROOT.result: $label,
+        }
+    ";
+
+    space.attributes_mut(root).add("item", unnamed);
+    assert_matches!(
+        test_run(&mut space, root, rules),
+        Some(Value::Symbol(sym)) if sym.as_ref() == "unnamed"
+    );
+
+    // all inner matches are enumerated and the default is not used
+    space.attributes_mut(root).remove_all_named("item");
+    space.attributes_mut(root).add("item", named);
+    let mut system = System::new("test", &["ROOT"]).unwrap();
+    let mut loader = SystemLoader::new(vec![&mut system]);
+    loader.load_str(rules).unwrap();
+    for &matcher in &[Matcher::Search, Matcher::Incremental, Matcher::Compiled] {
+        space.attributes_mut(root).clear_named("result");
+        system.set_matcher(matcher);
+        system.run_saturation_with_control(&mut space, &[root], |_, _, _| RuntimeControl::Continue)
+            .unwrap();
+        let mut results = space.attributes(root)
+            .iter_named("result")
+            .map(|value| value.symbol().unwrap().to_string())
+            .collect::<Vec<_>>();
+        results.sort();
+        assert_eq!(results, vec!["first".to_string(), "second".to_string()]);
+    }
+
+    // later clauses using the optional bindings see the defaults
+    space.attributes_mut(root).remove_all_named("item");
+    space.attributes_mut(root).add("item", unnamed);
+    space.attributes_mut(root).add("tag", "unnamed");
+    assert_matches!(test_run(&mut space, root, "
+        rule test:test {
+            $ROOT.item: $item,
+            optional { $item.label: $label } else { $label: unnamed },
+            $ROOT.tag: $label,
+            not { $ROOT.result: found },
+        } do {
+            + $ROOT.result: found,
+        }
+    "), Some(Value::Symbol(sym)) if sym.as_ref() == "found");
+    space.attributes_mut(root).remove_all_named("tag");
+    space.attributes_mut(root).remove_all_named("item");
+    space.attributes_mut(root).add("item", named);
+
+    // defaults are not used when later clauses fail for all inner matches
+    assert!(system.run_to_first(&mut space, &[root]).unwrap().is_none());
+}
+
+#[test]
+fn optional_clause_errors() {
+
+    assert_matches!(
+        load_error("rule test:x {
+            optional { $ROOT.label: $label } else { $other: none },
+        } do { + $ROOT.out: $other }"),
+        Some(LoadError::Compile(CompileError::IllegalOptionalDefault { .. }))
+    );
+    assert_matches!(
+        load_error("rule test:x {
+            optional { $ROOT.label: $label } else { $ROOT: none },
+        } do { + $ROOT.out: $label }"),
+        Some(LoadError::Compile(CompileError::IllegalReuse { .. }))
+    );
+    assert_matches!(
+        load_error("rule test:x {
+            optional { $ROOT.label: $label, $ROOT.size: $size } else { $label: none },
+        } do { + $ROOT.out: $size }"),
+        Some(LoadError::Compile(CompileError::ExistingBindingRequired { .. }))
+    );
+    assert_matches!(
+        load_error("rule test:x {
+            optional { $ROOT.label: $label } else { $: none },
+        } do {}"),
+        Some(LoadError::Compile(CompileError::IllegalWildcard { .. }))
+    );
+}
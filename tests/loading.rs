@@ -99,4 +99,183 @@ fn invalid_system() {
         Err(LoadError::NoSuchSystem(name))
             if name.as_ref() == "test_unknown"
     );
-}
\ No newline at end of file
+}
+#[test]
+fn system_declarations() {
+
+    // declared systems are created
+    let mut loader = SystemLoader::declaring();
+    loader.load_str("
+        system agent.plan($SELF, $WORLD);
+        rule agent.plan:a { $SELF.x: $x } do { + $WORLD.y: $x }
+        rule agent.act:a { $SELF.x: $x } do { + $SELF.y: $x }
+        system agent.act($SELF);
+    ").unwrap();
+    loader.load_str("
+        system agent.plan($SELF, $WORLD);
+        rule agent.plan:b { $SELF.x: $x } do { + $WORLD.z: $x }
+    ").unwrap();
+    let systems = loader.into_declared();
+    assert_eq!(systems.len(), 2);
+    assert_eq!(systems[0].name().as_ref(), "agent.plan");
+    assert_eq!(systems[0].input_variables().len(), 2);
+    assert_eq!(systems[0].count(), 2);
+    assert_eq!(systems[1].name().as_ref(), "agent.act");
+    assert_eq!(systems[1].count(), 1);
+
+    // declarations matching provided systems
+    let mut system = System::new("test", &["X"]).unwrap();
+    let mut loader = SystemLoader::new(vec![&mut system]);
+    loader.load_str("
+        system test($X);
+        rule test:a { $X.x: $x } do { + $X.y: $x }
+    ").unwrap();
+    assert!(loader.into_declared().is_empty());
+    assert_eq!(system.count(), 1);
+}
+
+#[test]
+fn system_declaration_errors() {
+
+    let mut loader = SystemLoader::declaring();
+    assert_matches!(
+        loader.load_str("
+            rule test:x { $X.x: $ } do { + $X.x: 23 }
+        "),
+        Err(LoadError::UndeclaredSystem(name)) if name.as_ref() == "test"
+    );
+
+    let mut loader = SystemLoader::declaring();
+    loader.load_str("system test($X);").unwrap();
+    assert_matches!(
+        loader.load_str("system test($X, $Y);"),
+        Err(LoadError::ConflictingSystemDeclaration(name)) if name.as_ref() == "test"
+    );
+
+    let mut loader = SystemLoader::declaring();
+    assert_matches!(
+        loader.load_str("system test($X, $X);"),
+        Err(LoadError::System(SystemError::DuplicateInputVariable(name)))
+            if name.as_ref() == "X"
+    );
+
+    let mut loader = SystemLoader::declaring();
+    assert_matches!(
+        loader.load_str("system test($);"),
+        Err(LoadError::Parse(_))
+    );
+
+    let mut system = System::new("test", &["X"]).unwrap();
+    let mut loader = SystemLoader::new(vec![&mut system]);
+    assert_matches!(
+        loader.load_str("system other($X);"),
+        Err(LoadError::NoSuchSystem(name)) if name.as_ref() == "other"
+    );
+    assert_matches!(
+        loader.load_str("system test($Y);"),
+        Err(LoadError::ConflictingSystemDeclaration(name)) if name.as_ref() == "test"
+    );
+}
+
+#[test]
+fn decompiled_sources() {
+
+    let source = "
+        system test($ROOT);
+        system other($A, $B);
+        rule test:select {
+            $ROOT.kind: $k,
+            $ROOT.object: $o @ { value: $v, list: [1, ..$rest], kind: a | b | $k },
+            $ROOT.tuple: $t @ [$, 2.5, ..],
+            $o.parent+(3, $d): $p,
+            $o.child*: $c,
+            $o.path.to.$attr: 5,
+            $v >= 2.5,
+            $d < 3,
+            $x is ($v + 1) * 2 - $t[0] ++ $t[1..] ++ $rest[..$v],
+            3 in $rest,
+            $m in $rest,
+            not { $ROOT.done: true },
+            forall { $ROOT.item: $i } => { $i.packed: true },
+            optional { $o.label: $label } else { $label: unnamed },
+        } do {
+            + $ROOT.result: $v,
+            = $ROOT.count: $x + 1,
+            - $o.a.b: 1.0,
+            ! $o.list: [1, $m],
+            clear $o.list,
+            clear $o.$attr,
+            clear $c,
+            + $p.child: $n @ { name: $label, inner: { depth: $d } },
+            + $n.extra: [$c, { x: 1 }],
+            emit { kind: result, value: $x },
+            if { $p.x: $px } then { + $p.y: $px } else { halt },
+            match { { $c.a: 1 } => { delete $c }, _ => { purge $c } },
+            for { $ROOT.item: $item } do { + $item.seen: len($t) },
+            run first other($ROOT, $p),
+            halt sym(a, $x),
+            = $ROOT.member: ($v in $rest) + 1,
+        }
+    ";
+    let mut loader = SystemLoader::declaring();
+    loader.load_str(source).unwrap();
+    let systems = loader.into_declared();
+    let decompiled = systems[0].source().unwrap();
+    assert!(decompiled.starts_with("system test($ROOT);\n\nrule test:select {\n"));
+    assert!(decompiled.contains("    $v2.parent+(3, $v8): $v9,\n"));
+    assert!(decompiled.contains("    not { $ROOT.item: $v17, not { $v17.packed: true } },\n"));
+    assert!(decompiled.contains("    + $v9.child: $v19 @ { name: $v18, inner: { depth: $v8 } },\n"));
+    assert!(decompiled.contains("    - $v2.a.b: 1.0,\n"));
+
+    // decompiled rules load back into rules with the same source
+    let mut loader = SystemLoader::declaring();
+    loader.load_str(&decompiled).unwrap();
+    assert_eq!(loader.into_declared()[0].source().unwrap(), decompiled);
+}
+
+#[test]
+fn explained_rules() {
+
+    let source = "
+        system test($ROOT);
+        rule test:slow {
+            $ROOT.object: $o @ {},
+            $o.value: $v,
+            $v > 2,
+            not { $o.done: true },
+        } do {
+            + $ROOT.result: $v * 2,
+            if { $o.x: $x @ {} } then { + $x.y: $v },
+        }
+    ";
+    let mut loader = SystemLoader::declaring();
+    loader.load_str(source).unwrap();
+    let systems = loader.into_declared();
+    let explained = systems[0].explain("slow").unwrap();
+    assert_eq!(explained, [
+        "rule slow",
+        "  select, estimated cost 1956.2:",
+        "      0     1400.0  search $ROOT.object -> $o",
+        "      1     1400.0  not",
+        "      2     1398.7    require $o.done == true",
+        "      3     1398.7  end not",
+        "      4     1958.2  search $o.value -> $v",
+        "      5     1956.2  compare $v > 2",
+        "      6     1956.2  end",
+        "    eliminated:",
+        "      assert $o is object, implied by attribute access",
+        "  apply:",
+        "    calculate $#3 = $v * 2",
+        "    add $#3 to $ROOT.result",
+        "    conditional",
+        "      condition, estimated cost 1399.0:",
+        "          0     1400.0  search $o.x -> $x",
+        "          1     1399.0  assert $x is object",
+        "          2     1399.0  end",
+        "      then:",
+        "        add $v to $x.y",
+        "",
+    ].join("\n"));
+
+    assert_eq!(systems[0].explain("fast"), None);
+}
@@ -2,12 +2,16 @@
 use sym_engine::*;
 use assert_matches::{assert_matches};
 
+mod common;
+use common::{MATCHERS, dump};
+
 #[track_caller]
-fn test_package(rules: &str) -> (System, Space, Id, Id) {
+fn test_package(matcher: Matcher, rules: &str) -> (System, Space, Id, Id) {
 
     let mut system = System::new("test", &["A", "B"]).unwrap();
     let mut loader = SystemLoader::new(vec![&mut system]);
     loader.load_str(rules).expect("rules load successful");
+    system.set_matcher(matcher);
 
     let mut space = Space::new();
     let root_a = space.create_root_id();
@@ -43,227 +47,863 @@ fn input_variable_verification() {
 
 #[test]
 fn single_rule_fire() {
+    for &matcher in MATCHERS {
+        // rule found
+        let (system, mut space, a, b) = test_package(matcher, "
+            rule test:x {} do { + $A.x: 23 }
+            rule test:y {} do { + $A.x: 42 }
+        ");
+        let fired = system.run_to_first(&mut space, &[a, b]).unwrap();
+        assert_eq!(fired.unwrap().as_ref(), "x");
+        let value = space.attributes_mut(a).remove_single_named("x").unwrap();
+        assert_eq!(value.int(), Some(23));
+        assert!(space.attributes(a).is_empty());
+        assert!(space.attributes(b).is_empty());
 
-    // rule found
-    let (system, mut space, a, b) = test_package("
-        rule test:x {} do { + $A.x: 23 }
-        rule test:y {} do { + $A.x: 42 }
-    ");
-    let fired = system.run_to_first(&mut space, &[a, b]).unwrap();
-    assert_eq!(fired.unwrap().as_ref(), "x");
-    let value = space.attributes_mut(a).remove_single_named("x").unwrap();
-    assert_eq!(value.int(), Some(23));
-    assert!(space.attributes(a).is_empty());
-    assert!(space.attributes(b).is_empty());
-
-    // no rules applicable
-    let (system, mut space, a, b) = test_package("
-        rule test:x { $A.flag: true } do { + $A.x: 23 }
-        rule test:y { $A.flag: true } do { + $A.x: 42 }
-    ");
-    assert!(system.run_to_first(&mut space, &[a, b]).unwrap().is_none());
-    assert!(space.attributes(a).is_empty());
-    assert!(space.attributes(b).is_empty());
+        // no rules applicable
+        let (system, mut space, a, b) = test_package(matcher, "
+            rule test:x { $A.flag: true } do { + $A.x: 23 }
+            rule test:y { $A.flag: true } do { + $A.x: 42 }
+        ");
+        assert!(system.run_to_first(&mut space, &[a, b]).unwrap().is_none());
+        assert!(space.attributes(a).is_empty());
+        assert!(space.attributes(b).is_empty());
+    }
 }
 
 #[test]
 fn saturation() {
-    let (system, mut space, a, b) = test_package("
-        rule test:move2 { $A.b: $x } do { - $A.b: $x, + $A.c: $x }
-        rule test:move1 { $A.in: $x } do { - $A.in: $x, + $A.b: $x }
-        rule test:move3 { $A.c: $x } do { - $A.c: $x, + $A.done: $x }
-    ");
-    space.attributes_mut(a).add("in", 23);
-    assert!(!space.attributes(a).is_empty());
-    let run_count = system.run_saturation(&mut space, &[a, b]).unwrap();
-    assert_eq!(run_count, 3);
-    let value = space.attributes_mut(a).remove_single_named("done").unwrap();
-    assert_eq!(value.int(), Some(23));
-    assert!(space.attributes(a).is_empty());
-    assert!(space.attributes(b).is_empty());
+    for &matcher in MATCHERS {
+        let (system, mut space, a, b) = test_package(matcher, "
+            rule test:move2 { $A.b: $x } do { - $A.b: $x, + $A.c: $x }
+            rule test:move1 { $A.in: $x } do { - $A.in: $x, + $A.b: $x }
+            rule test:move3 { $A.c: $x } do { - $A.c: $x, + $A.done: $x }
+        ");
+        space.attributes_mut(a).add("in", 23);
+        assert!(!space.attributes(a).is_empty());
+        let run_count = system.run_saturation(&mut space, &[a, b]).unwrap();
+        assert_eq!(run_count, 3);
+        let value = space.attributes_mut(a).remove_single_named("done").unwrap();
+        assert_eq!(value.int(), Some(23));
+        assert!(space.attributes(a).is_empty());
+        assert!(space.attributes(b).is_empty());
+    }
 }
 
 #[test]
 fn rule_saturation() {
-    let (system, mut space, a, b) = test_package("
-        rule test:move1 {
-            $A.val: $v,
-        } do {
-            - $A.val: $v,
-            + $A.buf: $v,
-        }
-        rule test:move2 {
-            $A.buf: $v,
-            $nv is $v * 2,
-        } do {
-            - $A.buf: $v,
-            + $A.val: $nv,
-        }
-    ");
-    space.attributes_mut(a).add("val", 23);
-    space.attributes_mut(a).add("val", 42);
-    let run_count = system.run_rule_saturation(&mut space, &[a, b]).unwrap();
-    assert_eq!(run_count, 4);
-    let values = space.attributes_mut(a).remove_all_named("val");
-    assert_eq!(values.len(), 2);
-    assert!(values.contains(&Value::Int(46)));
-    assert!(values.contains(&Value::Int(84)));
-    assert!(space.attributes(a).is_empty());
-    assert!(space.attributes(b).is_empty());
+    for &matcher in MATCHERS {
+        let (system, mut space, a, b) = test_package(matcher, "
+            rule test:move1 {
+                $A.val: $v,
+            } do {
+                - $A.val: $v,
+                + $A.buf: $v,
+            }
+            rule test:move2 {
+                $A.buf: $v,
+                $nv is $v * 2,
+            } do {
+                - $A.buf: $v,
+                + $A.val: $nv,
+            }
+        ");
+        space.attributes_mut(a).add("val", 23);
+        space.attributes_mut(a).add("val", 42);
+        let run_count = system.run_rule_saturation(&mut space, &[a, b]).unwrap();
+        assert_eq!(run_count, 4);
+        let values = space.attributes_mut(a).remove_all_named("val");
+        assert_eq!(values.len(), 2);
+        assert!(values.contains(&Value::Int(46)));
+        assert!(values.contains(&Value::Int(84)));
+        assert!(space.attributes(a).is_empty());
+        assert!(space.attributes(b).is_empty());
+    }
 }
 
 #[test]
 fn saturation_run_control() {
+    for &matcher in MATCHERS {
+        let (system, mut space, a, b) = test_package(matcher, "
+            rule test:endless {} do {}
+        ");
 
-    let (system, mut space, a, b) = test_package("
-        rule test:endless {} do {}
-    ");
+        let mut self_count = 0;
+        let run_result = system.run_saturation_with_control(
+            &mut space,
+            &[a, b],
+            |name, _, count| {
+                self_count += 1;
+                assert_eq!(name.as_ref(), "endless");
+                assert_eq!(count, self_count);
+                assert!(count <= 5);
+                if count >= 5 {
+                    RuntimeControl::Stop
+                } else {
+                    RuntimeControl::Continue
+                }
+            },
+        );
+        assert_eq!(self_count, 5);
+        assert_matches!(run_result, Err(RuntimeError::Stopped { count: 5 }));
+    }
+}
 
-    let mut self_count = 0;
-    let run_result = system.run_saturation_with_control(
-        &mut space,
-        &[a, b],
-        |name, _, count| {
-            self_count += 1;
-            assert_eq!(name.as_ref(), "endless");
-            assert_eq!(count, self_count);
-            assert!(count <= 5);
-            if count >= 5 {
-                RuntimeControl::Stop
-            } else {
-                RuntimeControl::Continue
+#[test]
+fn rule_saturation_run_control() {
+    for &matcher in MATCHERS {
+        let (system, mut space, a, b) = test_package(matcher, "
+            rule test:endless {} do {}
+        ");
+
+        let mut self_count = 0;
+        let run_result = system.run_rule_saturation_with_control(
+            &mut space,
+            &[a, b],
+            |name, _, count| {
+                self_count += 1;
+                assert_eq!(name.as_ref(), "endless");
+                assert_eq!(count, self_count);
+                assert!(count <= 5);
+                if count >= 5 {
+                    RuntimeControl::Stop
+                } else {
+                    RuntimeControl::Continue
+                }
+            },
+        );
+        assert_eq!(self_count, 5);
+        assert_matches!(run_result, Err(RuntimeError::Stopped { count: 5 }));
+    }
+}
+
+#[test]
+fn control_helper_limit_total() {
+    for &matcher in MATCHERS {
+        let (system, mut space, a, b) = test_package(matcher, "
+            rule test:endless {} do {}
+        ");
+        let run_result = system.run_rule_saturation_with_control(
+            &mut space,
+            &[a, b],
+            control_limit_total(10),
+        );
+        assert_matches!(run_result, Err(RuntimeError::Stopped { count: 10 }));
+    }
+}
+
+#[test]
+fn control_helper_limit_per_rule() {
+    for &matcher in MATCHERS {
+        let (system, mut space, a, b) = test_package(matcher, "
+            rule test:a_to_b {} do {
+                - $A.val: 23,
+                + $B.val: 23,
             }
-        },
-    );
-    assert_eq!(self_count, 5);
-    assert_matches!(run_result, Err(RuntimeError::Stopped { count: 5 }));
+            rule test:b_to_a {} do {
+                - $B.val: 23,
+                + $A.val: 23,
+            }
+        ");
+        space.attributes_mut(a).add("val", 23);
+        let run_result = system.run_saturation_with_control(
+            &mut space,
+            &[a, b],
+            control_limit_per_rule(10),
+        );
+        assert_matches!(run_result, Err(RuntimeError::Stopped { count: 19 }));
+    }
 }
 
 #[test]
-fn rule_saturation_run_control() {
+fn control_helper_limit_total_and_per_rule() {
+    for &matcher in MATCHERS {
+        let (system, mut space, a, b) = test_package(matcher, "
+            rule test:endless {
+                not { $A.val: $ },
+                not { $B.val: $ },
+            } do {}
+            rule test:a_to_b {} do {
+                - $A.val: 23,
+                + $B.val: 23,
+            }
+            rule test:b_to_a {} do {
+                - $B.val: 23,
+                + $A.val: 23,
+            }
+        ");
+        let run_result = system.run_rule_saturation_with_control(
+            &mut space,
+            &[a, b],
+            control_limit_total(10),
+        );
+        assert_matches!(run_result, Err(RuntimeError::Stopped { count: 10 }));
+        space.attributes_mut(a).add("val", 23);
+        let run_result = system.run_saturation_with_control(
+            &mut space,
+            &[a, b],
+            control_limit_per_rule(10),
+        );
+        assert_matches!(run_result, Err(RuntimeError::Stopped { count: 19 }));
+    }
+}
 
-    let (system, mut space, a, b) = test_package("
-        rule test:endless {} do {}
-    ");
+#[test]
+fn splinter() {
+    for &matcher in MATCHERS {
+        let (system, mut space, a, b) = test_package(matcher, "
+            rule test:sp {
+                $A.value: $value,
+            } do {
+                + $A.found: $value,
+            }
+        ");
+        space.attributes_mut(a).apply(|attrs| {
+            attrs.add("value", 23);
+            attrs.add("value", 42);
+        });
 
-    let mut self_count = 0;
-    let run_result = system.run_rule_saturation_with_control(
-        &mut space,
-        &[a, b],
-        |name, _, count| {
-            self_count += 1;
-            assert_eq!(name.as_ref(), "endless");
-            assert_eq!(count, self_count);
-            assert!(count <= 5);
-            if count >= 5 {
-                RuntimeControl::Stop
-            } else {
+        space.transaction(&mut |tx| {
+            let mut new_txs = Vec::new();
+            system.run_splinter(&tx, &[a, b], |new_tx, name| {
+                assert_eq!(name.as_ref(), "sp");
+                new_txs.push(new_tx);
                 RuntimeControl::Continue
+            }).unwrap();
+            assert_eq!(new_txs.len(), 2);
+            assert!(new_txs.iter().any(|new_tx| {
+                new_tx.attributes(a).has("found", &23)
+                &&
+                !new_tx.attributes(a).has("found", &42)
+            }));
+            assert!(new_txs.iter().any(|new_tx| {
+                new_tx.attributes(a).has("found", &42)
+                &&
+                !new_tx.attributes(a).has("found", &23)
+            }));
+            None
+        });
+    }
+}
+#[test]
+fn emitted_output() {
+    for &matcher in MATCHERS {
+        let (system, mut space, a, b) = test_package(matcher, "
+            rule test:count {
+                $A.value: $value,
+                not { $A.seen: $value },
+            } do {
+                + $A.seen: $value,
+                emit [seen, $value],
             }
-        },
-    );
-    assert_eq!(self_count, 5);
-    assert_matches!(run_result, Err(RuntimeError::Stopped { count: 5 }));
+            rule test:fail {
+                $A.value: $value,
+            } do {
+                emit [failed, $value],
+                - $A.missing: $value,
+            }
+        ");
+        space.attributes_mut(a).apply(|attrs| {
+            attrs.add("value", 23);
+            attrs.add("value", 42);
+        });
+
+        // delivered per firing with the rule name
+        let mut output = Vec::new();
+        let count = system.run_saturation_with_control_and_output(
+            &mut space,
+            &[a, b],
+            control_limit_total(10),
+            &mut output,
+        ).unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(output.len(), 2);
+        assert!(output.iter().all(|(name, _)| name.as_ref() == "count"));
+        assert!(output.iter().any(|(_, value)| {
+            *value == Value::Tuple(vec![Value::from("seen"), Value::from(23)].into())
+        }));
+        assert!(output.iter().any(|(_, value)| {
+            *value == Value::Tuple(vec![Value::from("seen"), Value::from(42)].into())
+        }));
+
+        // failed applications are not delivered
+        let mut output = Vec::new();
+        let fired = system.run_to_first_with_output(&mut space, &[a, b], &mut output).unwrap();
+        assert!(fired.is_none());
+        assert!(output.is_empty());
+
+        // splinters carry their own emissions
+        space.attributes_mut(a).clear_named("seen");
+        space.transaction(&mut |tx| {
+            let mut collected = Vec::new();
+            system.run_splinter_with_output(&tx, &[a, b], |_, name, emitted| {
+                assert_eq!(name.as_ref(), "count");
+                collected.push(emitted);
+                RuntimeControl::Continue
+            }).unwrap();
+            assert_eq!(collected.len(), 2);
+            assert!(collected.iter().all(|emitted| emitted.len() == 1));
+            None
+        });
+    }
 }
 
 #[test]
-fn control_helper_limit_total() {
-    let (system, mut space, a, b) = test_package("
-        rule test:endless {} do {}
-    ");
-    let run_result = system.run_rule_saturation_with_control(
-        &mut space,
-        &[a, b],
-        control_limit_total(10),
-    );
-    assert_matches!(run_result, Err(RuntimeError::Stopped { count: 10 }));
+fn halting() {
+    for &matcher in MATCHERS {
+        // halting commits the firing and ends the run
+        let (system, mut space, a, b) = test_package(matcher, "
+            rule test:done {
+                $A.count: 3,
+                not { $A.done: true },
+            } do {
+                + $A.done: true,
+                halt [finished, 3],
+            }
+            rule test:count {
+                $A.count: $count,
+            } do {
+                = $A.count: $count + 1,
+            }
+        ");
+        space.attributes_mut(a).add("count", 0);
+        let result = system.run_saturation_with_control(
+            &mut space,
+            &[a, b],
+            control_limit_total(10),
+        );
+        assert_matches!(
+            result,
+            Err(RuntimeError::Halted { count: 4, rule, reason: Some(Value::Tuple(reason)) })
+                if rule.as_ref() == "done" && reason.len() == 2
+        );
+        assert!(space.attributes(a).has("done", &Value::from("true")));
+        assert!(space.attributes(a).has("count", &3));
+
+        // without a reason
+        let (system, mut space, a, b) = test_package(matcher, "
+            rule test:stop {} do {
+                + $A.stopped: 1,
+                halt,
+            }
+        ");
+        let result = system.run_to_first(&mut space, &[a, b]);
+        assert_matches!(
+            result,
+            Err(RuntimeError::Halted { count: 1, rule, reason: None }) if rule.as_ref() == "stop"
+        );
+        assert!(space.attributes(a).has("stopped", &1));
+
+        // failed applications do not halt
+        let (system, mut space, a, b) = test_package(matcher, "
+            rule test:fail {} do {
+                halt,
+                - $A.missing: 1,
+            }
+        ");
+        assert_matches!(system.run_saturation(&mut space, &[a, b]), Ok(0));
+
+        // splinters stop after the halting transaction is collected
+        let (system, mut space, a, b) = test_package(matcher, "
+            rule test:sp {
+                $A.value: $value,
+            } do {
+                + $A.found: $value,
+                halt $value,
+            }
+        ");
+        space.attributes_mut(a).apply(|attrs| {
+            attrs.add("value", 23);
+            attrs.add("value", 42);
+        });
+        space.transaction(&mut |tx| {
+            let mut collected = 0;
+            let result = system.run_splinter(&tx, &[a, b], |_, _| {
+                collected += 1;
+                RuntimeControl::Continue
+            });
+            assert_eq!(collected, 1);
+            assert_matches!(
+                result,
+                Err(RuntimeError::Halted { count: 1, reason: Some(Value::Int(_)), .. })
+            );
+            None
+        });
+    }
+}
+
+fn test_registry(rules: &str) -> (SystemRegistry, Space, Id) {
+
+    let mut registry = SystemRegistry::new();
+    registry.register(System::new("main", &["A"]).unwrap()).unwrap();
+    registry.register(System::new("sub", &["X"]).unwrap()).unwrap();
+    registry.loader().load_str(rules).expect("rules load successful");
+
+    let mut space = Space::new();
+    let root = space.create_root_id();
+
+    (registry, space, root)
 }
 
 #[test]
-fn control_helper_limit_per_rule() {
-    let (system, mut space, a, b) = test_package("
-        rule test:a_to_b {} do {
-            - $A.val: 23,
-            + $B.val: 23,
+fn nested_system_runs() {
+
+    // sub-runs see the current transaction and share its output
+    let (registry, mut space, a) = test_registry("
+        rule main:start {
+            not { $A.started: 1 },
+        } do {
+            + $A.started: 1,
+            run sub($A),
+            + $A.done: 1,
+        }
+        rule sub:mark {
+            $X.started: 1,
+            not { $X.marked: 1 },
+        } do {
+            + $X.marked: 1,
+            emit marked,
         }
-        rule test:b_to_a {} do {
-            - $B.val: 23,
-            + $A.val: 23,
+    ");
+    let mut output = Vec::new();
+    let fired = registry.run_to_first_with_output("main", &mut space, &[a], &mut output).unwrap();
+    assert_eq!(fired.unwrap().as_ref(), "start");
+    assert!(space.attributes(a).has("marked", &1));
+    assert!(space.attributes(a).has("done", &1));
+    assert_eq!(output.len(), 1);
+    assert_eq!(output[0].0.as_ref(), "start");
+    assert_eq!(output[0].1, Value::from("marked"));
+
+    // to-first runs without a firing fail the outer application
+    let (registry, mut space, a) = test_registry("
+        rule main:start {} do {
+            + $A.started: 1,
+            run first sub($A),
         }
+        rule sub:never { $X.missing: 1 } do {}
     ");
-    space.attributes_mut(a).add("val", 23);
-    let run_result = system.run_saturation_with_control(
-        &mut space,
-        &[a, b],
-        control_limit_per_rule(10),
+    assert!(registry.run_to_first("main", &mut space, &[a]).unwrap().is_none());
+    assert!(!space.attributes(a).has_named("started"));
+
+    // saturation runs succeed without firings
+    let (registry, mut space, a) = test_registry("
+        rule main:start { not { $A.started: 1 } } do {
+            + $A.started: 1,
+            run sub($A),
+        }
+        rule sub:never { $X.missing: 1 } do {}
+    ");
+    assert_matches!(
+        registry.run_saturation_with_control("main", &mut space, &[a], control_limit_total(10)),
+        Ok(1)
     );
-    assert_matches!(run_result, Err(RuntimeError::Stopped { count: 19 }));
-}
 
-#[test]
-fn control_helper_limit_total_and_per_rule() {
-    let (system, mut space, a, b) = test_package("
-        rule test:endless {
-            not { $A.val: $ },
-            not { $B.val: $ },
-        } do {}
-        rule test:a_to_b {} do {
-            - $A.val: 23,
-            + $B.val: 23,
+    // halting ends only the sub-run
+    let (registry, mut space, a) = test_registry("
+        rule main:start { not { $A.started: 1 } } do {
+            + $A.started: 1,
+            run first sub($A),
         }
-        rule test:b_to_a {} do {
-            - $B.val: 23,
-            + $A.val: 23,
+        rule sub:stop {} do {
+            + $X.stopped: 1,
+            halt,
         }
     ");
-    let run_result = system.run_rule_saturation_with_control(
-        &mut space,
-        &[a, b],
-        control_limit_total(10),
+    assert!(registry.run_to_first("main", &mut space, &[a]).unwrap().is_some());
+    assert!(space.attributes(a).has("stopped", &1));
+}
+
+#[test]
+fn nested_system_run_errors() {
+
+    // unknown systems
+    let (registry, mut space, a) = test_registry("
+        rule main:start {} do { run missing($A) }
+    ");
+    assert_matches!(
+        registry.run_to_first("main", &mut space, &[a]),
+        Err(RuntimeError::UnknownSystem { name }) if name.as_ref() == "missing"
     );
-    assert_matches!(run_result, Err(RuntimeError::Stopped { count: 10 }));
-    space.attributes_mut(a).add("val", 23);
-    let run_result = system.run_saturation_with_control(
-        &mut space,
-        &[a, b],
-        control_limit_per_rule(10),
+    assert_matches!(
+        registry.run_to_first("missing", &mut space, &[a]),
+        Err(RuntimeError::UnknownSystem { name }) if name.as_ref() == "missing"
+    );
+    assert_matches!(
+        registry.get("main").unwrap().run_to_first(&mut space, &[a]),
+        Err(RuntimeError::UnknownSystem { .. })
+    );
+
+    // recursion depth
+    let (mut registry, mut space, a) = test_registry("
+        rule main:start {} do { + $A.count: 1, run main($A) }
+    ");
+    registry.set_max_depth(3);
+    assert_matches!(
+        registry.run_to_first("main", &mut space, &[a]),
+        Err(RuntimeError::DepthLimitExceeded { name, max_depth: 3 }) if name.as_ref() == "main"
+    );
+    assert!(!space.attributes(a).has_named("count"));
+
+    // input counts
+    let (registry, mut space, a) = test_registry("
+        rule main:start {} do { run sub($A, $A) }
+    ");
+    assert_matches!(
+        registry.run_to_first("main", &mut space, &[a]),
+        Err(RuntimeError::InvalidInputArgumentLen { expected: 1, received: 2 })
+    );
+
+    // registration
+    let mut registry = SystemRegistry::new();
+    registry.register(System::new("main", &[]).unwrap()).unwrap();
+    assert_matches!(
+        registry.register(System::new("main", &[]).unwrap()),
+        Err(SystemError::DuplicateSystem(name)) if name.as_ref() == "main"
     );
-    assert_matches!(run_result, Err(RuntimeError::Stopped { count: 19 }));
 }
 
 #[test]
-fn splinter() {
+fn statistics_replanning() {
 
-    let (system, mut space, a, b) = test_package("
-        rule test:sp {
-            $A.value: $value,
+    let rules = "
+        rule test:pair {
+            $A.item: $i,
+            $i.flag: true,
+            $A.kind: $k,
+            $k.flag: true,
+            not { $B.pair: [$i, $k] },
         } do {
-            + $A.found: $value,
+            + $B.pair: [$i, $k],
         }
-    ");
-    space.attributes_mut(a).apply(|attrs| {
-        attrs.add("value", 23);
-        attrs.add("value", 42);
-    });
+    ";
+
+    for &matcher in MATCHERS {
+        let (mut system, mut space, a, b) = test_package(matcher, rules);
+        let kind = space.create_object().apply(|attrs| {
+            attrs.add("flag", "true");
+            attrs.object()
+        });
+        space.attributes_mut(a).add("kind", kind);
+        for index in 0..100 {
+            let item = space.create_object().apply(|attrs| {
+                attrs.add("flag", if index % 2 == 0 { "true" } else { "false" });
+                attrs.object()
+            });
+            space.attributes_mut(a).add("item", item);
+        }
+
+        let search_order = |system: &System, name: &str| {
+            let explained = system.explain(name).unwrap();
+            let item = explained.find("search $A.item").unwrap();
+            let kind = explained.find("search $A.kind").unwrap();
+            item < kind
+        };
+
+        // without statistics the searches keep their source order
+        assert!(search_order(&system, "pair"));
+
+        // with statistics the large fan-out search is moved to the end
+        let statistics = space.statistics();
+        system.replan(Some(&statistics));
+        assert!(!search_order(&system, "pair"));
+        assert_eq!(system.run_saturation(&mut space, &[a, b]).unwrap(), 50);
+        assert_eq!(space.attributes(b).iter_named("pair").count(), 50);
+
+        // rules loaded later are planned with the same statistics
+        let mut loader = SystemLoader::new(vec![&mut system]);
+        loader.load_str(&rules.replace("test:pair", "test:later")).unwrap();
+        assert!(!search_order(&system, "later"));
+
+        // and so are systems registered after replanning a registry
+        let (other, _, _, _) = test_package(matcher, rules);
+        let mut registry = SystemRegistry::new();
+        registry.replan(Some(&statistics));
+        registry.register(other).unwrap();
+        assert!(!search_order(registry.get("test").unwrap(), "pair"));
+
+        // replanning without statistics restores the fixed costs
+        system.replan(None);
+        assert!(search_order(&system, "pair"));
+        assert!(search_order(&system, "later"));
+    }
+}
+
+#[test]
+fn incremental_matching() {
+
+    let rules = "
+        rule test:seed {
+            $A.pending: $n,
+        } do {
+            - $A.pending: $n,
+            + $A.item: { value: $n, tags: [$n, seeded] },
+            emit [seeded, $n],
+        }
+        rule test:link {
+            $A.item: $i @ { value: $v },
+            $w is $v + 1,
+            $A.item: $j @ { value: $w },
+            not { $i.next: $ },
+        } do {
+            + $i.next: $j,
+        }
+        rule test:reach {
+            $A.item: $i,
+            $i.next+(3, $depth): $k,
+            not { $i.reach: [$k, $] },
+        } do {
+            + $i.reach: [$k, $depth],
+        }
+        rule test:label {
+            $A.item: $i,
+            optional { $i.next: $n } else { $n: none },
+            not { $i.label: $ },
+        } do {
+            + $i.label: $n,
+        }
+        rule test:total {
+            $A.item: $i @ { value: $v },
+            not { $i.counted: true },
+            $A.total: $t,
+        } do {
+            + $i.counted: true,
+            - $A.total: $t,
+            + $A.total: $t + $v,
+            emit $t + $v,
+        }
+        rule test:names {
+            $A.item: $i,
+            $i.$name: $,
+            not { $B.seen: $name },
+        } do {
+            + $B.seen: $name,
+        }
+        rule test:visit {
+            $A.total: $t,
+            $t > 20,
+            not { $A.visited: true },
+        } do {
+            + $A.visited: true,
+            for { $A.item: $i, $i.label: none } do {
+                + $i.last: true,
+            }
+        }
+        rule test:refill {
+            $A.refill: $n,
+        } do {
+            - $A.refill: $n,
+            + $A.pending: $n,
+        }
+    ";
+
+    assert_eq!(System::new("test", &[]).unwrap().matcher(), Matcher::Search);
 
-    space.transaction(&mut |tx| {
-        let mut new_txs = Vec::new();
-        system.run_splinter(&tx, &[a, b], |new_tx, name| {
-            assert_eq!(name.as_ref(), "sp");
-            new_txs.push(new_tx);
+    let mut runs = Vec::new();
+    for &matcher in MATCHERS {
+        let (system, mut space, a, b) = test_package(matcher, rules);
+        assert_eq!(system.matcher(), matcher);
+        space.attributes_mut(a).apply(|attrs| {
+            for value in &[5, 3, 4, 1] {
+                attrs.add("pending", *value);
+            }
+            for value in &[2, 6] {
+                attrs.add("refill", *value);
+            }
+            attrs.add("total", 0);
+        });
+
+        let mut fired = Vec::new();
+        let mut output = Vec::new();
+        let count = system.run_saturation_with_control_and_output(
+            &mut space,
+            &[a, b],
+            |name, _, _| {
+                fired.push(name.to_string());
+                RuntimeControl::Continue
+            },
+            &mut output,
+        ).unwrap();
+        assert_eq!(count, fired.len() as u64);
+        runs.push((fired, output, dump(&space, &[a, b])));
+    }
+
+    let (fired, output, state) = &runs[0];
+    assert_eq!(fired.iter().filter(|name| *name == "seed").count(), 6);
+    assert_eq!(fired.iter().filter(|name| *name == "visit").count(), 1);
+    assert_eq!(output.last().unwrap().1, Value::from(21));
+    assert_eq!(state.iter().filter(|line| line.contains(".reach: ")).count(), 12);
+    assert_eq!(state.iter().filter(|line| line.ends_with(".last: true")).count(), 3);
+    assert!(state.contains(&"0.visited: true".to_string()));
+    assert_eq!(runs[0], runs[1]);
+    assert_eq!(runs[0], runs[2]);
+}
+
+#[test]
+fn shared_prefixes() {
+
+    let rules = "
+        rule test:three {
+            $A.item: $i @ { state: new },
+            $i.value: 3,
+        } do {
+            - $i.state: new,
+            + $i.state: three,
+        }
+        rule test:big {
+            $A.item: $i @ { state: new },
+            $i.value: $v,
+            $v > 5,
+        } do {
+            - $i.state: new,
+            + $i.state: big,
+        }
+        rule test:any {
+            $A.item: $i @ { state: new },
+        } do {
+            - $i.state: new,
+            + $i.state: any,
+        }
+        rule test:old {
+            $A.item: $i @ { state: old },
+        } do {
+            - $i.state: old,
+            + $i.state: new,
+        }
+        rule test:other {
+            $B.item: $i,
+        } do {
+            - $B.item: $i,
+        }
+    ";
+
+    let (system, _, _, _) = test_package(Matcher::Search, rules);
+    let names = |names: &[&str]| names.iter().map(|&name| name.into()).collect::<Vec<_>>();
+    assert_eq!(system.shared_prefixes(), vec![
+        SharedPrefix { rules: names(&["three", "big", "any", "old"]), ops_len: 1 },
+        SharedPrefix { rules: names(&["three", "big", "any"]), ops_len: 2 },
+    ]);
+
+    // later matches of the shared selects are found for earlier rules first
+    for &matcher in MATCHERS {
+        let (system, mut space, a, b) = test_package(matcher, rules);
+        let mut items = Vec::new();
+        for (value, state) in &[(1, "new"), (7, "old"), (3, "new"), (9, "new"), (5, "old")] {
+            let item = space.create_object().apply(|attrs| {
+                attrs.add("value", *value);
+                attrs.add("state", *state);
+                attrs.object()
+            });
+            space.attributes_mut(a).add("item", item);
+            items.push(item);
+        }
+
+        let mut fired = Vec::new();
+        system.run_saturation_with_control(&mut space, &[a, b], |name, _, _| {
+            fired.push(name.to_string());
             RuntimeControl::Continue
         }).unwrap();
-        assert_eq!(new_txs.len(), 2);
-        assert!(new_txs.iter().any(|new_tx| {
-            new_tx.attributes(a).has("found", &23)
-            &&
-            !new_tx.attributes(a).has("found", &42)
-        }));
-        assert!(new_txs.iter().any(|new_tx| {
-            new_tx.attributes(a).has("found", &42)
-            &&
-            !new_tx.attributes(a).has("found", &23)
-        }));
-        None
+        assert_eq!(fired, vec!["three", "big", "any", "old", "big", "old", "any"]);
+        let states = items
+            .iter()
+            .map(|item| space.attributes(*item).single_named("state").unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(states, vec!["any", "big", "three", "big", "any"]);
+    }
+
+    // single firings use the shared selects as well
+    let (system, mut space, a, b) = test_package(Matcher::Search, rules);
+    let item = space.create_object().apply(|attrs| {
+        attrs.add("value", 3);
+        attrs.add("state", "old");
+        attrs.object()
     });
-}
\ No newline at end of file
+    space.attributes_mut(a).add("item", item);
+    space.attributes_mut(b).add("item", item);
+    assert_eq!(system.run_to_first(&mut space, &[a, b]).unwrap().unwrap().as_ref(), "old");
+    assert_eq!(system.run_to_first(&mut space, &[a, b]).unwrap().unwrap().as_ref(), "three");
+    assert_eq!(system.run_to_first(&mut space, &[a, b]).unwrap().unwrap().as_ref(), "other");
+    assert!(system.run_to_first(&mut space, &[a, b]).unwrap().is_none());
+}
+
+#[test]
+fn compiled_matching() {
+
+    let rules = "
+        rule test:label {
+            $A.item: $i,
+            not { $i.label: $ },
+            optional { $i.name: $name } else { $name: anonymous },
+            $i.tags: $tags,
+            $tag in $tags,
+            $tag > 5,
+        } do {
+            + $i.label: $name,
+            emit [label, $name, $tag],
+        }
+        rule test:depth {
+            $A.item: $i,
+            $i.parent+($depth): { name: top },
+            not { $i.depth: $ },
+            $next is $depth + 1,
+        } do {
+            + $i.depth: $depth,
+            emit [depth, $next],
+        }
+        rule test:pairs {
+            $A.item: $i,
+            $i.$key: $value,
+            not { $B.seen: [$key, $value] },
+        } do {
+            + $B.seen: [$key, $value],
+            emit $key,
+        }
+    ";
+
+    let mut runs = Vec::new();
+    for &matcher in MATCHERS {
+        let (system, mut space, a, b) = test_package(matcher, rules);
+        let top = space.create_object().apply(|attrs| {
+            attrs.add("name", "top");
+            attrs.object()
+        });
+        let mut parent = top;
+        for index in 0..6 {
+            let item = space.create_object().apply(|attrs| {
+                attrs.add("parent", parent);
+                if index % 2 == 0 {
+                    attrs.add("name", index);
+                }
+                let tags = vec![Value::from(index), Value::from(index + 10)];
+                attrs.add("tags", Value::Tuple(tags.into()));
+                attrs.object()
+            });
+            space.attributes_mut(a).add("item", item);
+            parent = item;
+        }
+
+        let mut fired = Vec::new();
+        let mut output = Vec::new();
+        system.run_saturation_with_control_and_output(
+            &mut space,
+            &[a, b],
+            |name, _, _| {
+                fired.push(name.to_string());
+                RuntimeControl::Continue
+            },
+            &mut output,
+        ).unwrap();
+        let seen = space.attributes(b).iter_named("seen").count();
+        let first = system.run_to_first(&mut space, &[a, b]).unwrap();
+        runs.push((fired, output, seen, first));
+    }
+
+    let (fired, output, seen, first) = &runs[0];
+    assert_eq!(fired.iter().filter(|name| *name == "label").count(), 6);
+    assert_eq!(fired.iter().filter(|name| *name == "depth").count(), 6);
+    assert!(output.iter().any(|(_, value)| value.to_string() == "[depth, 7]"));
+    assert!(output.iter().any(|(_, value)| value.to_string() == "[label, anonymous, 11]"));
+    assert_eq!(*seen, 25);
+    assert!(first.is_none());
+    assert_eq!(runs[0], runs[1]);
+    assert_eq!(runs[0], runs[2]);
+}
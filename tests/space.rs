@@ -70,6 +70,98 @@ fn object_cloning() {
     assert!(space.attributes(src).has("b", &42));
 }
 
+#[test]
+fn default_object_ids() {
+
+    // only implements the required methods
+    #[derive(Debug)]
+    struct Wrapped(Space);
+
+    impl Access for Wrapped {
+
+        fn create_id(&self) -> Id {
+            self.0.create_id()
+        }
+
+        fn clone_object(&mut self, object: Id) -> Id {
+            self.0.clone_object(object)
+        }
+
+        fn register_root(&mut self, object: Id) -> bool {
+            self.0.register_root(object)
+        }
+
+        fn unregister_root(&mut self, object: Id) -> bool {
+            self.0.unregister_root(object)
+        }
+
+        fn roots(&self) -> &[Id] {
+            self.0.roots()
+        }
+
+        fn attributes(&self, object: Id) -> Attributes<'_> {
+            self.0.attributes(object)
+        }
+
+        fn attributes_mut(&mut self, object: Id) -> AttributesMut<'_> {
+            self.0.attributes_mut(object)
+        }
+
+        fn transaction(
+            &mut self,
+            body: &mut dyn for<'tx> FnMut(Transaction<'tx>) -> Option<Transaction<'tx>>,
+        ) -> bool {
+            self.0.transaction(body)
+        }
+    }
+
+    let mut space = Wrapped(Space::new());
+    let root = space.create_root_id();
+    let child = space.create_id();
+    let nested = space.create_id();
+    let unreachable = space.create_id();
+    space.attributes_mut(root).add("child", child);
+    let pair = Value::Tuple(vec![Value::from(nested), Value::from(3)].into());
+    space.attributes_mut(child).add("pair", pair);
+    space.attributes_mut(unreachable).add("child", child);
+
+    let mut expected = vec![root, child, nested];
+    expected.sort();
+    assert_eq!(space.object_ids(), expected);
+}
+
+#[test]
+fn statistics() {
+    let mut space = Space::new();
+
+    let root = space.create_root_id();
+    space.attributes_mut(root).add("kind", "list");
+    for index in 0..10 {
+        let item = space.create_object().apply(|attrs| {
+            attrs.add("value", index);
+            attrs.add("value", index * 2);
+            attrs.object()
+        });
+        space.attributes_mut(root).add("item", item);
+    }
+    let empty = space.create_id();
+    space.attributes_mut(empty).add("gone", 1);
+    space.attributes_mut(empty).remove_all_named("gone");
+
+    let statistics = space.statistics();
+    assert_eq!(statistics.objects(), 11);
+    assert_eq!(statistics.attribute("item"), AttributeStatistics { objects: 1, values: 10 });
+    assert_eq!(statistics.attribute("value"), AttributeStatistics { objects: 10, values: 20 });
+    assert_eq!(statistics.attribute("gone"), AttributeStatistics::default());
+    assert_eq!(statistics.fan_out("kind"), 1.0);
+    assert_eq!(statistics.fan_out("item"), 10.0);
+    assert_eq!(statistics.fan_out("value"), 2.0);
+    assert!(statistics.fan_out("gone") > 0.0);
+    assert_eq!(statistics.mean_fan_out(), 31.0 / 12.0);
+    assert_eq!(statistics.attributes_per_object(), 12.0 / 11.0);
+    assert_eq!(statistics.values_per_object(), 31.0 / 11.0);
+}
+
 mod attributes {
     use super::*;
 